@@ -0,0 +1,312 @@
+//! Links between spans of different tapes that have the same value of a
+//! field, e.g., the request span of a client and the span of the server that
+//! handled it.
+
+use ahash::HashMap;
+use petgraph::graph::NodeIndex;
+use tracing_tape_parser::Value;
+
+use crate::state::LoadedTapes;
+
+/// The field that links spans unless configured otherwise. Keep it in sync
+/// with `tracing_tape_recorder::correlation::DEFAULT_FIELD`, trace-deck does
+/// not depend on the recorder.
+pub const DEFAULT_FIELD: &str = "trace_id";
+
+/// A span identified by the index of its tape in [LoadedTapes].
+pub type TapeSpan = (usize, NodeIndex<usize>);
+
+/// Groups of spans with the same value of the field in at least two tapes.
+#[derive(Debug, Default)]
+pub struct Correlations {
+    pub field: String,
+    groups: Vec<Vec<TapeSpan>>,
+    group_of: HashMap<TapeSpan, usize>,
+}
+
+impl Correlations {
+    pub fn new(tapes: &LoadedTapes, field: impl Into<String>) -> Self {
+        let field = field.into();
+        let mut spans_by_value = HashMap::<String, Vec<TapeSpan>>::default();
+        for (tape_index, loaded_tape) in tapes.iter().enumerate() {
+            let tape = &loaded_tape.tape;
+            let field_indices = tape
+                .callsites()
+                .iter()
+                .map(|callsite| callsite.fields().iter().position(|name| **name == *field))
+                .collect::<Vec<_>>();
+            if field_indices.iter().all(Option::is_none) {
+                continue;
+            }
+
+            for span_index in tape.spans().node_indices() {
+                let span = &tape.spans()[span_index];
+                let Some(field_index) = field_indices[span.callsite_index] else {
+                    continue;
+                };
+                match span.values.get(field_index) {
+                    None | Some(Value::Empty) => {}
+                    Some(value) => spans_by_value
+                        .entry(value.to_string())
+                        .or_default()
+                        .push((tape_index, span_index)),
+                }
+            }
+        }
+
+        let mut correlations = Self {
+            field,
+            ..Default::default()
+        };
+        for spans in spans_by_value.into_values() {
+            let first_tape = spans[0].0;
+            if spans
+                .iter()
+                .all(|(tape_index, _)| *tape_index == first_tape)
+            {
+                continue;
+            }
+            let group = correlations.groups.len();
+            correlations
+                .group_of
+                .extend(spans.iter().map(|span| (*span, group)));
+            correlations.groups.push(spans);
+        }
+        correlations
+    }
+
+    /// Number of groups of linked spans.
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn group(&self, span: TapeSpan) -> Option<usize> {
+        self.group_of.get(&span).copied()
+    }
+
+    /// The spans of other tapes that are linked to the span.
+    pub fn linked(&self, span: TapeSpan) -> impl Iterator<Item = TapeSpan> + '_ {
+        self.group(span)
+            .into_iter()
+            .flat_map(|group| self.groups[group].iter())
+            .filter(move |(tape_index, _)| *tape_index != span.0)
+            .copied()
+    }
+
+    /// Estimates the time offsets that align the clocks of the tapes.
+    ///
+    /// A request span and the span that handled it are assumed to be centered
+    /// on each other, as in NTP. The difference of their centers is the clock
+    /// offset, which is off by at most half the difference of their durations,
+    /// i.e., the round trip. Per pair of tapes the linked spans with the
+    /// shortest round trip are used. Tapes without linked spans and the first
+    /// tape of each set of linked tapes keep their offset.
+    pub fn estimate_time_offsets(&self, tapes: &LoadedTapes) -> Vec<time::Duration> {
+        let center = |(tape_index, span_index): TapeSpan| {
+            let tape = &tapes[tape_index];
+            let span = &tape.tape.spans()[span_index];
+            let opened = tape.timestamp_date_time(span.opened);
+            (
+                opened + (tape.timestamp_date_time(span.closed) - opened) / 2,
+                span.closed - span.opened,
+            )
+        };
+
+        // The round trip and the offset of the second tape relative to the
+        // first one.
+        let mut samples = HashMap::<(usize, usize), (i64, time::Duration)>::default();
+        for group in &self.groups {
+            for (i, a) in group.iter().enumerate() {
+                for b in &group[i + 1..] {
+                    let (a, b) = if a.0 < b.0 { (*a, *b) } else { (*b, *a) };
+                    if a.0 == b.0 {
+                        continue;
+                    }
+                    let (center_a, duration_a) = center(a);
+                    let (center_b, duration_b) = center(b);
+                    let sample = ((duration_a - duration_b).abs(), center_b - center_a);
+                    samples
+                        .entry((a.0, b.0))
+                        .and_modify(|best| {
+                            if sample.0 < best.0 {
+                                *best = sample;
+                            }
+                        })
+                        .or_insert(sample);
+                }
+            }
+        }
+
+        let mut time_offsets = tapes
+            .iter()
+            .map(|tape| tape.time_offset)
+            .collect::<Vec<_>>();
+        let mut aligned = vec![false; tapes.len()];
+        for root in 0..tapes.len() {
+            if aligned[root] {
+                continue;
+            }
+            aligned[root] = true;
+            let mut queue = std::collections::VecDeque::from([root]);
+            while let Some(a) = queue.pop_front() {
+                let correction = time_offsets[a] - tapes[a].time_offset;
+                for b in 0..tapes.len() {
+                    if aligned[b] {
+                        continue;
+                    }
+                    let offset = match (samples.get(&(a, b)), samples.get(&(b, a))) {
+                        (Some((_, offset)), _) => *offset,
+                        (None, Some((_, offset))) => -*offset,
+                        (None, None) => continue,
+                    };
+                    time_offsets[b] = tapes[b].time_offset + correction - offset;
+                    aligned[b] = true;
+                    queue.push_back(b);
+                }
+            }
+        }
+        time_offsets
+    }
+}
+
+/// Where a linked span was drawn in a timeline during this frame.
+pub struct LinkAnchor {
+    pub group: usize,
+    pub tape_index: usize,
+    /// The visible part of the span on the screen.
+    pub rect: egui::Rect,
+}
+
+/// Draws arrows from the linked span that starts first to the spans of the
+/// other tapes, across all timeline tabs.
+pub fn paint_links(ctx: &egui::Context, anchors: &[LinkAnchor], selected_group: Option<usize>) {
+    let mut groups = HashMap::<usize, Vec<&LinkAnchor>>::default();
+    for anchor in anchors {
+        groups.entry(anchor.group).or_default().push(anchor);
+    }
+
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("span_links"),
+    ));
+    let color = ctx.style().visuals.text_color();
+    for (group, mut anchors) in groups {
+        anchors.sort_by(|a, b| a.rect.left().total_cmp(&b.rect.left()));
+        let stroke = if selected_group == Some(group) {
+            egui::Stroke::new(2.0, ctx.style().visuals.selection.stroke.color)
+        } else {
+            egui::Stroke::new(1.0, color.gamma_multiply(0.5))
+        };
+        let origin = anchors[0];
+        for anchor in &anchors[1..] {
+            if anchor.tape_index == origin.tape_index {
+                continue;
+            }
+            let from = origin.rect.center();
+            let to = if anchor.rect.top() > from.y {
+                anchor.rect.center_top()
+            } else {
+                anchor.rect.center_bottom()
+            };
+            painter.arrow(from, to - from, stroke);
+        }
+    }
+}
+
+#[test]
+fn test_correlations() {
+    use tracing_tape_parser::TapeBuilder;
+
+    use crate::testing::{callsite, span, state};
+
+    let mut client = TapeBuilder::new(16, 0);
+    let request = client.callsite(callsite("request", &["trace_id"]));
+    let requests = [1, 2, 3, 5, 5]
+        .map(|trace_id| client.span(None, span(request, 0..10, &[Value::U64(trace_id)])));
+    client.span(None, span(request, 0..10, &[Value::Empty]));
+
+    let mut server = TapeBuilder::new(16, 0);
+    let other = server.callsite(callsite("other", &["n"]));
+    let handle = server.callsite(callsite("handle", &["n", "trace_id"]));
+    let handled = [1, 2, 4].map(|trace_id| {
+        server.span(
+            None,
+            span(handle, 0..10, &[Value::I64(7), Value::U64(trace_id)]),
+        )
+    });
+    server.span(None, span(other, 0..10, &[Value::I64(3)]));
+
+    let state = state(vec![
+        ("client.tape", client.finish()),
+        ("server.tape", server.finish()),
+    ]);
+    let correlations = Correlations::new(&state.loaded_tapes, DEFAULT_FIELD);
+    assert_eq!(correlations.len(), 2);
+    assert_eq!(
+        correlations.linked((0, requests[0])).collect::<Vec<_>>(),
+        vec![(1, handled[0])]
+    );
+    assert_eq!(
+        correlations.linked((1, handled[1])).collect::<Vec<_>>(),
+        vec![(0, requests[1])]
+    );
+    // Values that only occur in one tape are not linked.
+    assert_eq!(correlations.group((0, requests[2])), None);
+    assert_eq!(correlations.group((0, requests[3])), None);
+    assert_eq!(correlations.group((1, handled[2])), None);
+
+    // Only the server has the field.
+    let correlations = Correlations::new(&state.loaded_tapes, "n");
+    assert_eq!(correlations.len(), 0);
+}
+
+#[test]
+fn test_estimate_time_offsets() {
+    use time::Duration;
+    use tracing_tape_parser::TapeBuilder;
+
+    use crate::testing::{callsite, span, state};
+
+    // The clock of the server is 5µs ahead of the client, the one of the
+    // backend is 300ns ahead, i.e., 4.7µs behind the server.
+    let mut client = TapeBuilder::new(16, 0);
+    let request = client.callsite(callsite("request", &["trace_id"]));
+    client.span(None, span(request, 100..300, &[Value::U64(1)]));
+    client.span(None, span(request, 1000..1100, &[Value::U64(2)]));
+
+    // The first request is not handled in its center, but has the longer
+    // round trip.
+    let mut server = TapeBuilder::new(16, 0);
+    let handle = server.callsite(callsite("handle", &["trace_id"]));
+    server.span(None, span(handle, 5200..5280, &[Value::U64(1)]));
+    server.span(None, span(handle, 6030..6070, &[Value::U64(2)]));
+    server.span(None, span(handle, 7000..7100, &[Value::U64(3)]));
+
+    let mut backend = TapeBuilder::new(16, 0);
+    let query = backend.callsite(callsite("query", &["trace_id"]));
+    backend.span(None, span(query, 2340..2360, &[Value::U64(3)]));
+
+    let unrelated = TapeBuilder::new(16, 0);
+
+    let mut state = state(vec![
+        ("client.tape", client.finish()),
+        ("server.tape", server.finish()),
+        ("backend.tape", backend.finish()),
+        ("unrelated.tape", unrelated.finish()),
+    ]);
+    // Offsets that were set before are replaced, except for the first tape.
+    state.loaded_tapes[0].time_offset = Duration::nanoseconds(10);
+    state.loaded_tapes[1].time_offset = Duration::nanoseconds(1000);
+    state.loaded_tapes[3].time_offset = Duration::nanoseconds(-20);
+
+    let correlations = Correlations::new(&state.loaded_tapes, DEFAULT_FIELD);
+    assert_eq!(
+        correlations.estimate_time_offsets(&state.loaded_tapes),
+        vec![
+            Duration::nanoseconds(10),
+            Duration::nanoseconds(10 - 5000),
+            Duration::nanoseconds(10 - 300),
+            Duration::nanoseconds(-20),
+        ]
+    );
+}
@@ -0,0 +1,362 @@
+//! Aggregation of the span trees of a tape into call paths, e.g., for
+//! flamegraphs.
+
+use std::ops::Range;
+
+use ahash::HashMap;
+use crossbeam_channel::Receiver;
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+use tracing_tape_parser::Tape;
+
+use crate::{
+    state::{Callsites, LoadedTape},
+    utils,
+};
+
+/// The time that is attributed to a span.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Weight {
+    /// The time between opening and closing the span.
+    #[default]
+    Wall,
+    /// The time the span has been entered.
+    Busy,
+    /// The time the span has been entered while none of its children were
+    /// entered on the same thread. Call paths are inverted, i.e., they start
+    /// at the span the time was spent in and continue with its callers.
+    SelfTime,
+}
+
+impl Weight {
+    pub const ALL: [Weight; 3] = [Weight::Wall, Weight::Busy, Weight::SelfTime];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Weight::Wall => "Wall",
+            Weight::Busy => "Busy",
+            Weight::SelfTime => "Self",
+        }
+    }
+
+    /// The weight of the span within the timestamp range.
+    pub fn of(self, tape: &Tape, index: NodeIndex<usize>, range: &Range<i64>) -> i64 {
+        let spans = tape.spans();
+        let span = &spans[index];
+        match self {
+            Weight::Wall => overlap(span.opened..span.closed, range),
+            Weight::Busy => span
+                .entrances
+                .iter()
+                .map(|entrance| overlap(entrance.entered..entrance.exited, range))
+                .sum(),
+            Weight::SelfTime => {
+                let children = spans
+                    .neighbors(index)
+                    .flat_map(|child| spans[child].entrances.iter())
+                    .collect::<Vec<_>>();
+                span.entrances
+                    .iter()
+                    .map(|entrance| {
+                        let entered =
+                            entrance.entered.max(range.start)..entrance.exited.min(range.end);
+                        let nested = children
+                            .iter()
+                            .filter(|child| child.thread_id == entrance.thread_id)
+                            .map(|child| overlap(child.entered..child.exited, &entered))
+                            .sum::<i64>();
+                        (entered.end - entered.start - nested).max(0)
+                    })
+                    .sum()
+            }
+        }
+    }
+}
+
+/// The global indices of the callsites of the tape by their index in the
+/// tape.
+pub fn global_callsites(loaded_tape: &LoadedTape, callsites: &Callsites) -> Vec<usize> {
+    (0..loaded_tape.tape.callsites().len())
+        .map(|index| {
+            callsites
+                .tape_to_global(&loaded_tape.path, index)
+                .expect("callsite")
+        })
+        .collect()
+}
+
+fn overlap(a: Range<i64>, b: &Range<i64>) -> i64 {
+    (a.end.min(b.end) - a.start.max(b.start)).max(0)
+}
+
+pub struct Frame {
+    /// Global index of the callsite, `None` for the root frame.
+    pub callsite_index: Option<usize>,
+    pub parent: Option<usize>,
+    /// Children ordered by their callsite, i.e., by target and location.
+    pub children: Vec<usize>,
+    /// Number of spans that contributed to the frame.
+    pub count: usize,
+    /// Sum of the weights of the spans.
+    pub total: i64,
+    /// Sum of the self times of the spans that ended the call path, i.e., not
+    /// of the callers of an inverted path.
+    pub self_time: i64,
+    /// The span with the largest weight that ended the call path, i.e., not
+    /// one of the callers of an inverted path, and its weight.
+    pub longest: Option<(NodeIndex<usize>, i64)>,
+    /// The total or the sum of the widths of the children if that is larger,
+    /// e.g., because children ran in parallel on other threads.
+    pub width: i64,
+}
+
+impl Frame {
+    pub fn mean(&self) -> i64 {
+        self.total / self.count.max(1) as i64
+    }
+}
+
+/// The spans of a tape within a timestamp range merged by their call path,
+/// i.e., the callsites of their ancestors and their own callsite.
+pub struct CallTree {
+    pub weight: Weight,
+    pub range: Range<i64>,
+    pub frames: Vec<Frame>,
+    /// Maximum number of frames below the root.
+    pub depth: usize,
+    /// Sum of the weights of the spans of each callsite. Nested calls of a
+    /// recursive callsite are part of the wall and busy time of the outermost
+    /// one and only counted for self time, which does not overlap.
+    pub totals: HashMap<usize, i64>,
+    children: HashMap<(usize, usize), usize>,
+}
+
+impl CallTree {
+    pub const ROOT: usize = 0;
+
+    /// Builds the tree on the rayon thread pool, see [utils::spawn].
+    pub fn spawn(
+        ctx: &egui::Context,
+        loaded_tape: &LoadedTape,
+        callsites: &Callsites,
+        range: Range<i64>,
+        weight: Weight,
+    ) -> Receiver<Self> {
+        let tape = loaded_tape.tape.clone();
+        let global_callsites = global_callsites(loaded_tape, callsites);
+        utils::spawn(ctx, move || {
+            Self::build(&tape, &global_callsites, range, weight)
+        })
+    }
+
+    /// Builds the tree from the global indices of the callsites of the tape,
+    /// which does not need the state.
+    pub fn build(
+        tape: &Tape,
+        global_callsites: &[usize],
+        range: Range<i64>,
+        weight: Weight,
+    ) -> Self {
+        let mut tree = Self {
+            weight,
+            range: range.clone(),
+            frames: vec![Frame {
+                callsite_index: None,
+                parent: None,
+                children: Vec::new(),
+                count: 0,
+                total: 0,
+                self_time: 0,
+                longest: None,
+                width: 0,
+            }],
+            depth: 0,
+            totals: HashMap::default(),
+            children: HashMap::default(),
+        };
+
+        let mut path = Vec::new();
+        let spans = tape.query_spans().time_range(range.start..=range.end);
+        for (index, _) in spans.iter() {
+            let value = weight.of(tape, index, &range);
+            if value == 0 {
+                continue;
+            }
+
+            path.clear();
+            path.extend(
+                std::iter::once(index)
+                    .chain(tape.ancestors(index))
+                    .map(|span| global_callsites[tape.spans()[span].callsite_index]),
+            );
+            tree.depth = tree.depth.max(path.len());
+            if weight == Weight::SelfTime || !path[1..].contains(&path[0]) {
+                *tree.totals.entry(path[0]).or_default() += value;
+            }
+
+            let own_frame = if weight == Weight::SelfTime {
+                let mut frame = Self::ROOT;
+                for callsite_index in &path {
+                    frame = tree.child(frame, *callsite_index);
+                    tree.frames[frame].count += 1;
+                    tree.frames[frame].total += value;
+                }
+                tree.child(Self::ROOT, path[0])
+            } else {
+                let frame = path.iter().rev().fold(Self::ROOT, |frame, callsite_index| {
+                    tree.child(frame, *callsite_index)
+                });
+                tree.frames[frame].count += 1;
+                tree.frames[frame].total += value;
+                frame
+            };
+            tree.frames[own_frame].self_time += if weight == Weight::SelfTime {
+                value
+            } else {
+                Weight::SelfTime.of(tape, index, &range)
+            };
+            let longest = &mut tree.frames[own_frame].longest;
+            if longest.is_none_or(|(_, longest)| value > longest) {
+                *longest = Some((index, value));
+            }
+        }
+
+        // Children are always created after their parent.
+        for index in (0..tree.frames.len()).rev() {
+            let frame = &mut tree.frames[index];
+            frame.width = frame.width.max(frame.total);
+            if let Some(parent) = frame.parent {
+                let width = frame.width;
+                tree.frames[parent].width += width;
+            }
+        }
+        for index in 0..tree.frames.len() {
+            let mut children = std::mem::take(&mut tree.frames[index].children);
+            children.sort_by_key(|child| tree.frames[*child].callsite_index);
+            tree.frames[index].children = children;
+        }
+
+        tree
+    }
+
+    /// Returns the child of the frame for the callsite, creating it if needed.
+    fn child(&mut self, parent: usize, callsite_index: usize) -> usize {
+        *self
+            .children
+            .entry((parent, callsite_index))
+            .or_insert_with(|| {
+                let index = self.frames.len();
+                self.frames.push(Frame {
+                    callsite_index: Some(callsite_index),
+                    parent: Some(parent),
+                    children: Vec::new(),
+                    count: 0,
+                    total: 0,
+                    self_time: 0,
+                    longest: None,
+                    width: 0,
+                });
+                self.frames[parent].children.push(index);
+                index
+            })
+    }
+
+    /// The callsites of the frame and its ancestors, starting at the root.
+    pub fn path(&self, frame: usize) -> Vec<usize> {
+        let mut path = std::iter::successors(Some(frame), |frame| self.frames[*frame].parent)
+            .filter_map(|frame| self.frames[frame].callsite_index)
+            .collect::<Vec<_>>();
+        path.reverse();
+        path
+    }
+
+    /// Looks up the frame of a call path.
+    pub fn find(&self, path: &[usize]) -> Option<usize> {
+        path.iter().try_fold(Self::ROOT, |frame, callsite_index| {
+            self.children.get(&(frame, *callsite_index)).copied()
+        })
+    }
+}
+
+#[test]
+fn test_call_tree() {
+    use tracing_tape_parser::TapeBuilder;
+
+    use crate::testing::{callsite, span, state};
+
+    let mut builder = TapeBuilder::new(16, 0);
+    let main = builder.callsite(callsite("main", &[]));
+    let work = builder.callsite(callsite("work", &[]));
+    let root = builder.span(None, span(main, 0..100, &[]));
+    builder.span(Some(root), span(work, 10..40, &[]));
+    let longest = builder.span(Some(root), span(work, 50..90, &[]));
+    let state = state(vec![("test.tape", builder.finish())]);
+    let loaded_tape = &state.loaded_tapes[0];
+    let global = |index| state.callsites.tape_to_global(&loaded_tape.path, index);
+    let global_callsites = global_callsites(loaded_tape, &state.callsites);
+    let (main, work) = (global(main).unwrap(), global(work).unwrap());
+
+    let tree = CallTree::build(&loaded_tape.tape, &global_callsites, 0..100, Weight::Wall);
+    let frame = &tree.frames[tree.find(&[main]).unwrap()];
+    assert_eq!((frame.count, frame.total), (1, 100));
+    let frame = &tree.frames[tree.find(&[main, work]).unwrap()];
+    assert_eq!((frame.count, frame.total), (2, 70));
+    assert_eq!(frame.longest, Some((longest, 40)));
+    assert_eq!(frame.self_time, 70);
+    assert_eq!(tree.frames[tree.find(&[main]).unwrap()].self_time, 30);
+    assert_eq!(tree.depth, 2);
+    assert!(tree.find(&[work]).is_none());
+
+    // Spans are clipped to the range.
+    let tree = CallTree::build(&loaded_tape.tape, &global_callsites, 20..60, Weight::Wall);
+    assert_eq!(tree.frames[tree.find(&[main]).unwrap()].total, 40);
+    assert_eq!(tree.frames[tree.find(&[main, work]).unwrap()].total, 30);
+
+    // Inverted paths start at the span the time was spent in.
+    let tree = CallTree::build(
+        &loaded_tape.tape,
+        &global_callsites,
+        0..100,
+        Weight::SelfTime,
+    );
+    assert_eq!(tree.frames[tree.find(&[main]).unwrap()].total, 30);
+    assert_eq!(tree.frames[tree.find(&[work]).unwrap()].total, 70);
+    assert_eq!(tree.frames[tree.find(&[work, main]).unwrap()].total, 70);
+    assert!(tree.find(&[main, work]).is_none());
+    assert_eq!(tree.frames[CallTree::ROOT].width, 100);
+}
+
+#[test]
+fn test_call_tree_recursive_totals() {
+    use tracing_tape_parser::TapeBuilder;
+
+    use crate::testing::{callsite, span, state};
+
+    let mut builder = TapeBuilder::new(16, 0);
+    let main = builder.callsite(callsite("main", &[]));
+    let fib = builder.callsite(callsite("fib", &[]));
+    let root = builder.span(None, span(main, 0..100, &[]));
+    let outer = builder.span(Some(root), span(fib, 10..90, &[]));
+    builder.span(Some(outer), span(fib, 20..50, &[]));
+    let state = state(vec![("test.tape", builder.finish())]);
+    let loaded_tape = &state.loaded_tapes[0];
+    let global = |index| state.callsites.tape_to_global(&loaded_tape.path, index);
+    let global_callsites = global_callsites(loaded_tape, &state.callsites);
+    let (main, fib) = (global(main).unwrap(), global(fib).unwrap());
+
+    for weight in [Weight::Wall, Weight::Busy] {
+        let tree = CallTree::build(&loaded_tape.tape, &global_callsites, 0..100, weight);
+        assert_eq!(tree.totals[&main], 100);
+        assert_eq!(tree.totals[&fib], 80);
+    }
+
+    // The callers on the inverted paths of fib are not charged for it.
+    let tree = CallTree::build(
+        &loaded_tape.tape,
+        &global_callsites,
+        0..100,
+        Weight::SelfTime,
+    );
+    assert_eq!(tree.totals[&main], 20);
+    assert_eq!(tree.totals[&fib], 80);
+}
@@ -5,18 +5,25 @@ use std::{path::PathBuf, sync::Arc};
 use clap::Parser;
 use eframe::egui;
 use egui_dock::{DockArea, DockState, Style};
+use session::{Open, Session};
 use state::{LoadedTape, LoadedTapes, State};
 use tabs::{Tab, TabViewer};
 use tracing_tape_parser::Tape;
 
+pub(crate) mod correlation;
+pub(crate) mod flamegraph;
+pub(crate) mod session;
 mod state;
 pub(crate) mod statistics;
 mod tabs;
+#[cfg(test)]
+mod testing;
 pub(crate) mod timeline;
 pub(crate) mod utils;
 
 #[derive(Debug, Default, Parser)]
 struct Args {
+    /// Tapes, traces in the Trace Event Format, or a session (.deck).
     tape_files: Vec<String>,
 
     #[clap(short, long)]
@@ -60,15 +67,27 @@ fn main() {
     });
 }
 
+/// Keys of the values that are persisted between runs on native builds.
+#[cfg(not(target_arch = "wasm32"))]
+const LAST_SESSION_KEY: &str = "last_session";
+#[cfg(not(target_arch = "wasm32"))]
+const RECENT_FILES_KEY: &str = "recent_files";
+
 struct TraceDeck {
     dock_state: DockState<Tab>,
     state: State,
+    /// Recently opened tapes and sessions, the most recent first.
+    recent_files: Vec<PathBuf>,
+    /// The session that was saved when trace-deck was closed the last time.
+    last_session: Option<Session>,
+    /// Where the session is saved, edited in the file menu.
+    session_path: String,
+    error: Option<String>,
 }
 
 impl TraceDeck {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
-        // Restore app state using cc.storage (requires the "persistence" feature).
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that
         // you can use for e.g. egui::PaintCallback.
 
@@ -81,20 +100,185 @@ impl TraceDeck {
                 .unwrap();
         }
 
-        let (dock_state, tapes) = if args.tape_files.is_empty() {
-            (DockState::new(vec![Tab::welcome()]), LoadedTapes::default())
-        } else {
-            Self::load_files(args.tape_files.iter().map(|path| {
-                let path = path.into();
-                let file = std::fs::read(&path).unwrap();
-                (path, file.into())
-            }))
-            .unwrap()
+        let mut trace_deck = Self {
+            dock_state: DockState::new(vec![Tab::welcome()]),
+            state: LoadedTapes::default().into(),
+            recent_files: Vec::new(),
+            last_session: None,
+            session_path: String::new(),
+            error: None,
         };
 
-        Self {
-            dock_state,
-            state: tapes.into(),
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(storage) = cc.storage {
+            trace_deck.recent_files =
+                eframe::get_value(storage, RECENT_FILES_KEY).unwrap_or_default();
+            trace_deck.last_session = eframe::get_value(storage, LAST_SESSION_KEY);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = cc;
+
+        if !args.tape_files.is_empty() {
+            trace_deck.open_or_show_error(Open::Files(
+                args.tape_files.iter().map(PathBuf::from).collect(),
+            ));
+        }
+
+        trace_deck
+    }
+
+    /// Reads and opens tapes or a session from the file system.
+    fn open_paths(&mut self, paths: Vec<PathBuf>) -> std::io::Result<()> {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                // Sessions refer to the tapes by their path, which must not
+                // depend on the working directory.
+                let path = std::path::absolute(&path).unwrap_or(path);
+                let file = std::fs::read(&path)?;
+                Ok((path, file.into()))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        self.open(files)
+    }
+
+    /// Opens tapes or a session, replacing the current ones.
+    fn open(&mut self, files: Vec<(PathBuf, Arc<[u8]>)>) -> std::io::Result<()> {
+        let is_session = |path: &PathBuf| {
+            path.extension()
+                .is_some_and(|extension| extension == session::EXTENSION)
+        };
+
+        if let Some((path, file)) = files.iter().find(|(path, _)| is_session(path)) {
+            self.open_session(Session::from_slice(file)?)?;
+            self.session_path = path.to_string_lossy().into_owned();
+            session::add_recent_file(&mut self.recent_files, path.clone());
+            return Ok(());
+        }
+
+        let paths = files
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        let (dock_state, tapes) = Self::load_files(files.into_iter())?;
+        self.dock_state = dock_state;
+        self.state = tapes.into();
+        self.session_path.clear();
+        for path in paths.into_iter().rev() {
+            session::add_recent_file(&mut self.recent_files, path);
+        }
+        Ok(())
+    }
+
+    fn open_session(&mut self, session: Session) -> std::io::Result<()> {
+        let files = session
+            .tapes
+            .iter()
+            .map(|tape| Ok((tape.path.clone(), std::fs::read(&tape.path)?.into())))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let (_, mut tapes) = Self::load_files(files.into_iter())?;
+        for tape in tapes.iter_mut() {
+            let session_tape = session.tapes.iter().find(|t| t.path == tape.path);
+            if let Some(session_tape) = session_tape {
+                tape.time_offset = session_tape.time_offset;
+            }
+        }
+
+        let mut state: State = tapes.into();
+        self.dock_state = session.restore(&mut state);
+        self.state = state;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    /// Creates a session of the current state. The dock layout is moved into
+    /// the session, see [Self::end_session].
+    fn begin_session(&mut self) -> Session {
+        let dock_state = std::mem::replace(&mut self.dock_state, DockState::new(vec![]));
+        Session::new(&self.state, dock_state)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn end_session(&mut self, session: Session) {
+        self.dock_state = session.dock_state;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_session(&mut self) -> std::io::Result<()> {
+        let path = PathBuf::from(&self.session_path);
+        let session = self.begin_session();
+        let result = session.write(&path);
+        self.end_session(session);
+        result?;
+        session::add_recent_file(&mut self.recent_files, path);
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    /// The default path of the session is next to the first tape.
+    fn default_session_path(&self) -> String {
+        self.state
+            .loaded_tapes
+            .first()
+            .map(|tape| {
+                tape.path
+                    .with_extension(session::EXTENSION)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .unwrap_or_default()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn menu_ui(&mut self, ui: &mut egui::Ui) {
+        egui::MenuBar::new().ui(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                ui.add_enabled_ui(!self.state.loaded_tapes.is_empty(), |ui| {
+                    if self.session_path.is_empty() {
+                        self.session_path = self.default_session_path();
+                    }
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.session_path).desired_width(300.0),
+                        );
+                        if ui.button("Save Session").clicked() {
+                            if let Err(err) = self.save_session() {
+                                self.error = Some(format!("Failed to save the session: {err}"));
+                            }
+                            ui.close();
+                        }
+                    });
+                });
+
+                ui.menu_button("Open Recent", |ui| {
+                    if self.recent_files.is_empty() {
+                        ui.label("No recent files");
+                    }
+                    let mut open = None;
+                    for path in &self.recent_files {
+                        if ui.button(path.to_string_lossy()).clicked() {
+                            open = Some(path.clone());
+                            ui.close();
+                        }
+                    }
+                    if let Some(path) = open {
+                        self.open_or_show_error(Open::Files(vec![path]));
+                    }
+                });
+            });
+        });
+    }
+
+    fn open_or_show_error(&mut self, open: Open) {
+        let result = match open {
+            Open::Files(paths) => self.open_paths(paths),
+            Open::LastSession => match self.last_session.take() {
+                Some(session) => self.open_session(session),
+                None => Ok(()),
+            },
+        };
+        if let Err(err) = result {
+            self.error = Some(format!("Failed to open: {err}"));
         }
     }
 
@@ -106,23 +290,45 @@ impl TraceDeck {
         let mut tapes: Vec<LoadedTape> = Vec::with_capacity(files.size_hint().1.unwrap_or(0));
 
         for (path, file) in files {
-            let path = path.into();
-
             // De-duplicate files.
             // Linear search is fine, we should only ever have a few files.
             if tapes.iter().find(|t| &t.path == &path).is_some() {
                 continue;
             }
 
-            let tape = Tape::parse(&file);
+            // Traces in the Trace Event Format can be viewed alongside tapes.
+            let tape = if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                tracing_tape_parser::import::chrome::read(&file[..])?
+            } else {
+                Tape::parse(&file)?
+            };
 
             tapes.push(LoadedTape {
                 path,
-                tape,
+                tape: Arc::new(tape),
                 time_offset: time::Duration::ZERO,
             });
         }
 
+        // Traces without an absolute time have a time base of zero. They start
+        // together with the first tape that has one, the offset can be
+        // adjusted by the user afterwards.
+        let start_time = tapes
+            .iter()
+            .find(|tape| tape.tape.timestamp_base() != 0)
+            .map(|tape| *tape.tape.time_range().start());
+        if let Some(start_time) = start_time {
+            let start_time = time::OffsetDateTime::from_unix_timestamp_nanos(start_time).unwrap();
+            for tape in tapes.iter_mut() {
+                if tape.tape.timestamp_base() == 0 {
+                    tape.time_offset = start_time - time::OffsetDateTime::UNIX_EPOCH;
+                }
+            }
+        }
+
         let mut paths = tapes.iter().map(|t| &t.path);
         if let Some(path) = paths.next() {
             let main_surface = dock_state.main_surface_mut();
@@ -132,18 +338,33 @@ impl TraceDeck {
                 main_surface.split_above(root_index, 0.9, vec![Tab::timeline(path)]);
 
             let [timeline_node, callsites] =
-                main_surface.split_left(first_timeline, 0.1, vec![Tab::callsites()]);
+                main_surface.split_left(first_timeline, 0.1, vec![Tab::callsites(), Tab::search()]);
 
             let [_callsites, _details] =
                 main_surface.split_below(callsites, 0.5, vec![Tab::details()]);
 
-            let [timeline_node, event_node] =
-                main_surface.split_right(timeline_node, 0.5, vec![Tab::events(path)]);
+            let [timeline_node, event_node] = main_surface.split_right(
+                timeline_node,
+                0.5,
+                vec![
+                    Tab::events(path),
+                    Tab::flamegraph(path),
+                    Tab::statistics(path),
+                ],
+            );
 
             for (index, path) in paths.enumerate() {
                 let fraction = (index as f32 + 1.0) / (index as f32 + 2.0);
                 main_surface.split_below(timeline_node, fraction, vec![Tab::timeline(path)]);
-                main_surface.split_right(event_node, fraction, vec![Tab::events(path)]);
+                main_surface.split_right(
+                    event_node,
+                    fraction,
+                    vec![
+                        Tab::events(path),
+                        Tab::flamegraph(path),
+                        Tab::statistics(path),
+                    ],
+                );
             }
         }
 
@@ -153,53 +374,121 @@ impl TraceDeck {
 
 impl eframe::App for TraceDeck {
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let mut global_time_span: Option<std::ops::Range<time::OffsetDateTime>> = None;
-            for tape in &*self.state.loaded_tapes {
-                let tape_time_span = tape.adjusted_timespan();
-                if let Some(acc) = global_time_span {
-                    let min = acc.start.min(tape_time_span.start);
-                    let max = acc.end.max(tape_time_span.end);
-                    global_time_span = Some(min..max);
-                } else {
-                    global_time_span = Some(tape_time_span);
-                }
+        // Files can only be saved on native builds.
+        #[cfg(not(target_arch = "wasm32"))]
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| self.menu_ui(ui));
+
+        if let Some(error) = &self.error {
+            let mut open = true;
+            egui::Window::new("Error")
+                .open(&mut open)
+                .collapsible(false)
+                .show(ctx, |ui| ui.label(error));
+            if !open {
+                self.error = None;
             }
-            let global_time_span = global_time_span.unwrap_or_else(|| {
+        }
+
+        let mut open = None;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            // The time span is only updated when the time offsets are set,
+            // such that it stays the same while a tape is dragged.
+            let global_time_span = if self.state.loaded_tapes.is_empty() {
                 let now = time::OffsetDateTime::from_unix_timestamp(0).expect("time");
                 now..now + time::Duration::MINUTE
-            });
+            } else {
+                self.state.timeline_start_time
+                    ..self.state.timeline_start_time + self.state.timeline_duration
+            };
 
+            self.state.link_anchors.clear();
             let mut viewer = TabViewer {
                 // tapes: &self.tapes,
                 state: &mut self.state,
                 global_time_span,
                 new_tabs: vec![],
+                recent_files: &self.recent_files,
+                has_last_session: self.last_session.is_some(),
+                open: None,
             };
 
             DockArea::new(&mut self.dock_state)
                 .style(Style::from_egui(ui.style().as_ref()))
                 .show_inside(ui, &mut viewer);
 
+            let selected_group = match &viewer.state.selected_item {
+                Some(tabs::SelectedItem::Span { tape, span_index }) => viewer
+                    .state
+                    .loaded_tapes
+                    .index_of(tape)
+                    .and_then(|tape_index| {
+                        viewer.state.correlations.group((tape_index, *span_index))
+                    }),
+                _ => None,
+            };
+            correlation::paint_links(ctx, &viewer.state.link_anchors, selected_group);
+
+            open = viewer.open.take();
             if !viewer.new_tabs.is_empty() {
                 self.dock_state.add_window(viewer.new_tabs);
             }
         });
+
+        if let Some(open) = open {
+            self.open_or_show_error(open);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, RECENT_FILES_KEY, &self.recent_files);
+        if !self.state.loaded_tapes.is_empty() {
+            let session = self.begin_session();
+            eframe::set_value(storage, LAST_SESSION_KEY, &session);
+            self.end_session(session);
+        }
     }
 
     fn raw_input_hook(&mut self, _ctx: &egui::Context, raw_input: &mut egui::RawInput) {
         if !raw_input.dropped_files.is_empty() {
-            let (dock_state, tapes) = Self::load_files(raw_input.dropped_files.iter().map(|f| {
-                let path = f.path.clone().unwrap_or_else(|| (&f.name).into());
-                let bytes = f
-                    .bytes
-                    .clone()
-                    .unwrap_or_else(|| std::fs::read(&path).unwrap().into());
-                (path, bytes)
-            }))
-            .unwrap();
-            self.dock_state = dock_state;
-            self.state = tapes.into();
+            let result = raw_input
+                .dropped_files
+                .iter()
+                .map(|f| {
+                    let path = f.path.clone().unwrap_or_else(|| (&f.name).into());
+                    let bytes = match f.bytes.clone() {
+                        Some(bytes) => bytes,
+                        None => std::fs::read(&path)?.into(),
+                    };
+                    Ok((path, bytes))
+                })
+                .collect::<std::io::Result<Vec<_>>>()
+                .and_then(|files| self.open(files));
+            if let Err(err) = result {
+                self.error = Some(format!("Failed to open: {err}"));
+            }
         }
     }
 }
+
+#[test]
+fn test_load_trace_without_start_time() {
+    let mut builder = tracing_tape_parser::TapeBuilder::new(20, 1_000_000_000);
+    let callsite = builder.callsite(testing::callsite("span", &[]));
+    builder.span(None, testing::span(callsite, 0..10, &[]));
+    let mut tape = vec![];
+    builder.finish().write(&mut tape).unwrap();
+    let trace = r#"[{"name": "span", "ph": "X", "ts": 5.0, "dur": 1.0, "pid": 1, "tid": 1}]"#;
+
+    let files = vec![
+        (PathBuf::from("trace.json"), trace.as_bytes().into()),
+        (PathBuf::from("test.tape"), tape.into()),
+    ];
+    let (_, tapes) = TraceDeck::load_files(files.into_iter()).unwrap();
+    assert_eq!(tapes[0].time_offset, time::Duration::SECOND);
+    assert_eq!(tapes[1].time_offset, time::Duration::ZERO);
+    assert_eq!(
+        tapes[0].adjusted_timespan().start,
+        tapes[1].adjusted_timespan().start
+    );
+}
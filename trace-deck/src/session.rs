@@ -0,0 +1,209 @@
+//! Sessions store which tapes are loaded and how they are viewed, i.e., the
+//! time offsets, callsite colors, dock layout, visible and selected range, and
+//! the selection. They are saved as RON in `.deck` files, like the last session
+//! is persisted by eframe.
+
+use std::path::{Path, PathBuf};
+
+use egui_dock::DockState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    correlation::Correlations,
+    state::State,
+    tabs::{SelectedItem, Tab},
+    timeline::TimeRange,
+};
+
+/// The extension of session files.
+pub const EXTENSION: &str = "deck";
+
+/// Number of entries in the list of recently opened files.
+pub const MAX_RECENT_FILES: usize = 10;
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionTape {
+    pub path: PathBuf,
+    pub time_offset: time::Duration,
+}
+
+/// Callsites are identified by their index in the first tape that contains
+/// them.
+#[derive(Serialize, Deserialize)]
+pub struct CallsiteColor {
+    pub tape: PathBuf,
+    pub callsite_index: usize,
+    pub color: egui::Color32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub tapes: Vec<SessionTape>,
+    pub callsite_colors: Vec<CallsiteColor>,
+    pub correlation_field: String,
+    pub timeline_range: TimeRange,
+    pub selected_range: Option<TimeRange>,
+    pub selected_item: Option<SelectedItem>,
+    pub dock_state: DockState<Tab>,
+}
+
+impl Session {
+    pub fn new(state: &State, dock_state: DockState<Tab>) -> Self {
+        let callsite_colors = state
+            .callsites
+            .iter()
+            .filter_map(|callsite| {
+                let tape = state
+                    .loaded_tapes
+                    .iter()
+                    .find(|tape| callsite.tape_data.contains_key(&tape.path))?;
+                Some(CallsiteColor {
+                    tape: tape.path.clone(),
+                    callsite_index: callsite.tape_data[&tape.path].callsite_index,
+                    color: callsite.color,
+                })
+            })
+            .collect();
+
+        Self {
+            tapes: state
+                .loaded_tapes
+                .iter()
+                .map(|tape| SessionTape {
+                    path: tape.path.clone(),
+                    time_offset: tape.time_offset,
+                })
+                .collect(),
+            callsite_colors,
+            correlation_field: state.correlations.field.clone(),
+            timeline_range: state.timeline_range.clone(),
+            selected_range: state.selected_range.clone(),
+            selected_item: state.selected_item.clone(),
+            dock_state,
+        }
+    }
+
+    pub fn from_slice(data: &[u8]) -> std::io::Result<Self> {
+        ron::de::from_bytes(data)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(std::io::Error::other)?;
+        std::fs::write(path, text)
+    }
+
+    /// Applies the session to the state of its tapes, which were loaded with
+    /// the time offsets of the session. Returns the dock layout without the
+    /// tabs that refer to callsites or spans the tapes no longer contain.
+    pub fn restore(mut self, state: &mut State) -> DockState<Tab> {
+        for callsite_color in &self.callsite_colors {
+            let index = state
+                .callsites
+                .tape_to_global(&callsite_color.tape, callsite_color.callsite_index);
+            if let Some(index) = index {
+                state.callsites[index].color = callsite_color.color;
+            }
+        }
+
+        if self.correlation_field != state.correlations.field {
+            state.correlations = Correlations::new(&state.loaded_tapes, self.correlation_field);
+        }
+        state.timeline_range = self.timeline_range;
+        state.selected_range = self.selected_range;
+        state.selected_item = self.selected_item.filter(|item| match item {
+            SelectedItem::Callsite(index) => *index < state.callsites.len(),
+            SelectedItem::Span { tape, span_index } => state
+                .loaded_tapes
+                .get(tape)
+                .is_some_and(|tape| span_index.index() < tape.tape.spans().node_count()),
+            SelectedItem::Event { tape, event_index } => state
+                .loaded_tapes
+                .get(tape)
+                .is_some_and(|tape| *event_index < tape.tape.events().len()),
+        });
+
+        self.dock_state.retain_tabs(|tab| tab.is_valid(state));
+        self.dock_state
+    }
+}
+
+/// What to open after a frame, e.g., when a recent file is clicked.
+pub enum Open {
+    Files(Vec<PathBuf>),
+    /// The session that was saved when trace-deck was closed.
+    LastSession,
+}
+
+/// Moves a path to the front of the list of recently opened files.
+pub fn add_recent_file(recent_files: &mut Vec<PathBuf>, path: PathBuf) {
+    recent_files.retain(|recent| *recent != path);
+    recent_files.insert(0, path);
+    recent_files.truncate(MAX_RECENT_FILES);
+}
+
+/// Serializes a [tracing::Level] as its name.
+pub mod level {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        level: &tracing::Level,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(level.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<tracing::Level, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[test]
+fn test_restore_drops_invalid_tabs() {
+    use petgraph::graph::NodeIndex;
+    use tracing_tape_parser::TapeBuilder;
+
+    use crate::testing::{callsite, span, state};
+
+    let tape = || {
+        let mut builder = TapeBuilder::new(16, 0);
+        let work = builder.callsite(callsite("work", &[]));
+        builder.span(None, span(work, 0..10, &[]));
+        builder.finish()
+    };
+    let path = PathBuf::from("test.tape");
+    let mut saved = state(vec![("test.tape", tape())]);
+    saved.selected_item = Some(SelectedItem::Span {
+        tape: path.clone(),
+        span_index: NodeIndex::new(1),
+    });
+    let dock_state = DockState::new(vec![
+        Tab::welcome(),
+        Tab::plot_span_duration(0, path.clone()),
+        Tab::plot_span_histogram(1, path.clone()),
+        Tab::plot_event_rate(0, PathBuf::from("other.tape")),
+        Tab::span_tree(path.clone(), NodeIndex::new(0)),
+        Tab::span_tree(path.clone(), NodeIndex::new(1)),
+    ]);
+    let session = Session::new(&saved, dock_state);
+    let text = ron::ser::to_string(&session).unwrap();
+    let session = Session::from_slice(text.as_bytes()).unwrap();
+
+    let mut state = state(vec![("test.tape", tape())]);
+    let dock_state = session.restore(&mut state);
+    let ids = dock_state
+        .iter_all_tabs()
+        .map(|(_, tab)| match tab {
+            Tab::Welcome(_) => "welcome",
+            Tab::PlotSpanDuration(_) => "plot",
+            Tab::SpanTree(_) => "tree",
+            _ => "other",
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ids, ["welcome", "plot", "tree"]);
+    assert_eq!(state.selected_item, None);
+}
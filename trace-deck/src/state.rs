@@ -1,19 +1,31 @@
 use std::{
-    ops::{Deref, DerefMut},
+    ops::{Deref, DerefMut, RangeInclusive},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use ahash::HashMap;
+use ahash::{HashMap, HashSet};
+use crossbeam_channel::Receiver;
+use petgraph::graph::NodeIndex;
 use time::Duration;
 use tracing_tape_parser::Tape;
 // use tracing_tape::Metadata;
 
-use crate::{statistics::CallsiteStatistics, tabs::SelectedItem, timeline::TimeRange, utils::AutoColor};
+use crate::{
+    correlation::{self, Correlations, LinkAnchor},
+    statistics::CallsiteStatistics,
+    tabs::SelectedItem,
+    timeline::TimeRange,
+    utils::AutoColor,
+};
 
 #[derive(Debug)]
 pub struct LoadedTape {
     pub path: PathBuf,
-    pub tape: Tape,
+    /// Shared with background workers, e.g., for statistics.
+    pub tape: Arc<Tape>,
+    /// Added to the timestamps of the tape, e.g., to correct a skewed clock
+    /// of the machine that recorded it.
     pub time_offset: time::Duration,
 }
 
@@ -38,7 +50,7 @@ impl LoadedTape {
         let time_range = self.tape.time_range();
         let start_time =
             time::OffsetDateTime::from_unix_timestamp_nanos(*time_range.start()).unwrap();
-        start_time - (global_start + self.time_offset)
+        start_time + self.time_offset - global_start
     }
 
     pub fn timestamp_to_global_offset(
@@ -80,29 +92,20 @@ impl Into<State> for Vec<LoadedTape> {
 
 impl Into<State> for LoadedTapes {
     fn into(self) -> State {
-        let t_min = self
-            .iter()
-            .map(|t| t.adjusted_timespan().start)
-            .min()
-            .unwrap_or_else(|| time::OffsetDateTime::from_unix_timestamp(0).expect("time"));
-
-        let t_max = self
-            .iter()
-            .map(|t| t.adjusted_timespan().end)
-            .max()
-            .unwrap_or_else(|| time::OffsetDateTime::from_unix_timestamp(0).expect("time"));
-
-        let timeline_duration = t_max - t_min;
+        let (t_min, timeline_duration) = self.time_span();
 
         State {
             current_action: Action::None,
             callsites: Callsites::for_loaded_tapes(&self),
+            correlations: Correlations::new(&self, correlation::DEFAULT_FIELD),
             loaded_tapes: self,
             timeline_start_time: t_min,
             timeline_duration,
             timeline_range: Duration::ZERO..=timeline_duration,
             selected_range: None,
             selected_item: None,
+            highlighted_spans: HashMap::default(),
+            link_anchors: Vec::new(),
         }
     }
 }
@@ -129,11 +132,37 @@ impl LoadedTapes {
     pub fn get_mut(&mut self, path: &Path) -> Option<&mut LoadedTape> {
         self.0.iter_mut().find(|tape| tape.path == path)
     }
+
+    pub fn index_of(&self, path: &Path) -> Option<usize> {
+        self.0.iter().position(|tape| tape.path == path)
+    }
+
+    /// The start and duration of the time span that covers all tapes.
+    fn time_span(&self) -> (time::OffsetDateTime, time::Duration) {
+        let t_min = self
+            .iter()
+            .map(|t| t.adjusted_timespan().start)
+            .min()
+            .unwrap_or_else(|| time::OffsetDateTime::from_unix_timestamp(0).expect("time"));
+
+        let t_max = self
+            .iter()
+            .map(|t| t.adjusted_timespan().end)
+            .max()
+            .unwrap_or_else(|| time::OffsetDateTime::from_unix_timestamp(0).expect("time"));
+
+        (t_min, t_max - t_min)
+    }
 }
 
 pub struct CallsiteTapeData {
     pub callsite_index: usize,
     pub statistics: Option<CallsiteStatistics>,
+    /// The timestamp range the statistics were calculated for, `None` for the
+    /// whole tape.
+    pub statistics_range: Option<RangeInclusive<i64>>,
+    /// The range and result of the statistics that are being calculated.
+    pub pending: Option<(Option<RangeInclusive<i64>>, Receiver<CallsiteStatistics>)>,
 }
 
 pub struct Callsite {
@@ -187,7 +216,17 @@ impl Callsites {
                     .inspect(|(path, tape_index)| {
                         tape_to_global.insert(((*path).clone(), *tape_index), global_index);
                     })
-                    .map(|(path, tape_index)| (path.clone(), CallsiteTapeData { callsite_index: tape_index, statistics: None }))
+                    .map(|(path, tape_index)| {
+                        (
+                            path.clone(),
+                            CallsiteTapeData {
+                                callsite_index: tape_index,
+                                statistics: None,
+                                statistics_range: None,
+                                pending: None,
+                            },
+                        )
+                    })
                     .collect(),
             };
 
@@ -209,6 +248,13 @@ impl Callsites {
     pub fn tape_to_global(&self, path: &Path, index: usize) -> Option<usize> {
         self.tape_to_global.get(&(path.to_path_buf(), index)).copied()
     }
+
+    /// Whether the global callsite index exists and the tape has the callsite.
+    pub fn is_in_tape(&self, index: usize, path: &Path) -> bool {
+        self.callsites
+            .get(index)
+            .is_some_and(|callsite| callsite.tape_data.contains_key(path))
+    }
 }
 
 impl Deref for Callsites {
@@ -239,4 +285,61 @@ pub struct State {
     pub selected_range: Option<TimeRange>,
     pub current_action: Action,
     pub selected_item: Option<SelectedItem>,
+    /// Spans that are outlined on the timelines, e.g., the spans of a
+    /// histogram bucket.
+    pub highlighted_spans: HashMap<PathBuf, HashSet<NodeIndex<usize>>>,
+    /// Spans of different tapes with the same correlation id.
+    pub correlations: Correlations,
+    /// Where the timelines drew linked spans during this frame.
+    pub link_anchors: Vec<LinkAnchor>,
+}
+
+impl State {
+    /// Moves the timeline such that the given range is centered. The timeline
+    /// is zoomed out if the range does not fit.
+    pub fn center_timeline(&mut self, range: TimeRange) {
+        let visible = *self.timeline_range.end() - *self.timeline_range.start();
+        let duration = *range.end() - *range.start();
+        let visible = if duration > visible * 0.8 {
+            duration * 1.25
+        } else {
+            visible
+        };
+        let center = *range.start() + duration / 2;
+        self.timeline_range = center - visible / 2..=center + visible / 2;
+    }
+
+    /// Sets the time offsets of the tapes and updates the time span of the
+    /// timeline such that the visible and selected ranges stay at the same
+    /// absolute time.
+    pub fn set_time_offsets(&mut self, time_offsets: &[time::Duration]) {
+        for (tape, time_offset) in self.loaded_tapes.iter_mut().zip(time_offsets) {
+            tape.time_offset = *time_offset;
+        }
+
+        let (start_time, duration) = self.loaded_tapes.time_span();
+        let shift = self.timeline_start_time - start_time;
+        self.timeline_start_time = start_time;
+        self.timeline_duration = duration;
+        self.timeline_range =
+            *self.timeline_range.start() + shift..=*self.timeline_range.end() + shift;
+        if let Some(selected_range) = &mut self.selected_range {
+            *selected_range = *selected_range.start() + shift..=*selected_range.end() + shift;
+        }
+    }
+
+    pub fn time_offsets(&self) -> Vec<time::Duration> {
+        self.loaded_tapes
+            .iter()
+            .map(|tape| tape.time_offset)
+            .collect()
+    }
+
+    /// The selected range or the visible range of the timeline if nothing is
+    /// selected.
+    pub fn focused_range(&self) -> TimeRange {
+        self.selected_range
+            .clone()
+            .unwrap_or_else(|| self.timeline_range.clone())
+    }
 }
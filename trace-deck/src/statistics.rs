@@ -1,4 +1,9 @@
-use tracing_tape_parser::Tape;
+use std::ops::{Range, RangeInclusive};
+
+use ahash::HashMap;
+use tracing_tape_parser::{percentile, Tape, Value};
+
+use crate::flamegraph::Weight;
 
 #[derive(Debug)]
 pub enum CallsiteStatistics {
@@ -15,33 +20,51 @@ pub struct SpanCallsiteStatistics {
     pub min: i64,
     pub max: i64,
     pub mean: i64,
+    pub p90: i64,
+    pub p95: i64,
+    pub p99: i64,
+    pub p999: i64,
     pub span_indices: Vec<usize>,
     pub outliers_slow: Vec<usize>,
     pub outliers_fast: Vec<usize>,
 }
 
-fn calculate_span_statistics(tape: &Tape, callsite_index: usize) -> SpanCallsiteStatistics {
+/// Calculates the statistics of the spans of a callsite that overlap the
+/// timestamp range or of all its spans.
+pub fn calculate_span_statistics(
+    tape: &Tape,
+    callsite_index: usize,
+    range: Option<RangeInclusive<i64>>,
+) -> SpanCallsiteStatistics {
     let mut min = i64::MAX;
     let mut max = i64::MIN;
     let mut sum = 0;
-    let mut spans: Vec<(usize, i64)> = tape
-        .spans()
-        .node_weights()
-        .enumerate()
-        .filter_map(|(index, span)| {
-            if span.callsite_index != callsite_index {
-                return None;
-            }
-
+    let mut query = tape.query_spans().callsite(callsite_index);
+    if let Some(range) = range {
+        query = query.time_range(range);
+    }
+    let mut spans: Vec<(usize, i64)> = query
+        .iter()
+        .map(|(index, span)| {
             let duration = span.closed - span.opened;
             sum += duration;
             min = min.min(duration);
             max = max.max(duration);
 
-            Some((index, duration))
+            (index.index(), duration)
         })
         .collect();
 
+    let mut sorted = spans
+        .iter()
+        .map(|(_, duration)| *duration)
+        .collect::<Vec<_>>();
+    sorted.sort_unstable();
+    let p90 = percentile(&sorted, 0.9);
+    let p95 = percentile(&sorted, 0.95);
+    let p99 = percentile(&sorted, 0.99);
+    let p999 = percentile(&sorted, 0.999);
+
     if spans.len() >= 3 {
         let q2_index = spans.len() / 2;
         let (lower_half, q2, upper_half) =
@@ -82,6 +105,10 @@ fn calculate_span_statistics(tape: &Tape, callsite_index: usize) -> SpanCallsite
             min,
             max,
             mean: sum / spans.len() as i64,
+            p90,
+            p95,
+            p99,
+            p999,
             outliers_slow,
             outliers_fast,
             span_indices: spans.into_iter().map(|(index, _)| index).collect(),
@@ -92,9 +119,13 @@ fn calculate_span_statistics(tape: &Tape, callsite_index: usize) -> SpanCallsite
             q2: 0,
             q3: 0,
             iqr: 0,
-            min,
-            max,
-            mean: sum / spans.len() as i64,
+            min: if spans.is_empty() { 0 } else { min },
+            max: if spans.is_empty() { 0 } else { max },
+            mean: sum / (spans.len() as i64).max(1),
+            p90,
+            p95,
+            p99,
+            p999,
             outliers_slow: vec![],
             outliers_fast: vec![],
             span_indices: spans.into_iter().map(|(index, _)| index).collect(),
@@ -102,18 +133,342 @@ fn calculate_span_statistics(tape: &Tape, callsite_index: usize) -> SpanCallsite
     }
 }
 
+/// Number of distinct values listed for non-numeric fields.
+const TOP_VALUES: usize = 5;
+
+#[derive(Debug)]
+pub struct EventCallsiteStatistics {
+    pub count: usize,
+    pub first: i64,
+    pub last: i64,
+    /// Events per second between the first and the last event.
+    pub rate: f64,
+    /// Number of inter-arrival times in `[2^i, 2^(i + 1))` nanoseconds for
+    /// each bucket `i`, zero is counted in the first bucket.
+    pub inter_arrival_histogram: Vec<usize>,
+    pub fields: Vec<FieldStatistics>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NumericFieldStatistics {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
 #[derive(Debug)]
-pub struct EventCallsiteStatistics {}
+pub struct FieldStatistics {
+    pub name: String,
+    pub numeric: Option<NumericFieldStatistics>,
+    pub distinct_values: usize,
+    /// The most frequent non-numeric values with their number of occurrences.
+    pub top_values: Vec<(String, usize)>,
+}
 
-fn calculate_event_statistics(_: &Tape, _: usize) -> EventCallsiteStatistics {
-    EventCallsiteStatistics {}
+fn calculate_event_statistics(
+    tape: &Tape,
+    callsite_index: usize,
+    range: Option<RangeInclusive<i64>>,
+) -> EventCallsiteStatistics {
+    let fields = tape.callsites()[callsite_index].fields();
+    let mut numeric = vec![None::<NumericFieldStatistics>; fields.len()];
+    let mut values = vec![HashMap::<String, usize>::default(); fields.len()];
+
+    let mut count = 0;
+    let mut first = 0;
+    let mut last = 0;
+    let mut inter_arrival_histogram = vec![];
+    let mut query = tape.query_events().callsite(callsite_index);
+    if let Some(range) = range {
+        query = query.time_range(range);
+    }
+    for event in query.iter() {
+        if count == 0 {
+            first = event.timestamp;
+        } else {
+            let inter_arrival = (event.timestamp - last).max(1) as u64;
+            let bucket = inter_arrival.ilog2() as usize;
+            if inter_arrival_histogram.len() <= bucket {
+                inter_arrival_histogram.resize(bucket + 1, 0);
+            }
+            inter_arrival_histogram[bucket] += 1;
+        }
+        count += 1;
+        last = event.timestamp;
+
+        for (index, value) in event.values.iter().enumerate().take(fields.len()) {
+            if let Some(value) = value.as_f64() {
+                let statistics = numeric[index].get_or_insert(NumericFieldStatistics {
+                    count: 0,
+                    min: f64::INFINITY,
+                    max: f64::NEG_INFINITY,
+                    mean: 0.0,
+                });
+                statistics.count += 1;
+                statistics.min = statistics.min.min(value);
+                statistics.max = statistics.max.max(value);
+                statistics.mean += (value - statistics.mean) / statistics.count as f64;
+            } else if !matches!(value, Value::Empty) {
+                *values[index].entry(value.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    let duration = (last - first) as f64 / 1e9;
+    let fields = fields
+        .iter()
+        .zip(numeric)
+        .zip(values)
+        .map(|((name, numeric), values)| {
+            let distinct_values = values.len();
+            let mut top_values = values.into_iter().collect::<Vec<_>>();
+            top_values.sort_unstable_by(|(value_a, count_a), (value_b, count_b)| {
+                count_b.cmp(count_a).then_with(|| value_a.cmp(value_b))
+            });
+            top_values.truncate(TOP_VALUES);
+            FieldStatistics {
+                name: name.to_string(),
+                numeric,
+                distinct_values,
+                top_values,
+            }
+        })
+        .collect();
+
+    EventCallsiteStatistics {
+        count,
+        first,
+        last,
+        rate: if duration > 0.0 {
+            (count - 1) as f64 / duration
+        } else {
+            0.0
+        },
+        inter_arrival_histogram,
+        fields,
+    }
 }
 
-pub fn calculate_statistics(tape: &Tape, callsite_index: usize) -> CallsiteStatistics {
+/// Calculates the statistics of a callsite within the timestamp range or over
+/// the whole tape.
+pub fn calculate_statistics(
+    tape: &Tape,
+    callsite_index: usize,
+    range: Option<RangeInclusive<i64>>,
+) -> CallsiteStatistics {
     let callsite = &tape.callsites()[callsite_index];
     if callsite.kind().is_span() {
-        CallsiteStatistics::Span(calculate_span_statistics(tape, callsite_index))
+        CallsiteStatistics::Span(calculate_span_statistics(tape, callsite_index, range))
     } else {
-        CallsiteStatistics::Event(calculate_event_statistics(tape, callsite_index))
+        CallsiteStatistics::Event(calculate_event_statistics(tape, callsite_index, range))
     }
 }
+
+/// Aggregated durations of the spans of a callsite.
+#[derive(Debug, Clone)]
+pub struct SpanSummary {
+    /// Index of the callsite in the tape.
+    pub callsite_index: usize,
+    pub count: usize,
+    /// Time the spans were open within the range.
+    pub total: i64,
+    /// Time spent in the spans within the range while none of their children
+    /// were entered.
+    pub self_time: i64,
+    /// The mean, percentiles, and maximum are of the whole durations of the
+    /// spans.
+    pub mean: i64,
+    pub p50: i64,
+    pub p95: i64,
+    pub p99: i64,
+    pub max: i64,
+}
+
+#[derive(Debug)]
+pub struct RangeStatistics {
+    pub range: Range<i64>,
+    /// Summaries of the span callsites with at least one span in the range.
+    pub callsites: Vec<SpanSummary>,
+}
+
+/// Summarizes the spans that overlap the timestamp range by callsite. Times
+/// are clipped to the range like the weights of the flamegraph.
+pub fn calculate_range_statistics(tape: &Tape, range: Range<i64>) -> RangeStatistics {
+    let mut durations = vec![Vec::new(); tape.callsites().len()];
+    let mut totals = vec![0; tape.callsites().len()];
+    let mut self_times = vec![0; tape.callsites().len()];
+    for (index, span) in tape
+        .query_spans()
+        .time_range(range.start..=range.end - 1)
+        .iter()
+    {
+        durations[span.callsite_index].push(span.closed - span.opened);
+        totals[span.callsite_index] += Weight::Wall.of(tape, index, &range);
+        self_times[span.callsite_index] += Weight::SelfTime.of(tape, index, &range);
+    }
+
+    let callsites = durations
+        .into_iter()
+        .zip(totals.into_iter().zip(self_times))
+        .enumerate()
+        .filter(|(_, (durations, _))| !durations.is_empty())
+        .map(|(callsite_index, (mut durations, (total, self_time)))| {
+            durations.sort_unstable();
+            SpanSummary {
+                callsite_index,
+                count: durations.len(),
+                total,
+                self_time,
+                mean: durations.iter().sum::<i64>() / durations.len() as i64,
+                p50: percentile(&durations, 0.5),
+                p95: percentile(&durations, 0.95),
+                p99: percentile(&durations, 0.99),
+                max: *durations.last().unwrap(),
+            }
+        })
+        .collect();
+
+    RangeStatistics { range, callsites }
+}
+
+#[test]
+fn test_range_statistics() {
+    use tracing_tape_parser::TapeBuilder;
+
+    use crate::testing::{callsite, span};
+
+    let mut builder = TapeBuilder::new(16, 0);
+    let outer = builder.callsite(callsite("outer", &[]));
+    let inner = builder.callsite(callsite("inner", &[]));
+    let root = builder.span(None, span(outer, 0..100, &[]));
+    builder.span(Some(root), span(inner, 20..40, &[]));
+    builder.span(Some(root), span(inner, 60..100, &[]));
+    builder.span(None, span(outer, 100..200, &[]));
+    let tape = builder.finish();
+
+    let statistics = calculate_range_statistics(&tape, 30..100);
+    let summary = |callsite_index| {
+        statistics
+            .callsites
+            .iter()
+            .find(|summary| summary.callsite_index == callsite_index)
+            .unwrap()
+    };
+    // The span opened at the end of the range is not part of it.
+    let outer = summary(outer);
+    assert_eq!((outer.count, outer.total, outer.self_time), (1, 70, 20));
+    assert_eq!((outer.mean, outer.max), (100, 100));
+    let inner = summary(inner);
+    assert_eq!((inner.count, inner.total, inner.self_time), (2, 50, 50));
+    assert_eq!((inner.p50, inner.max), (40, 40));
+}
+
+#[test]
+fn test_span_statistics() {
+    use tracing_tape_parser::TapeBuilder;
+
+    use crate::testing::{callsite, span};
+
+    let mut builder = TapeBuilder::new(16, 0);
+    let callsite = builder.callsite(callsite("span", &[]));
+    for duration in 1..=1000 {
+        let opened = duration * 1000;
+        builder.span(None, span(callsite, opened..opened + duration, &[]));
+    }
+    let tape = builder.finish();
+
+    let statistics = calculate_span_statistics(&tape, callsite, None);
+    assert_eq!(statistics.span_indices.len(), 1000);
+    assert_eq!(
+        (statistics.min, statistics.max, statistics.mean),
+        (1, 1000, 500)
+    );
+    assert_eq!(
+        (statistics.q1, statistics.q2, statistics.q3),
+        (251, 501, 751)
+    );
+    let percentiles = [
+        statistics.p90,
+        statistics.p95,
+        statistics.p99,
+        statistics.p999,
+    ];
+    assert_eq!(percentiles, [900, 950, 990, 999]);
+
+    // Only the spans overlapping the range are part of the statistics.
+    let statistics = calculate_span_statistics(&tape, callsite, Some(0..=10_000));
+    assert_eq!(statistics.span_indices.len(), 10);
+    assert_eq!((statistics.p90, statistics.p999), (9, 10));
+}
+
+#[test]
+fn test_event_statistics() {
+    use std::sync::Arc;
+
+    use tracing_tape_parser::{Callsite, Event, Metadata, TapeBuilder};
+
+    let mut builder = TapeBuilder::new(16, 0);
+    let callsite = builder.callsite(Callsite::Event(Metadata {
+        level: tracing::Level::INFO,
+        name: "event".into(),
+        target: "test".into(),
+        module_path: "test".into(),
+        file: None,
+        line: None,
+        fields: Arc::new(["value".into(), "kind".into()]),
+    }));
+    let events = [
+        (0, Value::F64(1.0), "b"),
+        (0, Value::I64(2), "a"),
+        (4, Value::U64(3), "b"),
+        (4, Value::Empty, "c"),
+        (4, Value::Empty, "d"),
+        (4, Value::Empty, "e"),
+        (1_000_000_000, Value::Empty, "f"),
+    ];
+    for (timestamp, value, kind) in events {
+        builder.event(Event {
+            timestamp,
+            callsite_index: callsite,
+            thread_id: 1,
+            span: None,
+            values: Arc::new([value, Value::String(kind.into())]),
+        });
+    }
+    let tape = builder.finish();
+
+    let statistics = calculate_event_statistics(&tape, callsite, None);
+    assert_eq!(statistics.count, 7);
+    assert_eq!((statistics.first, statistics.last), (0, 1_000_000_000));
+    assert_eq!(statistics.rate, 6.0);
+    // Zero gaps are counted in the first bucket.
+    let mut histogram = vec![0; 30];
+    histogram[0] = 4;
+    histogram[2] = 1;
+    histogram[29] = 1;
+    assert_eq!(statistics.inter_arrival_histogram, histogram);
+
+    let [value, kind] = &statistics.fields[..] else {
+        panic!("expected two fields");
+    };
+    let numeric = value.numeric.as_ref().unwrap();
+    assert_eq!(numeric.count, 3);
+    assert_eq!((numeric.min, numeric.max, numeric.mean), (1.0, 3.0, 2.0));
+    assert!(value.top_values.is_empty());
+    assert!(kind.numeric.is_none());
+    assert_eq!(kind.distinct_values, 6);
+    let top_values = kind
+        .top_values
+        .iter()
+        .map(|(value, count)| (value.as_str(), *count))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        top_values,
+        [("b", 2), ("a", 1), ("c", 1), ("d", 1), ("e", 1)]
+    );
+
+    let statistics = calculate_event_statistics(&tape, callsite, Some(1..=10));
+    assert_eq!(statistics.count, 4);
+    assert_eq!(statistics.rate, 0.0);
+}
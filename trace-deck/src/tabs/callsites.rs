@@ -1,8 +1,9 @@
 use egui::{text::LayoutJob, TextFormat};
+use serde::{Deserialize, Serialize};
 
 use super::TabViewer;
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Callsites {
     filter: String,
 }
@@ -1,24 +1,54 @@
 use std::path::PathBuf;
 
+use ahash::{HashMap, HashSet};
+use crossbeam_channel::TryRecvError;
+use egui_extras::{Column, TableBuilder};
+
 use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
 use time::Duration;
+use tracing_tape_parser::CriticalPathSegment;
 
 use crate::statistics::{
     calculate_statistics, CallsiteStatistics, EventCallsiteStatistics, SpanCallsiteStatistics,
 };
 
+use crate::{
+    state::LoadedTape,
+    utils::{self, level_color},
+};
+
 use super::{Tab, TabViewer};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Number of outliers listed for each tape.
+const MAX_OUTLIERS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SelectedItem {
     Callsite(usize),
     Span {
         tape: PathBuf,
         span_index: NodeIndex<usize>,
     },
+    Event {
+        tape: PathBuf,
+        /// The index of the event in [tracing_tape_parser::Tape::events].
+        event_index: usize,
+    },
 }
 
-pub struct Details;
+/// The critical path of a span, cached as long as the span is shown.
+struct CriticalPath {
+    tape: PathBuf,
+    span_index: NodeIndex<usize>,
+    segments: Vec<CriticalPathSegment>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Details {
+    #[serde(skip)]
+    critical_path: Option<CriticalPath>,
+}
 
 impl Details {
     pub fn id(&self) -> &str {
@@ -37,23 +67,63 @@ impl Details {
             Some(SelectedItem::Span { span_index, tape }) => {
                 self.span_ui(ui, viewer, tape.clone(), *span_index);
             }
+            Some(SelectedItem::Event { tape, event_index }) => {
+                self.event_ui(ui, viewer, tape.clone(), *event_index);
+            }
             None => {}
         }
     }
 
     fn callsite_ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer, callsite_index: usize) {
         let callsite = &mut viewer.state.callsites[callsite_index];
+        let selected_range = &viewer.state.selected_range;
+        let global_start = viewer.global_time_span.start;
 
         let mut tape_statistics = vec![];
+        let mut calculating = false;
 
+        // The statistics are calculated in the background, the previous ones
+        // are shown until they are done.
         for (path, data) in &mut callsite.tape_data {
-            let statistics = data.statistics.get_or_insert_with(|| {
-                calculate_statistics(
-                    &viewer.state.loaded_tapes.get(&path).unwrap().tape,
-                    data.callsite_index,
-                )
+            let loaded_tape = viewer.state.loaded_tapes.get(path).unwrap();
+            let range = selected_range.as_ref().map(|range| {
+                loaded_tape.global_offset_to_timestamp(*range.start(), global_start)
+                    ..=loaded_tape.global_offset_to_timestamp(*range.end(), global_start)
             });
-            tape_statistics.push((path, statistics));
+
+            if let Some((pending_range, receiver)) = &data.pending {
+                match receiver.try_recv() {
+                    Ok(statistics) => {
+                        data.statistics = Some(statistics);
+                        data.statistics_range = pending_range.clone();
+                        data.pending = None;
+                    }
+                    Err(TryRecvError::Disconnected) => data.pending = None,
+                    Err(TryRecvError::Empty) => {}
+                }
+            }
+
+            // Only one calculation runs at a time, the range may have changed
+            // again by the time it finishes.
+            let latest = match (&data.pending, &data.statistics) {
+                (Some((range, _)), _) => Some(range),
+                (None, Some(_)) => Some(&data.statistics_range),
+                (None, None) => None,
+            };
+            if latest != Some(&range) && data.pending.is_none() {
+                let tape = loaded_tape.tape.clone();
+                let callsite_index = data.callsite_index;
+                let job_range = range.clone();
+                let receiver = utils::spawn(ui.ctx(), move || {
+                    calculate_statistics(&tape, callsite_index, job_range)
+                });
+                data.pending = Some((range, receiver));
+            }
+
+            calculating |= data.pending.is_some();
+            if let Some(statistics) = &data.statistics {
+                tape_statistics.push((path, statistics));
+            }
         }
 
         ui.heading(callsite.inner.name().to_string());
@@ -83,29 +153,129 @@ impl Details {
 
                 ui.label("Fields");
                 ui.vertical(|ui| {
-                    for field in callsite.inner.fields() {
-                        ui.label(field.to_string());
+                    for (field_index, field) in callsite.inner.fields().iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(field.to_string());
+                            if ui.small_button("Plot").clicked() {
+                                viewer
+                                    .new_tabs
+                                    .push(Tab::plot_field_values(callsite_index, field_index));
+                            }
+                        });
                     }
                 });
                 ui.end_row();
             });
 
-        ui.heading("Statistics");
+        ui.horizontal(|ui| {
+            if selected_range.is_some() {
+                ui.heading("Statistics of the Selection");
+            } else {
+                ui.heading("Statistics");
+            }
+            if calculating {
+                ui.spinner();
+            }
+        });
+        let mut clicked_outlier = None;
         for (path, statistics) in tape_statistics {
             match statistics {
                 CallsiteStatistics::Span(span_statistics) => {
                     Self::span_statistics_ui(ui, span_statistics, path);
-                    if ui.button("Plot").clicked() {
-                        viewer
-                            .new_tabs
-                            .push(Tab::plot_span_duration(callsite_index, path.clone()));
+                    let loaded_tape = viewer.state.loaded_tapes.get(path).unwrap();
+                    for (label, outliers, slowest_first) in [
+                        ("Slow Outliers", &span_statistics.outliers_slow, true),
+                        ("Fast Outliers", &span_statistics.outliers_fast, false),
+                    ] {
+                        if let Some(span_index) = Self::outliers_ui(
+                            ui,
+                            label,
+                            outliers,
+                            slowest_first,
+                            loaded_tape,
+                            global_start,
+                        ) {
+                            clicked_outlier = Some((path.clone(), span_index));
+                        }
                     }
+                    ui.horizontal(|ui| {
+                        if ui.button("Plot").clicked() {
+                            viewer
+                                .new_tabs
+                                .push(Tab::plot_span_duration(callsite_index, path.clone()));
+                        }
+                        if ui.button("Histogram").clicked() {
+                            viewer
+                                .new_tabs
+                                .push(Tab::plot_span_histogram(callsite_index, path.clone()));
+                        }
+                    });
                 }
                 CallsiteStatistics::Event(event_statistics) => {
                     Self::event_statistics_ui(ui, event_statistics, path);
+                    if ui.button("Plot Rate").clicked() {
+                        viewer
+                            .new_tabs
+                            .push(Tab::plot_event_rate(callsite_index, path.clone()));
+                    }
                 }
             }
         }
+
+        if let Some((tape, span_index)) = clicked_outlier {
+            let loaded_tape = viewer.state.loaded_tapes.get(&tape).unwrap();
+            let span = &loaded_tape.tape.spans()[span_index];
+            let range = loaded_tape.timestamp_to_global_offset(span.opened, global_start)
+                ..=loaded_tape.timestamp_to_global_offset(span.closed, global_start);
+            viewer.state.center_timeline(range);
+            viewer.state.selected_item = Some(SelectedItem::Span { tape, span_index });
+        }
+    }
+
+    /// Lists the outlier spans by their duration, returns the clicked one.
+    fn outliers_ui(
+        ui: &mut egui::Ui,
+        label: &str,
+        outliers: &[usize],
+        slowest_first: bool,
+        loaded_tape: &LoadedTape,
+        global_start: time::OffsetDateTime,
+    ) -> Option<NodeIndex<usize>> {
+        let spans = loaded_tape.tape.spans();
+        let mut outliers = outliers
+            .iter()
+            .map(|index| {
+                let span = &spans[NodeIndex::new(*index)];
+                (span.closed - span.opened, NodeIndex::new(*index))
+            })
+            .collect::<Vec<_>>();
+        outliers.sort_unstable();
+        if slowest_first {
+            outliers.reverse();
+        }
+
+        let mut clicked = None;
+        egui::CollapsingHeader::new(format!("{} ({})", label, outliers.len()))
+            .id_salt((label, &loaded_tape.path))
+            .show(ui, |ui| {
+                for (duration, span_index) in outliers.iter().take(MAX_OUTLIERS) {
+                    let offset = loaded_tape
+                        .timestamp_to_global_offset(spans[*span_index].opened, global_start);
+                    if ui
+                        .selectable_label(
+                            false,
+                            format!("{:.1} at {:.6}", Duration::nanoseconds(*duration), offset),
+                        )
+                        .clicked()
+                    {
+                        clicked = Some(*span_index);
+                    }
+                }
+                if outliers.len() > MAX_OUTLIERS {
+                    ui.label(format!("and {} more", outliers.len() - MAX_OUTLIERS));
+                }
+            });
+        clicked
     }
 
     pub fn span_statistics_ui(
@@ -142,6 +312,17 @@ impl Details {
                 ui.label(format!("{}", Duration::nanoseconds(statistics.q3)));
                 ui.end_row();
 
+                for (label, value) in [
+                    ("p90", statistics.p90),
+                    ("p95", statistics.p95),
+                    ("p99", statistics.p99),
+                    ("p99.9", statistics.p999),
+                ] {
+                    ui.label(label);
+                    ui.label(format!("{}", Duration::nanoseconds(value)));
+                    ui.end_row();
+                }
+
                 ui.label("Max");
                 ui.label(format!("{}", Duration::nanoseconds(statistics.max)));
                 ui.end_row();
@@ -176,10 +357,89 @@ impl Details {
     }
 
     pub fn event_statistics_ui(
-        _ui: &mut egui::Ui,
-        _statistics: &EventCallsiteStatistics,
-        _tape_path: &PathBuf,
+        ui: &mut egui::Ui,
+        statistics: &EventCallsiteStatistics,
+        tape_path: &PathBuf,
     ) {
+        ui.label(tape_path.to_str().unwrap());
+        egui::Grid::new(("event_statistics", tape_path))
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Events");
+                ui.label(format!("{}", statistics.count));
+                ui.end_row();
+
+                ui.label("Time Span");
+                ui.label(format!(
+                    "{}",
+                    Duration::nanoseconds(statistics.last - statistics.first)
+                ));
+                ui.end_row();
+
+                ui.label("Rate");
+                ui.label(format!("{:.2}/s", statistics.rate));
+                ui.end_row();
+
+                ui.label("Inter-Arrival Times");
+                egui_plot::Plot::new(("inter_arrival", tape_path))
+                    .height(100.0)
+                    .show_y(false)
+                    .allow_drag(false)
+                    .allow_zoom(false)
+                    .allow_scroll(false)
+                    .allow_boxed_zoom(false)
+                    .x_axis_formatter(|mark, _| {
+                        format!("{}", Duration::nanoseconds(2f64.powf(mark.value) as i64))
+                    })
+                    .label_formatter(|_, point| {
+                        let bucket = point.x.round();
+                        format!(
+                            "{} to {}",
+                            Duration::nanoseconds(2f64.powf(bucket) as i64),
+                            Duration::nanoseconds(2f64.powf(bucket + 1.0) as i64),
+                        )
+                    })
+                    .show(ui, |ui| {
+                        ui.bar_chart(egui_plot::BarChart::new(
+                            "inter-arrival-histogram",
+                            statistics
+                                .inter_arrival_histogram
+                                .iter()
+                                .enumerate()
+                                .map(|(bucket, count)| {
+                                    egui_plot::Bar::new(bucket as f64, *count as f64)
+                                })
+                                .collect(),
+                        ));
+                    });
+                ui.end_row();
+            });
+
+        ui.label("Fields");
+        egui::Grid::new(("event_field_statistics", tape_path))
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for field in &statistics.fields {
+                    ui.label(&field.name);
+                    ui.vertical(|ui| {
+                        if let Some(numeric) = &field.numeric {
+                            ui.label(format!(
+                                "min {}, max {}, mean {:.3} ({} values)",
+                                numeric.min, numeric.max, numeric.mean, numeric.count
+                            ));
+                        }
+                        if field.distinct_values > 0 {
+                            ui.label(format!("{} distinct values", field.distinct_values));
+                            for (value, count) in &field.top_values {
+                                ui.label(format!("{}× {}", count, value));
+                            }
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
     }
 
     fn span_ui(
@@ -210,6 +470,16 @@ impl Details {
             {
                 viewer.state.selected_item = Some(SelectedItem::Callsite(global_callsite_index));
             }
+            ui.visuals_mut().override_text_color = None;
+            if ui
+                .button("Span Tree")
+                .on_hover_text("Show the descendants of the span as a table")
+                .clicked()
+            {
+                viewer
+                    .new_tabs
+                    .push(Tab::span_tree(tape.path.clone(), span_index));
+            }
         });
 
         egui::Grid::new("span_data")
@@ -235,5 +505,329 @@ impl Details {
                     ui.end_row();
                 }
             });
+
+        let mut clicked_segment = None;
+        egui::CollapsingHeader::new("Critical Path")
+            .id_salt("critical_path")
+            .show(ui, |ui| {
+                let up_to_date = self.critical_path.as_ref().is_some_and(|critical_path| {
+                    critical_path.tape == tape.path && critical_path.span_index == span_index
+                });
+                if !up_to_date {
+                    self.critical_path = Some(CriticalPath {
+                        tape: tape.path.clone(),
+                        span_index,
+                        segments: tape.tape.critical_path(span_index),
+                    });
+                }
+                let segments = &self.critical_path.as_ref().unwrap().segments;
+                clicked_segment = Self::critical_path_ui(
+                    ui,
+                    &mut viewer.state.highlighted_spans,
+                    tape,
+                    span.opened,
+                    span.closed,
+                    segments,
+                );
+            });
+
+        let mut clicked_link = None;
+        let linked = viewer
+            .state
+            .loaded_tapes
+            .index_of(&tape.path)
+            .map(|tape_index| {
+                viewer
+                    .state
+                    .correlations
+                    .linked((tape_index, span_index))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if !linked.is_empty() {
+            egui::CollapsingHeader::new(format!("Linked Spans ({})", linked.len()))
+                .id_salt("linked_spans")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("linked_spans")
+                        .num_columns(4)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (tape_index, linked_index) in linked {
+                                let linked_tape = &viewer.state.loaded_tapes[tape_index];
+                                let linked_span = &linked_tape.tape.spans()[linked_index];
+                                let linked_callsite =
+                                    &linked_tape.tape.callsites()[linked_span.callsite_index];
+                                ui.label(
+                                    linked_tape
+                                        .path
+                                        .file_name()
+                                        .unwrap_or_default()
+                                        .to_string_lossy(),
+                                );
+                                if ui.link(linked_callsite.name().to_string()).clicked() {
+                                    clicked_link = Some((tape_index, linked_index));
+                                }
+                                let start = linked_tape.timestamp_to_global_offset(
+                                    linked_span.opened,
+                                    viewer.global_time_span.start,
+                                ) - tape.timestamp_to_global_offset(
+                                    span.opened,
+                                    viewer.global_time_span.start,
+                                );
+                                ui.label(if start.is_negative() {
+                                    format!("{:.1}", start)
+                                } else {
+                                    format!("+{:.1}", start)
+                                });
+                                ui.label(format!(
+                                    "{:.1}",
+                                    Duration::nanoseconds(linked_span.closed - linked_span.opened)
+                                ));
+                                ui.end_row();
+                            }
+                        });
+                });
+        }
+
+        // Events of the span and its descendants.
+        let events = tape.tape.events();
+        let first = events.partition_point(|event| event.timestamp < span.opened);
+        let last = events.partition_point(|event| event.timestamp <= span.closed);
+        let span_events = events[first..last]
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| {
+                event.span.is_some_and(|event_span| {
+                    event_span == span_index
+                        || tape
+                            .tape
+                            .ancestors(event_span)
+                            .any(|span| span == span_index)
+                })
+            })
+            .map(|(index, event)| (first + index, event))
+            .collect::<Vec<_>>();
+
+        ui.label(format!("Events ({})", span_events.len()));
+        egui::ScrollArea::vertical()
+            .id_salt("span_events")
+            .show(ui, |ui| {
+                egui::Grid::new("span_events")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (event_index, event) in span_events {
+                            let event_callsite = &tape.tape.callsites()[event.callsite_index];
+                            ui.label(format!(
+                                "+{:.1}",
+                                Duration::nanoseconds(event.timestamp - span.opened)
+                            ));
+                            ui.colored_label(
+                                level_color(event_callsite.level()),
+                                event_callsite.level().as_str(),
+                            );
+                            let text = event_callsite
+                                .fields()
+                                .iter()
+                                .zip(event.values.iter())
+                                .map(|(name, value)| {
+                                    if &**name == "message" {
+                                        value.to_string()
+                                    } else {
+                                        format!("{name} = {value}")
+                                    }
+                                })
+                                .collect::<Vec<_>>()
+                                .join(" ");
+                            if ui.link(text).clicked() {
+                                viewer.state.selected_item = Some(SelectedItem::Event {
+                                    tape: tape.path.clone(),
+                                    event_index,
+                                });
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if let Some(span_index) = clicked_segment {
+            let span = &tape.tape.spans()[span_index];
+            let range = tape.timestamp_to_global_offset(span.opened, viewer.global_time_span.start)
+                ..=tape.timestamp_to_global_offset(span.closed, viewer.global_time_span.start);
+            viewer.state.selected_item = Some(SelectedItem::Span {
+                tape: tape.path.clone(),
+                span_index,
+            });
+            viewer.state.center_timeline(range);
+        }
+
+        if let Some((tape_index, span_index)) = clicked_link {
+            let tape = &viewer.state.loaded_tapes[tape_index];
+            let span = &tape.tape.spans()[span_index];
+            let range = tape.timestamp_to_global_offset(span.opened, viewer.global_time_span.start)
+                ..=tape.timestamp_to_global_offset(span.closed, viewer.global_time_span.start);
+            viewer.state.selected_item = Some(SelectedItem::Span {
+                tape: tape.path.clone(),
+                span_index,
+            });
+            viewer.state.center_timeline(range);
+        }
+    }
+
+    /// Lists the segments of a critical path with their share of the span,
+    /// returns the span of the clicked segment.
+    fn critical_path_ui(
+        ui: &mut egui::Ui,
+        highlighted_spans: &mut HashMap<PathBuf, HashSet<NodeIndex<usize>>>,
+        tape: &LoadedTape,
+        opened: i64,
+        closed: i64,
+        segments: &[CriticalPathSegment],
+    ) -> Option<NodeIndex<usize>> {
+        let duration = (closed - opened).max(1);
+        let busy = segments.iter().map(|segment| segment.busy).sum::<i64>();
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{} segments, busy for {:.1} of {:.1}",
+                segments.len(),
+                Duration::nanoseconds(busy),
+                Duration::nanoseconds(closed - opened)
+            ));
+            if ui
+                .button("Highlight")
+                .on_hover_text("Highlight the spans of the critical path on the timeline")
+                .clicked()
+            {
+                highlighted_spans.insert(
+                    tape.path.clone(),
+                    segments.iter().map(|segment| segment.span).collect(),
+                );
+            }
+        });
+
+        let mut clicked = None;
+        TableBuilder::new(ui)
+            .id_salt("critical_path_segments")
+            .striped(true)
+            .max_scroll_height(200.0)
+            .column(Column::auto().clip(true))
+            .columns(Column::auto(), 4)
+            .header(20.0, |mut header| {
+                for title in ["Span", "Start", "Duration", "Busy", "Share"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(18.0, segments.len(), |mut row| {
+                    let segment = &segments[row.index()];
+                    let span = &tape.tape.spans()[segment.span];
+                    row.col(|ui| {
+                        let name = tape.tape.callsites()[span.callsite_index].name();
+                        if ui.link(name).clicked() {
+                            clicked = Some(segment.span);
+                        }
+                    });
+                    row.col(|ui| {
+                        ui.label(format!(
+                            "+{:.1}",
+                            Duration::nanoseconds(segment.start - opened)
+                        ));
+                    });
+                    for value in [segment.duration(), segment.busy] {
+                        row.col(|ui| {
+                            ui.label(format!("{:.1}", Duration::nanoseconds(value)));
+                        });
+                    }
+                    row.col(|ui| {
+                        ui.label(format!(
+                            "{:.1}%",
+                            segment.duration() as f64 / duration as f64 * 100.0
+                        ));
+                    });
+                });
+            });
+        clicked
+    }
+
+    fn event_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        viewer: &mut TabViewer,
+        tape: PathBuf,
+        event_index: usize,
+    ) {
+        let tape = viewer.state.loaded_tapes.get(&tape).unwrap();
+        let event = &tape.tape.events()[event_index];
+        let callsite = tape.tape.callsites().get(event.callsite_index).unwrap();
+
+        let global_callsite_index = viewer
+            .state
+            .callsites
+            .tape_to_global(&tape.path, event.callsite_index)
+            .unwrap();
+
+        let global_callsite = &viewer.state.callsites[global_callsite_index];
+
+        ui.horizontal(|ui| {
+            ui.heading(format!("Event {:x}", event_index));
+            ui.visuals_mut().override_text_color = Some(egui::Color32::WHITE);
+            if ui
+                .add(egui::Button::new(callsite.name()).fill(global_callsite.color))
+                .clicked()
+            {
+                viewer.state.selected_item = Some(SelectedItem::Callsite(global_callsite_index));
+            }
+        });
+
+        egui::Grid::new("event_data")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Time");
+                ui.label(format!("{}", tape.timestamp_date_time(event.timestamp)));
+                ui.end_row();
+
+                ui.label("Level");
+                ui.label(callsite.level().to_string());
+                ui.end_row();
+
+                ui.label("Thread");
+                match tape.tape.threads().get(&event.thread_id) {
+                    Some(Some(name)) => ui.label(name.to_string()),
+                    _ => ui.label(format!("{:x}", event.thread_id)),
+                };
+                ui.end_row();
+
+                if let Some(span_index) = event.span {
+                    let span = &tape.tape.spans()[span_index];
+                    ui.label("Span");
+                    let name = tape.tape.callsites()[span.callsite_index].name();
+                    if ui
+                        .link(format!("{} {:x}", name, span_index.index()))
+                        .clicked()
+                    {
+                        viewer.state.selected_item = Some(SelectedItem::Span {
+                            tape: tape.path.clone(),
+                            span_index,
+                        });
+                    }
+                    ui.end_row();
+                }
+            });
+
+        ui.label("Fields");
+        egui::Grid::new("event_fields")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for (name, value) in callsite.fields().iter().zip(event.values.iter()) {
+                    ui.label(name.to_string());
+                    ui.label(value.to_string());
+                    ui.end_row();
+                }
+            });
     }
 }
@@ -0,0 +1,280 @@
+use std::{ops::Range, path::PathBuf};
+
+use crossbeam_channel::{Receiver, TryRecvError};
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use crate::{
+    flamegraph::{CallTree, Weight},
+    state::Callsites,
+};
+
+use super::{Tab, TabViewer};
+
+/// Frames narrower than this many points are not drawn.
+const MIN_FRAME_WIDTH: f32 = 1.0;
+
+/// Aggregates the spans of a tape by call path and draws them as an icicle
+/// graph, i.e., with the callers on top.
+#[derive(Serialize, Deserialize)]
+pub struct Flamegraph {
+    title: String,
+    tape_path: PathBuf,
+    weight: Weight,
+    /// Call path of the frame that fills the width, empty for the root.
+    zoom: Vec<usize>,
+    #[serde(skip)]
+    tree: Option<Box<CallTree>>,
+    /// The tree that is built in the background with its weight and range.
+    #[serde(skip)]
+    pending: Option<(Weight, Range<i64>, Receiver<CallTree>)>,
+}
+
+impl Flamegraph {
+    pub fn new<P: Into<PathBuf>>(tape_path: P) -> Self {
+        let tape_path = tape_path.into();
+        let short_filename = tape_path
+            .file_name()
+            .map(|f| f.to_string_lossy())
+            .unwrap_or_else(|| tape_path.to_string_lossy());
+
+        let title = format!("Flamegraph {}", short_filename);
+        Self {
+            title,
+            tape_path,
+            weight: Weight::default(),
+            zoom: Vec::new(),
+            tree: None,
+            pending: None,
+        }
+    }
+
+    pub fn id(&self) -> (&PathBuf, &str) {
+        (&self.tape_path, "flamegraph")
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        (&self.title).into()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        let Some(loaded_tape) = viewer.state.loaded_tapes.get(&self.tape_path) else {
+            return;
+        };
+
+        let range = viewer.state.focused_range();
+        let start =
+            loaded_tape.global_offset_to_timestamp(*range.start(), viewer.global_time_span.start);
+        let end =
+            loaded_tape.global_offset_to_timestamp(*range.end(), viewer.global_time_span.start);
+
+        if let Some((_, _, receiver)) = &self.pending {
+            match receiver.try_recv() {
+                Ok(tree) => {
+                    self.tree = Some(Box::new(tree));
+                    self.pending = None;
+                }
+                Err(TryRecvError::Disconnected) => self.pending = None,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+
+        ui.horizontal(|ui| {
+            for weight in Weight::ALL {
+                ui.selectable_value(&mut self.weight, weight, weight.name());
+            }
+            ui.separator();
+            if ui
+                .add_enabled(!self.zoom.is_empty(), egui::Button::new("Reset Zoom"))
+                .clicked()
+            {
+                self.zoom.clear();
+            }
+            ui.label(format!("Range: {:.3}", Duration::nanoseconds(end - start)));
+            ui.separator();
+            if ui
+                .button("Compare")
+                .on_hover_text("Compare with another tape or range")
+                .clicked()
+            {
+                // Another tape is the most likely comparison.
+                let other = viewer
+                    .state
+                    .loaded_tapes
+                    .iter()
+                    .map(|tape| &tape.path)
+                    .find(|path| **path != self.tape_path)
+                    .unwrap_or(&self.tape_path);
+                viewer
+                    .new_tabs
+                    .push(Tab::flamegraph_diff(self.tape_path.clone(), other.clone()));
+            }
+            if ui
+                .button("Call Tree")
+                .on_hover_text("Show the call paths as a table")
+                .clicked()
+            {
+                viewer.new_tabs.push(Tab::call_tree(self.tape_path.clone()));
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+        });
+
+        // Only one tree is built at a time, the previous one is shown until
+        // it is done.
+        let latest = match (&self.pending, &self.tree) {
+            (Some((weight, range, _)), _) => Some((*weight, range.clone())),
+            (None, Some(tree)) => Some((tree.weight, tree.range.clone())),
+            (None, None) => None,
+        };
+        if latest != Some((self.weight, start..end)) && self.pending.is_none() {
+            let receiver = CallTree::spawn(
+                ui.ctx(),
+                loaded_tape,
+                &viewer.state.callsites,
+                start..end,
+                self.weight,
+            );
+            self.pending = Some((self.weight, start..end, receiver));
+        }
+
+        let Some(tree) = &self.tree else {
+            return;
+        };
+        let callsites = &viewer.state.callsites;
+        let root_width = tree.frames[CallTree::ROOT].width.max(1) as f64;
+        let weight = self.weight;
+
+        frames_ui(
+            ui,
+            tree,
+            callsites,
+            &mut self.zoom,
+            |frame| match tree.frames[frame].callsite_index {
+                Some(callsite_index) => callsites[callsite_index].color,
+                None => egui::Color32::GRAY,
+            },
+            |ui, frame| {
+                let mut text = frame_description(tree, callsites, frame);
+                let frame = &tree.frames[frame];
+                text.push_str(&format!(
+                    "\n{:.1}% of {} time",
+                    frame.width as f64 / root_width * 100.0,
+                    weight.name().to_lowercase()
+                ));
+                if frame.count > 0 {
+                    text.push_str(&format!(
+                        "\nCount: {}\nTotal: {:.1}\nMean: {:.1}",
+                        frame.count,
+                        Duration::nanoseconds(frame.total),
+                        Duration::nanoseconds(frame.mean())
+                    ));
+                }
+                ui.label(text);
+            },
+        );
+    }
+}
+
+/// The name and target of the callsite of a frame.
+pub(super) fn frame_description(tree: &CallTree, callsites: &Callsites, frame: usize) -> String {
+    match tree.frames[frame].callsite_index {
+        Some(callsite_index) => {
+            let callsite = &callsites[callsite_index].inner;
+            format!("{}\n{}", callsite.name(), callsite.target())
+        }
+        None => "all".to_string(),
+    }
+}
+
+/// Draws the frames of the tree as an icicle graph where the zoomed frame
+/// fills the width. Clicking a frame zooms to it.
+pub(super) fn frames_ui(
+    ui: &mut egui::Ui,
+    tree: &CallTree,
+    callsites: &Callsites,
+    zoom: &mut Vec<usize>,
+    color: impl Fn(usize) -> egui::Color32,
+    tooltip: impl FnOnce(&mut egui::Ui, usize),
+) {
+    // The zoomed frame may be gone if the range changed.
+    let zoomed = tree.find(zoom).unwrap_or(CallTree::ROOT);
+    let mut ancestors =
+        std::iter::successors(Some(zoomed), |frame| tree.frames[*frame].parent).collect::<Vec<_>>();
+    ancestors.reverse();
+
+    let font_id = egui::FontId::default();
+    let padding = ui.style().spacing.button_padding;
+    let row_height = font_id.size + 2.0 * padding.y;
+    let text_color = egui::Color32::WHITE;
+
+    let mut hovered = None;
+    egui::ScrollArea::vertical()
+        .auto_shrink(false)
+        .show(ui, |ui| {
+            let height = (ancestors.len() + tree.depth) as f32 * row_height;
+            let size = egui::vec2(ui.available_width(), height.max(ui.available_height()));
+            let (response, painter) = ui.allocate_painter(size, egui::Sense::click());
+            let rect = response.rect;
+            let pointer = response.hover_pos();
+
+            let mut paint = |frame: usize, left: f32, width: f32, depth: usize| {
+                let top = rect.top() + depth as f32 * row_height;
+                let frame_rect = egui::Rect::from_min_size(
+                    egui::pos2(left, top),
+                    egui::vec2(width, row_height - 1.0),
+                );
+                painter.rect_filled(frame_rect, 0.0, color(frame));
+                if pointer.is_some_and(|pointer| frame_rect.contains(pointer)) {
+                    hovered = Some(frame);
+                    painter.rect_stroke(
+                        frame_rect,
+                        0.0,
+                        egui::Stroke::new(1.0, text_color),
+                        egui::StrokeKind::Inside,
+                    );
+                }
+                if width > 2.0 * padding.x {
+                    let name = match tree.frames[frame].callsite_index {
+                        Some(callsite_index) => callsites[callsite_index].inner.name(),
+                        None => "all",
+                    };
+                    painter.with_clip_rect(frame_rect.shrink2(padding)).text(
+                        frame_rect.left_center() + egui::vec2(padding.x, 0.0),
+                        egui::Align2::LEFT_CENTER,
+                        name,
+                        font_id.clone(),
+                        text_color,
+                    );
+                }
+            };
+
+            // The zoomed frame and its ancestors fill the width.
+            for (depth, frame) in ancestors.iter().enumerate() {
+                paint(*frame, rect.left(), rect.width(), depth);
+            }
+
+            let scale = rect.width() / tree.frames[zoomed].width.max(1) as f32;
+            let mut stack = vec![(zoomed, rect.left(), ancestors.len() - 1)];
+            while let Some((frame, left, depth)) = stack.pop() {
+                let mut left = left;
+                for child in &tree.frames[frame].children {
+                    let width = tree.frames[*child].width as f32 * scale;
+                    if width >= MIN_FRAME_WIDTH {
+                        paint(*child, left, width, depth + 1);
+                        stack.push((*child, left, depth + 1));
+                    }
+                    left += width;
+                }
+            }
+
+            let Some(frame) = hovered else {
+                return;
+            };
+            if response.clicked() {
+                *zoom = tree.path(frame);
+            }
+            response.on_hover_ui_at_pointer(|ui| tooltip(ui, frame));
+        });
+}
@@ -0,0 +1,415 @@
+use std::{
+    ops::Range,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use ahash::HashMap;
+use crossbeam_channel::{Receiver, TryRecvError};
+use egui::Align;
+use egui_extras::{Column, TableBuilder};
+use serde::{Deserialize, Serialize};
+use time::Duration;
+use tracing_tape_parser::Tape;
+
+use crate::{
+    flamegraph::{global_callsites, CallTree, Weight},
+    state::Callsites,
+    statistics::{calculate_span_statistics, SpanCallsiteStatistics},
+    timeline::TimeRange,
+    utils,
+};
+
+use super::{
+    flamegraph::{frame_description, frames_ui},
+    SelectedItem, TabViewer,
+};
+
+/// Number of callsites listed with their change.
+const TOP_CHANGES: usize = 20;
+
+const UNCHANGED: egui::Color32 = egui::Color32::from_rgb(0x70, 0x70, 0x70);
+const REGRESSION: egui::Color32 = egui::Color32::from_rgb(0xd0, 0x30, 0x30);
+const IMPROVEMENT: egui::Color32 = egui::Color32::from_rgb(0x30, 0x70, 0xd0);
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn next_id() -> usize {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The color of a change from `before` to `after`, saturated if the time
+/// doubled or vanished.
+fn change_color(before: i64, after: i64) -> egui::Color32 {
+    let change = (after - before) as f32 / before.max(after).max(1) as f32;
+    if change > 0.0 {
+        UNCHANGED.lerp_to_gamma(REGRESSION, change)
+    } else {
+        UNCHANGED.lerp_to_gamma(IMPROVEMENT, -change)
+    }
+}
+
+fn signed(nanoseconds: i64) -> String {
+    let sign = if nanoseconds > 0 { "+" } else { "" };
+    format!("{}{:.1}", sign, Duration::nanoseconds(nanoseconds))
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// One side of the comparison.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Side {
+    tape: PathBuf,
+    /// Range of the global timeline or the whole tape if `None`.
+    range: Option<TimeRange>,
+}
+
+impl Side {
+    fn ui(&mut self, ui: &mut egui::Ui, label: &str, viewer: &TabViewer) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            egui::ComboBox::from_id_salt(ui.id().with(label))
+                .selected_text(file_name(&self.tape))
+                .show_ui(ui, |ui| {
+                    for loaded_tape in viewer.state.loaded_tapes.iter() {
+                        ui.selectable_value(
+                            &mut self.tape,
+                            loaded_tape.path.clone(),
+                            file_name(&loaded_tape.path),
+                        );
+                    }
+                });
+            if ui
+                .selectable_label(self.range.is_none(), "Whole Tape")
+                .clicked()
+            {
+                self.range = None;
+            }
+            if ui
+                .button("Use Range")
+                .on_hover_text("Use the selected or visible range of the timeline")
+                .clicked()
+            {
+                self.range = Some(viewer.state.focused_range());
+            }
+            if let Some(range) = &self.range {
+                ui.label(format!("{:.3} to {:.3}", range.start(), range.end()));
+            }
+        });
+    }
+}
+
+/// The time spent in a callsite on both sides.
+struct Change {
+    callsite_index: usize,
+    totals: [i64; 2],
+    statistics: [Option<SpanCallsiteStatistics>; 2],
+}
+
+struct Comparison {
+    sides: [Side; 2],
+    weight: Weight,
+    trees: [CallTree; 2],
+    /// The callsites with the largest absolute change of their total time.
+    changes: Vec<Change>,
+}
+
+impl Comparison {
+    /// Compares the sides on the rayon thread pool, `None` if a tape is not
+    /// loaded.
+    fn spawn(
+        ctx: &egui::Context,
+        sides: &[Side; 2],
+        weight: Weight,
+        viewer: &TabViewer,
+    ) -> Option<Receiver<Self>> {
+        let mut inputs = Vec::with_capacity(2);
+        for side in sides {
+            let loaded_tape = viewer.state.loaded_tapes.get(&side.tape)?;
+            let range = match &side.range {
+                Some(range) => {
+                    let global_start = viewer.global_time_span.start;
+                    loaded_tape.global_offset_to_timestamp(*range.start(), global_start)
+                        ..loaded_tape.global_offset_to_timestamp(*range.end(), global_start)
+                }
+                None => {
+                    let range = loaded_tape.tape.timestamp_range();
+                    *range.start()..*range.end() + 1
+                }
+            };
+            inputs.push((
+                loaded_tape.tape.clone(),
+                global_callsites(loaded_tape, &viewer.state.callsites),
+                range,
+            ));
+        }
+
+        let sides = sides.clone();
+        Some(utils::spawn(ctx, move || Self::new(sides, weight, inputs)))
+    }
+
+    /// Compares the tapes with the global indices of their callsites within
+    /// the timestamp ranges of the sides.
+    fn new(
+        sides: [Side; 2],
+        weight: Weight,
+        inputs: Vec<(Arc<Tape>, Vec<usize>, Range<i64>)>,
+    ) -> Self {
+        let trees = [0, 1].map(|side| {
+            let (tape, global_callsites, range) = &inputs[side];
+            CallTree::build(tape, global_callsites, range.clone(), weight)
+        });
+
+        let mut totals = HashMap::<usize, [i64; 2]>::default();
+        for (side, tree) in trees.iter().enumerate() {
+            for (callsite_index, total) in &tree.totals {
+                totals.entry(*callsite_index).or_default()[side] += total;
+            }
+        }
+        let mut totals = totals.into_iter().collect::<Vec<_>>();
+        totals.sort_by_key(|(callsite_index, [before, after])| {
+            (std::cmp::Reverse((after - before).abs()), *callsite_index)
+        });
+        totals.truncate(TOP_CHANGES);
+
+        let changes = totals
+            .into_iter()
+            .map(|(callsite_index, totals)| Change {
+                callsite_index,
+                totals,
+                statistics: [0, 1].map(|side| {
+                    let (tape, global_callsites, range) = &inputs[side];
+                    let local_index = global_callsites
+                        .iter()
+                        .position(|global_index| *global_index == callsite_index)?;
+                    Some(calculate_span_statistics(
+                        tape,
+                        local_index,
+                        Some(range.start..=range.end - 1),
+                    ))
+                }),
+            })
+            .collect();
+
+        Self {
+            sides,
+            weight,
+            trees,
+            changes,
+        }
+    }
+
+    /// The width of a frame of the second tree and of the frame with the same
+    /// call path in the first tree.
+    fn frame_widths(&self, frame: usize) -> (i64, i64) {
+        let [before, after] = &self.trees;
+        let before = before
+            .find(&after.path(frame))
+            .map_or(0, |frame| before.frames[frame].width);
+        (before, after.frames[frame].width)
+    }
+
+    fn changes_ui(&self, ui: &mut egui::Ui, callsites: &Callsites) -> Option<usize> {
+        let mut clicked = None;
+        let delta = |change: &Change, statistic: fn(&SpanCallsiteStatistics) -> i64| match &change
+            .statistics
+        {
+            [Some(before), Some(after)] => signed(statistic(after) - statistic(before)),
+            _ => String::new(),
+        };
+
+        TableBuilder::new(ui)
+            .auto_shrink(false)
+            .striped(true)
+            .sense(egui::Sense::click())
+            .column(Column::remainder().clip(true))
+            .columns(Column::auto(), 6)
+            .cell_layout(egui::Layout::left_to_right(Align::Center))
+            .header(20.0, |mut header| {
+                for title in [
+                    "Callsite", "Before", "After", "Change", "Δ Mean", "Δ p50", "Δ p75",
+                ] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(18.0, self.changes.len(), |mut row| {
+                    let change = &self.changes[row.index()];
+                    let [before, after] = change.totals;
+                    row.col(|ui| {
+                        ui.label(callsites[change.callsite_index].inner.name());
+                    });
+                    row.col(|ui| {
+                        ui.label(format!("{:.1}", Duration::nanoseconds(before)));
+                    });
+                    row.col(|ui| {
+                        ui.label(format!("{:.1}", Duration::nanoseconds(after)));
+                    });
+                    row.col(|ui| {
+                        ui.colored_label(change_color(before, after), signed(after - before));
+                    });
+                    row.col(|ui| {
+                        ui.label(delta(change, |statistics| statistics.mean));
+                    });
+                    row.col(|ui| {
+                        ui.label(delta(change, |statistics| statistics.q2));
+                    });
+                    row.col(|ui| {
+                        ui.label(delta(change, |statistics| statistics.q3));
+                    });
+                    if row.response().clicked() {
+                        clicked = Some(change.callsite_index);
+                    }
+                });
+            });
+
+        clicked
+    }
+}
+
+/// Compares the call paths of two tapes or two ranges of a tape. The frames
+/// are laid out like the flamegraph of the second side and colored by how
+/// much their time changed, call paths that only exist on the first side are
+/// only part of the listed changes.
+#[derive(Serialize, Deserialize)]
+pub struct FlamegraphDiff {
+    #[serde(skip, default = "next_id")]
+    id: usize,
+    sides: [Side; 2],
+    weight: Weight,
+    /// Call path of the frame that fills the width, empty for the root.
+    zoom: Vec<usize>,
+    #[serde(skip)]
+    comparison: Option<Box<Comparison>>,
+    /// The comparison that is calculated in the background with its sides
+    /// and weight.
+    #[serde(skip)]
+    pending: Option<([Side; 2], Weight, Receiver<Comparison>)>,
+}
+
+impl FlamegraphDiff {
+    pub fn new(before: PathBuf, after: PathBuf) -> Self {
+        Self {
+            id: next_id(),
+            sides: [
+                Side {
+                    tape: before,
+                    range: None,
+                },
+                Side {
+                    tape: after,
+                    range: None,
+                },
+            ],
+            weight: Weight::default(),
+            zoom: Vec::new(),
+            comparison: None,
+            pending: None,
+        }
+    }
+
+    pub fn id(&self) -> egui::Id {
+        egui::Id::new(("flamegraph_diff", self.id))
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        "Flamegraph Diff".into()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        let [before, after] = &mut self.sides;
+        before.ui(ui, "Before", viewer);
+        after.ui(ui, "After", viewer);
+        ui.horizontal(|ui| {
+            for weight in Weight::ALL {
+                ui.selectable_value(&mut self.weight, weight, weight.name());
+            }
+            ui.separator();
+            if ui
+                .add_enabled(!self.zoom.is_empty(), egui::Button::new("Reset Zoom"))
+                .clicked()
+            {
+                self.zoom.clear();
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+        });
+
+        if let Some((_, _, receiver)) = &self.pending {
+            match receiver.try_recv() {
+                Ok(comparison) => {
+                    self.comparison = Some(Box::new(comparison));
+                    self.pending = None;
+                }
+                Err(TryRecvError::Disconnected) => self.pending = None,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+
+        // Only one comparison is calculated at a time, the previous one is
+        // shown until it is done.
+        let latest = match (&self.pending, &self.comparison) {
+            (Some((sides, weight, _)), _) => Some((sides, *weight)),
+            (None, Some(comparison)) => Some((&comparison.sides, comparison.weight)),
+            (None, None) => None,
+        };
+        if latest != Some((&self.sides, self.weight)) && self.pending.is_none() {
+            if let Some(receiver) = Comparison::spawn(ui.ctx(), &self.sides, self.weight, viewer) {
+                self.pending = Some((self.sides.clone(), self.weight, receiver));
+            }
+        }
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+        let callsites = &viewer.state.callsites;
+
+        let clicked = egui::TopBottomPanel::bottom(self.id().with("changes"))
+            .resizable(true)
+            .show_inside(ui, |ui| comparison.changes_ui(ui, callsites))
+            .inner;
+
+        let tree = &comparison.trees[1];
+        frames_ui(
+            ui,
+            tree,
+            callsites,
+            &mut self.zoom,
+            |frame| {
+                let (before, after) = comparison.frame_widths(frame);
+                change_color(before, after)
+            },
+            |ui, frame| {
+                let (before, after) = comparison.frame_widths(frame);
+                let mut text = frame_description(tree, callsites, frame);
+                text.push_str(&format!(
+                    "\nBefore: {:.1}\nAfter: {:.1}\nChange: {}",
+                    Duration::nanoseconds(before),
+                    Duration::nanoseconds(after),
+                    signed(after - before)
+                ));
+                if before > 0 {
+                    text.push_str(&format!(
+                        " ({:+.1}%)",
+                        (after - before) as f64 / before as f64 * 100.0
+                    ));
+                }
+                ui.label(text);
+            },
+        );
+
+        if let Some(callsite_index) = clicked {
+            viewer.state.selected_item = Some(SelectedItem::Callsite(callsite_index));
+        }
+    }
+}
@@ -1,11 +1,16 @@
+use serde::{Deserialize, Serialize};
 use time::Duration;
 
-use crate::{timeline::Timeline, utils::AutoColor};
+use crate::{correlation::Correlations, timeline::Timeline, utils::AutoColor};
 
 use super::TabViewer;
 
-#[derive(Default)]
-pub struct GlobalTimeline {}
+#[derive(Default, Serialize, Deserialize)]
+pub struct GlobalTimeline {
+    /// The correlation field while it is edited.
+    #[serde(skip)]
+    correlation_field: Option<String>,
+}
 
 impl GlobalTimeline {
     pub fn id(&self) -> &str {
@@ -17,6 +22,11 @@ impl GlobalTimeline {
     }
 
     pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        if viewer.state.loaded_tapes.len() > 1 {
+            self.correlation_ui(ui, viewer);
+            Self::time_offsets_ui(ui, viewer);
+        }
+
         let timeline = Timeline::new(
             "Global Timeline",
             Duration::ZERO..=viewer.state.timeline_duration,
@@ -26,6 +36,9 @@ impl GlobalTimeline {
         .with_selected_range(Some(viewer.state.timeline_range.clone()))
         .without_drag();
 
+        let draggable = viewer.state.loaded_tapes.len() > 1;
+        let mut dragged = None;
+        let mut drag_stopped = false;
         timeline.show(ui, |timeline_ui, _| {
             let mut color_iter = AutoColor::default();
             for (level, tape) in viewer.state.loaded_tapes.iter().enumerate() {
@@ -33,13 +46,115 @@ impl GlobalTimeline {
                 let span = tape.adjusted_timespan();
                 let end = start + (span.end - span.start);
 
-                timeline_ui.item(
+                let response = timeline_ui.item(
                     level,
                     tape.path.to_string_lossy().to_string(),
                     color_iter.next().expect("color"),
                     start..=end,
                 );
+
+                if draggable {
+                    let response = timeline_ui
+                        .drag_handle(egui::Id::new(("time_offset", level)), response.rect)
+                        .on_hover_text("Drag to shift the clock of the tape");
+                    if response.dragged() {
+                        dragged = Some((level, timeline_ui.dx2dt(response.drag_delta().x)));
+                    }
+                    drag_stopped |= response.drag_stopped();
+                }
+            }
+        });
+
+        // The time span of the timeline is kept while dragging, otherwise the
+        // other tapes would move when the first one is dragged.
+        if let Some((level, delta)) = dragged {
+            viewer.state.loaded_tapes[level].time_offset += delta;
+        }
+        if drag_stopped {
+            let time_offsets = viewer.state.time_offsets();
+            viewer.state.set_time_offsets(&time_offsets);
+        }
+    }
+
+    /// Shows the time offsets of the tapes, which align the clocks of the
+    /// machines that recorded them.
+    fn time_offsets_ui(ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        egui::CollapsingHeader::new("Clock Offsets")
+            .id_salt("time_offsets")
+            .show(ui, |ui| {
+                let mut time_offsets = viewer.state.time_offsets();
+                let mut changed = false;
+                egui::Grid::new("time_offsets")
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        for (tape, time_offset) in
+                            viewer.state.loaded_tapes.iter().zip(&mut time_offsets)
+                        {
+                            ui.label(tape.path.file_name().unwrap_or_default().to_string_lossy());
+                            let mut milliseconds = time_offset.as_seconds_f64() * 1e3;
+                            if ui
+                                .add(
+                                    egui::DragValue::new(&mut milliseconds)
+                                        .speed(0.01)
+                                        .max_decimals(6)
+                                        .suffix(" ms"),
+                                )
+                                .changed()
+                            {
+                                *time_offset = Duration::seconds_f64(milliseconds / 1e3);
+                                changed = true;
+                            }
+                            if ui
+                                .add_enabled(!time_offset.is_zero(), egui::Button::new("Reset"))
+                                .clicked()
+                            {
+                                *time_offset = Duration::ZERO;
+                                changed = true;
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                if ui
+                    .add_enabled(
+                        viewer.state.correlations.len() > 0,
+                        egui::Button::new("Align Clocks"),
+                    )
+                    .on_hover_text(
+                        "Estimate the offsets from the linked spans, assuming that a request \
+                         span and the span that handled it are centered on each other",
+                    )
+                    .clicked()
+                {
+                    time_offsets = viewer
+                        .state
+                        .correlations
+                        .estimate_time_offsets(&viewer.state.loaded_tapes);
+                    changed = true;
+                }
+
+                if changed {
+                    viewer.state.set_time_offsets(&time_offsets);
+                }
+            });
+    }
+
+    /// Configures the field that links spans of different tapes.
+    fn correlation_ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        ui.horizontal(|ui| {
+            ui.label("Correlation field");
+            let field = self
+                .correlation_field
+                .get_or_insert_with(|| viewer.state.correlations.field.clone());
+            let response = ui.add(egui::TextEdit::singleline(field).desired_width(120.0));
+            if response.lost_focus() {
+                if *field != viewer.state.correlations.field {
+                    viewer.state.correlations =
+                        Correlations::new(&viewer.state.loaded_tapes, field.clone());
+                }
+                self.correlation_field = None;
             }
+            ui.label(format!("{} linked groups", viewer.state.correlations.len()));
         });
     }
 }
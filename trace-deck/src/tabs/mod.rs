@@ -1,5 +1,8 @@
 use std::path::PathBuf;
 
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+
 mod welcome;
 pub use welcome::Welcome;
 
@@ -18,15 +21,44 @@ use callsites::Callsites;
 mod plot_span_duration;
 pub use plot_span_duration::PlotSpanDuration;
 
+mod plot_span_histogram;
+pub use plot_span_histogram::PlotSpanHistogram;
+
+mod plot_event_rate;
+pub use plot_event_rate::PlotEventRate;
+
+mod plot_field_values;
+pub use plot_field_values::PlotFieldValues;
+
+mod search;
+use search::Search;
+
+mod flamegraph;
+use flamegraph::Flamegraph;
+
+mod tape_statistics;
+use tape_statistics::TapeStatistics;
+
+mod flamegraph_diff;
+use flamegraph_diff::FlamegraphDiff;
+
+mod span_tree;
+use span_tree::SpanTree;
+
 mod details;
 pub use details::{Details, SelectedItem};
 
-use crate::state::State;
+use crate::{session::Open, state::State};
 
 pub struct TabViewer<'a> {
     pub state: &'a mut State,
     pub global_time_span: std::ops::Range<time::OffsetDateTime>,
     pub new_tabs: Vec<Tab>,
+    /// Recently opened tapes and sessions, the most recent first.
+    pub recent_files: &'a [PathBuf],
+    /// Whether the session of the last run can be restored.
+    pub has_last_session: bool,
+    pub open: Option<Open>,
 }
 
 impl egui_dock::TabViewer for TabViewer<'_> {
@@ -40,7 +72,15 @@ impl egui_dock::TabViewer for TabViewer<'_> {
             Tab::Events(tape) => egui::Id::new(tape.id()),
             Tab::Timeline(tape) => egui::Id::new(tape.id()),
             Tab::Details(details) => egui::Id::new(details.id()),
+            Tab::Search(search) => egui::Id::new(search.id()),
+            Tab::Flamegraph(flamegraph) => egui::Id::new(flamegraph.id()),
+            Tab::FlamegraphDiff(diff) => diff.id(),
+            Tab::SpanTree(tree) => tree.id(),
+            Tab::Statistics(statistics) => egui::Id::new(statistics.id()),
             Tab::PlotSpanDuration(plot) => plot.id(),
+            Tab::PlotSpanHistogram(plot) => plot.id(),
+            Tab::PlotEventRate(plot) => plot.id(),
+            Tab::PlotFieldValues(plot) => plot.id(),
         }
     }
 
@@ -52,7 +92,15 @@ impl egui_dock::TabViewer for TabViewer<'_> {
             Tab::Events(tape) => tape.title(),
             Tab::Timeline(tape) => tape.title(),
             Tab::Details(details) => details.title(),
+            Tab::Search(search) => search.title(),
+            Tab::Flamegraph(flamegraph) => flamegraph.title(),
+            Tab::FlamegraphDiff(diff) => diff.title(),
+            Tab::SpanTree(tree) => tree.title(),
+            Tab::Statistics(statistics) => statistics.title(),
             Tab::PlotSpanDuration(plot) => plot.title(),
+            Tab::PlotSpanHistogram(plot) => plot.title(),
+            Tab::PlotEventRate(plot) => plot.title(),
+            Tab::PlotFieldValues(plot) => plot.title(),
         }
     }
 
@@ -64,7 +112,15 @@ impl egui_dock::TabViewer for TabViewer<'_> {
             Tab::Events(tape) => tape.ui(ui, self),
             Tab::Timeline(tape) => tape.ui(ui, self),
             Tab::Details(details) => details.ui(ui, self),
+            Tab::Search(search) => search.ui(ui, self),
+            Tab::Flamegraph(flamegraph) => flamegraph.ui(ui, self),
+            Tab::FlamegraphDiff(diff) => diff.ui(ui, self),
+            Tab::SpanTree(tree) => tree.ui(ui, self),
+            Tab::Statistics(statistics) => statistics.ui(ui, self),
             Tab::PlotSpanDuration(plot) => plot.ui(ui, self),
+            Tab::PlotSpanHistogram(plot) => plot.ui(ui, self),
+            Tab::PlotEventRate(plot) => plot.ui(ui, self),
+            Tab::PlotFieldValues(plot) => plot.ui(ui, self),
         }
     }
 
@@ -76,7 +132,15 @@ impl egui_dock::TabViewer for TabViewer<'_> {
             Tab::Events(_) => true,
             Tab::Timeline(_) => true,
             Tab::Details(_) => true,
+            Tab::Search(_) => true,
+            Tab::Flamegraph(_) => true,
+            Tab::FlamegraphDiff(_) => true,
+            Tab::SpanTree(_) => true,
+            Tab::Statistics(_) => true,
             Tab::PlotSpanDuration(_) => true,
+            Tab::PlotSpanHistogram(_) => true,
+            Tab::PlotEventRate(_) => true,
+            Tab::PlotFieldValues(_) => true,
         }
     }
 
@@ -88,11 +152,22 @@ impl egui_dock::TabViewer for TabViewer<'_> {
             Tab::Events(_) => true,
             Tab::Timeline(_) => true,
             Tab::Details(_) => true,
+            Tab::Search(_) => true,
+            Tab::Flamegraph(_) => true,
+            Tab::FlamegraphDiff(_) => true,
+            Tab::SpanTree(_) => true,
+            Tab::Statistics(_) => true,
             Tab::PlotSpanDuration(_) => true,
+            Tab::PlotSpanHistogram(_) => true,
+            Tab::PlotEventRate(_) => true,
+            Tab::PlotFieldValues(_) => true,
         }
     }
 }
 
+/// The tabs are saved with the session, their caches are recalculated after
+/// loading it.
+#[derive(Serialize, Deserialize)]
 pub enum Tab {
     Welcome(Welcome),
     Callsites(Callsites),
@@ -100,10 +175,40 @@ pub enum Tab {
     Events(TapeEvents),
     Timeline(TapeTimeline),
     Details(Details),
+    Search(Search),
+    Flamegraph(Flamegraph),
+    FlamegraphDiff(FlamegraphDiff),
+    SpanTree(SpanTree),
+    Statistics(TapeStatistics),
     PlotSpanDuration(PlotSpanDuration),
+    PlotSpanHistogram(PlotSpanHistogram),
+    PlotEventRate(PlotEventRate),
+    PlotFieldValues(PlotFieldValues),
 }
 
 impl Tab {
+    /// Whether the callsites, spans, and tapes the tab refers to exist, e.g.,
+    /// after restoring a session whose tapes were recorded again.
+    pub fn is_valid(&self, state: &State) -> bool {
+        match self {
+            Tab::Welcome(_) => true,
+            Tab::Callsites(_) => true,
+            Tab::GlobalTimeline(_) => true,
+            Tab::Events(_) => true,
+            Tab::Timeline(_) => true,
+            Tab::Details(_) => true,
+            Tab::Search(_) => true,
+            Tab::Flamegraph(_) => true,
+            Tab::FlamegraphDiff(_) => true,
+            Tab::SpanTree(tree) => tree.is_valid(state),
+            Tab::Statistics(_) => true,
+            Tab::PlotSpanDuration(plot) => plot.is_valid(state),
+            Tab::PlotSpanHistogram(plot) => plot.is_valid(state),
+            Tab::PlotEventRate(plot) => plot.is_valid(state),
+            Tab::PlotFieldValues(plot) => plot.is_valid(state),
+        }
+    }
+
     pub fn welcome() -> Self {
         Self::Welcome(Welcome)
     }
@@ -113,7 +218,7 @@ impl Tab {
     }
 
     pub fn global_timeline() -> Self {
-        Self::GlobalTimeline(GlobalTimeline {})
+        Self::GlobalTimeline(GlobalTimeline::default())
     }
 
     pub fn events<P: Into<PathBuf>>(tape_path: P) -> Self {
@@ -125,7 +230,31 @@ impl Tab {
     }
 
     pub fn details() -> Self {
-        Self::Details(Details)
+        Self::Details(Details::default())
+    }
+
+    pub fn search() -> Self {
+        Self::Search(Search::default())
+    }
+
+    pub fn flamegraph<P: Into<PathBuf>>(tape_path: P) -> Self {
+        Self::Flamegraph(Flamegraph::new(tape_path))
+    }
+
+    pub fn flamegraph_diff(before: PathBuf, after: PathBuf) -> Self {
+        Self::FlamegraphDiff(FlamegraphDiff::new(before, after))
+    }
+
+    pub fn span_tree(tape: PathBuf, span_index: NodeIndex<usize>) -> Self {
+        Self::SpanTree(SpanTree::for_span(tape, span_index))
+    }
+
+    pub fn call_tree(tape: PathBuf) -> Self {
+        Self::SpanTree(SpanTree::for_call_tree(tape))
+    }
+
+    pub fn statistics<P: Into<PathBuf>>(tape_path: P) -> Self {
+        Self::Statistics(TapeStatistics::new(tape_path))
     }
 
     pub fn plot_span_duration(callsite_index: usize, tape: PathBuf) -> Self {
@@ -134,4 +263,19 @@ impl Tab {
             tape,
         })
     }
+
+    pub fn plot_span_histogram(callsite_index: usize, tape: PathBuf) -> Self {
+        Self::PlotSpanHistogram(PlotSpanHistogram::new(callsite_index, tape))
+    }
+
+    pub fn plot_event_rate(callsite_index: usize, tape: PathBuf) -> Self {
+        Self::PlotEventRate(PlotEventRate {
+            callsite_index,
+            tape,
+        })
+    }
+
+    pub fn plot_field_values(callsite_index: usize, field_index: usize) -> Self {
+        Self::PlotFieldValues(PlotFieldValues::new(callsite_index, field_index))
+    }
 }
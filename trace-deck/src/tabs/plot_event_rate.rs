@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use crate::state::State;
+
+use super::TabViewer;
+
+/// Number of bars the visible range is divided into.
+const BINS: i64 = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct PlotEventRate {
+    pub(super) callsite_index: usize,
+    pub(super) tape: PathBuf,
+}
+
+impl PlotEventRate {
+    pub fn id(&self) -> egui::Id {
+        egui::Id::new(("plot_event_rate", &self.tape, self.callsite_index))
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        format!(
+            "Rate {} {}",
+            self.callsite_index,
+            self.tape.file_name().unwrap().to_string_lossy()
+        )
+        .into()
+    }
+
+    /// Whether the tape still has the callsite, e.g., after restoring a
+    /// session whose tapes were recorded again.
+    pub fn is_valid(&self, state: &State) -> bool {
+        state.callsites.is_in_tape(self.callsite_index, &self.tape)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        let callsite = &viewer.state.callsites[self.callsite_index];
+        let (Some(loaded_tape), Some(tape_data)) = (
+            viewer.state.loaded_tapes.get(&self.tape),
+            callsite.tape_data.get(&self.tape),
+        ) else {
+            return;
+        };
+        let local_callsite_index = tape_data.callsite_index;
+
+        let range = viewer.state.focused_range();
+        let start =
+            loaded_tape.global_offset_to_timestamp(*range.start(), viewer.global_time_span.start);
+        let end =
+            loaded_tape.global_offset_to_timestamp(*range.end(), viewer.global_time_span.start);
+        let bin_duration = ((end - start) / BINS).max(1);
+
+        let mut counts = vec![0usize; BINS as usize];
+        for event in loaded_tape
+            .tape
+            .query_events()
+            .callsite(local_callsite_index)
+            .time_range(start..=end)
+            .iter()
+        {
+            let bin = ((event.timestamp - start) / bin_duration).min(BINS - 1);
+            counts[bin as usize] += 1;
+        }
+
+        let bin_start = |bin: usize| {
+            loaded_tape.timestamp_to_global_offset(
+                start + bin as i64 * bin_duration,
+                viewer.global_time_span.start,
+            )
+        };
+        let bin_seconds = bin_duration as f64 / 1e9;
+        let bars = counts
+            .iter()
+            .enumerate()
+            .map(|(bin, count)| {
+                let center = bin_start(bin) + Duration::nanoseconds(bin_duration / 2);
+                egui_plot::Bar::new(center.as_seconds_f64(), *count as f64 / bin_seconds)
+                    .width(bin_seconds)
+            })
+            .collect::<Vec<_>>();
+
+        let id = self.id().with("bars");
+
+        let plot = egui_plot::Plot::new(self.id())
+            .allow_boxed_zoom(false)
+            .allow_scroll(false)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_double_click_reset(false)
+            .x_axis_formatter(|mark, _| format!("{:.3}s", mark.value))
+            .y_axis_formatter(|mark, _| format!("{}/s", mark.value))
+            .show(ui, |ui| {
+                ui.bar_chart(
+                    egui_plot::BarChart::new("event_rate", bars)
+                        .id(id)
+                        .color(callsite.color),
+                )
+            });
+
+        // Clicking a bar zooms the timeline to the events of the bar.
+        if plot.response.clicked() && plot.hovered_plot_item == Some(id) {
+            if let Some(pos) = plot.response.hover_pos() {
+                let plot_pos = plot.transform.value_from_position(pos);
+                let offset = Duration::seconds_f64(plot_pos.x) - bin_start(0);
+                let bin = (offset.whole_nanoseconds() as i64).div_euclid(bin_duration);
+                if (0..BINS).contains(&bin) {
+                    let bin = bin as usize;
+                    viewer.state.timeline_range = bin_start(bin)..=bin_start(bin + 1);
+                }
+            }
+        }
+    }
+}
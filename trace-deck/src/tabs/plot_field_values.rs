@@ -0,0 +1,327 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use crate::{state::State, timeline::TimeRange, utils::AutoColor};
+
+use super::{SelectedItem, TabViewer};
+
+/// Maximum distance in points between the pointer and a point to select it.
+const SELECT_DISTANCE: f32 = 8.0;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn next_id() -> usize {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A numeric field of a callsite in one tape.
+#[derive(Serialize, Deserialize)]
+struct Series {
+    callsite_index: usize,
+    field_index: usize,
+    tape: PathBuf,
+    #[serde(skip)]
+    points: Option<Box<SeriesPoints>>,
+}
+
+/// The points of a series for a timestamp range of its tape.
+struct SeriesPoints {
+    range: (i64, i64),
+    /// The global offset of timestamp zero of the tape in seconds.
+    origin: f64,
+    /// The points with the item they belong to, ordered by time.
+    points: Vec<([f64; 2], SelectedItem)>,
+}
+
+/// Plots numeric values of event or span fields against time.
+#[derive(Serialize, Deserialize)]
+pub struct PlotFieldValues {
+    #[serde(skip, default = "next_id")]
+    id: usize,
+    series: Vec<Series>,
+    /// Callsite and field selected for adding a series.
+    new_series: Option<(usize, usize)>,
+    /// Whether to add the selected callsite and field in the next frame.
+    add_new_series: bool,
+    /// The timeline range the plot bounds were last set to.
+    synced_range: Option<TimeRange>,
+}
+
+impl PlotFieldValues {
+    pub fn new(callsite_index: usize, field_index: usize) -> Self {
+        Self {
+            id: next_id(),
+            series: Vec::new(),
+            new_series: Some((callsite_index, field_index)),
+            add_new_series: true,
+            synced_range: None,
+        }
+    }
+
+    pub fn id(&self) -> egui::Id {
+        egui::Id::new(("plot_field_values", self.id))
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        "Field Values".into()
+    }
+
+    /// Adds a series for each tape that contains the callsite.
+    fn add_series(&mut self, viewer: &TabViewer, callsite_index: usize, field_index: usize) {
+        let mut tapes = viewer.state.callsites[callsite_index]
+            .tape_data
+            .keys()
+            .collect::<Vec<_>>();
+        tapes.sort();
+        for tape in tapes {
+            let exists = self.series.iter().any(|series| {
+                series.callsite_index == callsite_index
+                    && series.field_index == field_index
+                    && &series.tape == tape
+            });
+            if !exists {
+                self.series.push(Series {
+                    callsite_index,
+                    field_index,
+                    tape: tape.clone(),
+                    points: None,
+                });
+            }
+        }
+    }
+
+    fn series_name(&self, viewer: &TabViewer, series: &Series) -> String {
+        let callsite = &viewer.state.callsites[series.callsite_index].inner;
+        format!(
+            "{}.{} ({})",
+            callsite.name(),
+            callsite.fields()[series.field_index],
+            series.tape.file_name().unwrap().to_string_lossy()
+        )
+    }
+
+    fn series_ui(&mut self, ui: &mut egui::Ui, viewer: &TabViewer) {
+        let callsites = &viewer.state.callsites;
+        ui.horizontal_wrapped(|ui| {
+            let selected_text = match self.new_series {
+                Some((callsite_index, field_index)) => {
+                    let callsite = &callsites[callsite_index].inner;
+                    format!("{}.{}", callsite.name(), callsite.fields()[field_index])
+                }
+                None => "Select field".to_string(),
+            };
+            egui::ComboBox::from_id_salt(("new_series", self.id))
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    for (callsite_index, callsite) in callsites.iter().enumerate() {
+                        for (field_index, field) in callsite.inner.fields().iter().enumerate() {
+                            ui.selectable_value(
+                                &mut self.new_series,
+                                Some((callsite_index, field_index)),
+                                format!("{}.{}", callsite.inner.name(), field),
+                            );
+                        }
+                    }
+                });
+            if self.new_series.is_some() && ui.button("Add").clicked() {
+                self.add_new_series = true;
+            }
+
+            ui.separator();
+
+            let mut removed = None;
+            for (index, series) in self.series.iter().enumerate() {
+                if ui
+                    .button(format!("{} ✖", self.series_name(viewer, series)))
+                    .clicked()
+                {
+                    removed = Some(index);
+                }
+            }
+            if let Some(index) = removed {
+                self.series.remove(index);
+            }
+        });
+    }
+
+    /// Whether the tapes still have the callsites and fields of all series,
+    /// e.g., after restoring a session whose tapes were recorded again.
+    pub fn is_valid(&self, state: &State) -> bool {
+        let callsites = &state.callsites;
+        self.series.iter().all(|series| {
+            callsites.is_in_tape(series.callsite_index, &series.tape)
+                && series.field_index < callsites[series.callsite_index].inner.fields().len()
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        if let Some((callsite_index, field_index)) = self.new_series {
+            if std::mem::take(&mut self.add_new_series) {
+                self.add_series(viewer, callsite_index, field_index);
+            }
+        }
+        self.series_ui(ui, viewer);
+
+        let range = viewer.state.timeline_range.clone();
+        let x_range = range.start().as_seconds_f64()..=range.end().as_seconds_f64();
+        // Only values within the selected range are plotted.
+        let focused_range = viewer.state.focused_range();
+
+        for series in &mut self.series {
+            let (Some(loaded_tape), Some(tape_data)) = (
+                viewer.state.loaded_tapes.get(&series.tape),
+                viewer.state.callsites[series.callsite_index]
+                    .tape_data
+                    .get(&series.tape),
+            ) else {
+                series.points = None;
+                continue;
+            };
+            let local_callsite_index = tape_data.callsite_index;
+            let tape = &loaded_tape.tape;
+
+            let start = loaded_tape
+                .global_offset_to_timestamp(*focused_range.start(), viewer.global_time_span.start);
+            let end = loaded_tape
+                .global_offset_to_timestamp(*focused_range.end(), viewer.global_time_span.start);
+            let origin = loaded_tape
+                .global_offset(viewer.global_time_span.start)
+                .as_seconds_f64();
+            let up_to_date = series
+                .points
+                .as_ref()
+                .is_some_and(|cache| cache.range == (start, end) && cache.origin == origin);
+            if up_to_date {
+                continue;
+            }
+
+            let x = |timestamp| origin + timestamp as f64 / 1e9;
+            let mut points = Vec::new();
+            if tape.callsites()[local_callsite_index].kind().is_span() {
+                for (span_index, span) in tape
+                    .query_spans()
+                    .callsite(local_callsite_index)
+                    .time_range(start..=end)
+                    .iter()
+                {
+                    if let Some(value) = span.values[series.field_index].as_f64() {
+                        points.push((
+                            [x(span.opened), value],
+                            SelectedItem::Span {
+                                tape: series.tape.clone(),
+                                span_index,
+                            },
+                        ));
+                    }
+                }
+                points.sort_by(|(a, _), (b, _)| a[0].total_cmp(&b[0]));
+            } else {
+                for (event_index, event) in tape
+                    .query_events()
+                    .callsite(local_callsite_index)
+                    .time_range(start..=end)
+                    .iter_indexed()
+                {
+                    if let Some(value) = event.values[series.field_index].as_f64() {
+                        points.push((
+                            [x(event.timestamp), value],
+                            SelectedItem::Event {
+                                tape: series.tape.clone(),
+                                event_index,
+                            },
+                        ));
+                    }
+                }
+            }
+            series.points = Some(Box::new(SeriesPoints {
+                range: (start, end),
+                origin,
+                points,
+            }));
+        }
+        // The points of all series with the item they belong to.
+        let points = self
+            .series
+            .iter()
+            .map(|series| match &series.points {
+                Some(cache) => &cache.points[..],
+                None => &[],
+            })
+            .collect::<Vec<_>>();
+
+        let synced = self.synced_range.as_ref() == Some(&range);
+        let plot = egui_plot::Plot::new(self.id())
+            .allow_boxed_zoom(false)
+            .allow_drag([true, false])
+            .allow_zoom([true, false])
+            .allow_scroll([true, false])
+            .allow_double_click_reset(false)
+            .x_axis_formatter(|mark, _| format!("{:.3}s", mark.value))
+            .legend(egui_plot::Legend::default())
+            .show(ui, |plot_ui| {
+                if !synced {
+                    plot_ui.set_plot_bounds_x(x_range.clone());
+                }
+                plot_ui.set_auto_bounds([false, true]);
+
+                let mut colors = AutoColor::default();
+                for (series, series_points) in self.series.iter().zip(points.iter().copied()) {
+                    let name = self.series_name(viewer, series);
+                    let color = colors.next().expect("color");
+                    let coordinates = series_points
+                        .iter()
+                        .map(|(point, _)| *point)
+                        .collect::<Vec<_>>();
+                    plot_ui
+                        .line(egui_plot::Line::new(name.clone(), coordinates.clone()).color(color));
+                    plot_ui.points(
+                        egui_plot::Points::new(name.clone(), coordinates)
+                            .id(egui::Id::new((name, "points")))
+                            .color(color)
+                            .radius(2.0),
+                    );
+                }
+            });
+
+        // Dragging or zooming the plot moves the timelines.
+        let interacted = plot.response.dragged()
+            || (plot.response.hovered()
+                && ui
+                    .input(|i| i.smooth_scroll_delta != egui::Vec2::ZERO || i.zoom_delta() != 1.0));
+        let bounds = plot.transform.bounds();
+        if synced
+            && interacted
+            && (bounds.min()[0], bounds.max()[0]) != (*x_range.start(), *x_range.end())
+        {
+            viewer.state.timeline_range =
+                Duration::seconds_f64(bounds.min()[0])..=Duration::seconds_f64(bounds.max()[0]);
+        }
+        self.synced_range = Some(viewer.state.timeline_range.clone());
+
+        if plot.response.clicked() {
+            if let Some(pos) = plot.response.hover_pos() {
+                let closest = points
+                    .iter()
+                    .copied()
+                    .flatten()
+                    .map(|(point, item)| {
+                        let position = plot
+                            .transform
+                            .position_from_point(&egui_plot::PlotPoint::new(point[0], point[1]));
+                        (position.distance(pos), item)
+                    })
+                    .min_by(|(a, _), (b, _)| a.total_cmp(b));
+                if let Some((distance, item)) = closest {
+                    if distance <= SELECT_DISTANCE {
+                        viewer.state.selected_item = Some(item.clone());
+                    }
+                }
+            }
+        }
+    }
+}
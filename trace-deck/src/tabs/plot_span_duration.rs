@@ -1,7 +1,12 @@
 use std::path::PathBuf;
 
+use serde::{Deserialize, Serialize};
+
+use crate::state::State;
+
 use super::{SelectedItem, TabViewer};
 
+#[derive(Serialize, Deserialize)]
 pub struct PlotSpanDuration {
     pub(super) callsite_index: usize,
     pub(super) tape: PathBuf,
@@ -21,51 +26,39 @@ impl PlotSpanDuration {
         .into()
     }
 
+    /// Whether the tape still has the callsite, e.g., after restoring a
+    /// session whose tapes were recorded again.
+    pub fn is_valid(&self, state: &State) -> bool {
+        state.callsites.is_in_tape(self.callsite_index, &self.tape)
+    }
+
     pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        let range = viewer.state.focused_range();
         let callsite = &mut viewer.state.callsites[self.callsite_index];
         let loaded_tape = viewer.state.loaded_tapes.get(&self.tape).unwrap();
         let local_callsite_index = callsite.tape_data[&self.tape].callsite_index;
 
-        let start = loaded_tape.global_offset_to_timestamp(
-            *viewer.state.timeline_range.start(),
-            viewer.global_time_span.start,
-        );
-        let end = loaded_tape.global_offset_to_timestamp(
-            *viewer.state.timeline_range.end(),
-            viewer.global_time_span.start,
-        );
+        let start =
+            loaded_tape.global_offset_to_timestamp(*range.start(), viewer.global_time_span.start);
+        let end =
+            loaded_tape.global_offset_to_timestamp(*range.end(), viewer.global_time_span.start);
 
         let spans = loaded_tape.tape.spans();
 
-        let relevant_root_spans = loaded_tape
+        // Nested calls of a recursive callsite are part of the outermost one.
+        let mut found_spans = loaded_tape
             .tape
-            .root_spans()
+            .query_spans()
+            .callsite(local_callsite_index)
+            .time_range(start..=end)
             .iter()
-            .filter(|span_id| {
-                let span = spans.node_weight(**span_id).unwrap();
-                span.opened <= end && span.closed >= start
+            .filter(|(index, _)| {
+                !loaded_tape
+                    .tape
+                    .ancestors(*index)
+                    .any(|ancestor| spans[ancestor].callsite_index == local_callsite_index)
             })
-            .copied();
-
-        let mut found_spans = vec![];
-
-        petgraph::visit::depth_first_search(spans, relevant_root_spans, |event| {
-            if let petgraph::visit::DfsEvent::Discover(span_id, _) = event {
-                let span = spans.node_weight(span_id).unwrap();
-                if span.closed >= start && span.opened <= end {
-                    if span.callsite_index == local_callsite_index {
-                        found_spans.push((span_id, span));
-                        petgraph::visit::Control::<()>::Prune
-                    } else {
-                        petgraph::visit::Control::Continue
-                    }
-                } else {
-                    petgraph::visit::Control::Prune
-                }
-            } else {
-                petgraph::visit::Control::Continue
-            }
-        });
+            .collect::<Vec<_>>();
         found_spans.sort_unstable_by_key(|(_, span)| span.opened);
 
         let bars = found_spans
@@ -0,0 +1,257 @@
+use std::path::PathBuf;
+
+use egui::Align;
+use egui_extras::{Column, TableBuilder};
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+use time::Duration;
+use tracing_tape_parser::percentile;
+
+use crate::state::State;
+
+use super::{SelectedItem, TabViewer};
+
+/// Number of buckets between two powers of two. Buckets are narrow enough for
+/// distributions with several modes to show separate peaks.
+const BUCKETS_PER_DOUBLING: f64 = 4.0;
+
+/// The bucket of a duration on a log scale.
+fn bucket(duration: i64) -> i64 {
+    ((duration.max(1) as f64).log2() * BUCKETS_PER_DOUBLING).floor() as i64
+}
+
+/// The x coordinate of the start of a bucket, i.e., the binary logarithm of
+/// its smallest duration in nanoseconds.
+fn bucket_x(bucket: i64) -> f64 {
+    bucket as f64 / BUCKETS_PER_DOUBLING
+}
+
+/// Counts the spans of each non-empty bucket, given the buckets of the spans
+/// in sorted order.
+fn bins(sorted_buckets: impl IntoIterator<Item = i64>) -> Vec<(i64, usize)> {
+    let mut bins: Vec<(i64, usize)> = vec![];
+    for bucket in sorted_buckets {
+        match bins.last_mut() {
+            Some((last, count)) if *last == bucket => *count += 1,
+            _ => bins.push((bucket, 1)),
+        }
+    }
+    bins
+}
+
+/// Plots the number of spans of a callsite by their duration on a log scale
+/// within the selected or visible range. Clicking a bucket lists its spans and
+/// highlights them on the timeline.
+#[derive(Serialize, Deserialize)]
+pub struct PlotSpanHistogram {
+    callsite_index: usize,
+    tape: PathBuf,
+    #[serde(skip)]
+    selected_bucket: Option<i64>,
+}
+
+impl PlotSpanHistogram {
+    pub fn new(callsite_index: usize, tape: PathBuf) -> Self {
+        Self {
+            callsite_index,
+            tape,
+            selected_bucket: None,
+        }
+    }
+
+    pub fn id(&self) -> egui::Id {
+        egui::Id::new(("plot_span_histogram", &self.tape, self.callsite_index))
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        format!(
+            "Histogram {} {}",
+            self.callsite_index,
+            self.tape.file_name().unwrap().to_string_lossy()
+        )
+        .into()
+    }
+
+    /// Whether the tape still has the callsite, e.g., after restoring a
+    /// session whose tapes were recorded again.
+    pub fn is_valid(&self, state: &State) -> bool {
+        state.callsites.is_in_tape(self.callsite_index, &self.tape)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        let range = viewer.state.focused_range();
+        let callsite = &viewer.state.callsites[self.callsite_index];
+        let Some(loaded_tape) = viewer.state.loaded_tapes.get(&self.tape) else {
+            return;
+        };
+        let Some(data) = callsite.tape_data.get(&self.tape) else {
+            return;
+        };
+        let global_start = viewer.global_time_span.start;
+
+        let start = loaded_tape.global_offset_to_timestamp(*range.start(), global_start);
+        let end = loaded_tape.global_offset_to_timestamp(*range.end(), global_start);
+
+        let mut spans = loaded_tape
+            .tape
+            .query_spans()
+            .callsite(data.callsite_index)
+            .time_range(start..=end)
+            .iter()
+            .map(|(index, span)| (bucket(span.closed - span.opened), index, span))
+            .collect::<Vec<_>>();
+        spans.sort_unstable_by_key(|(bucket, _, span)| (*bucket, span.opened));
+
+        let bars = bins(spans.iter().map(|(bucket, _, _)| *bucket))
+            .into_iter()
+            .map(|(bucket, count)| {
+                let color = if self.selected_bucket == Some(bucket) {
+                    ui.visuals().selection.bg_fill
+                } else {
+                    callsite.color
+                };
+                egui_plot::Bar::new(bucket_x(bucket) + 0.5 / BUCKETS_PER_DOUBLING, count as f64)
+                    .width(1.0 / BUCKETS_PER_DOUBLING)
+                    .fill(color)
+            })
+            .collect::<Vec<_>>();
+
+        let mut durations = spans
+            .iter()
+            .map(|(_, _, span)| span.closed - span.opened)
+            .collect::<Vec<_>>();
+        durations.sort_unstable();
+        let percentiles = [("p50", 0.5), ("p99", 0.99)]
+            .map(|(name, q)| (name, (percentile(&durations, q).max(1) as f64).log2()));
+
+        let selected = spans
+            .iter()
+            .filter(|(bucket, _, _)| self.selected_bucket == Some(*bucket))
+            .map(|(_, index, span)| (*index, span.opened, span.closed))
+            .collect::<Vec<_>>();
+
+        let mut clicked_span = None;
+        egui::TopBottomPanel::bottom(self.id().with("spans"))
+            .resizable(true)
+            .show_animated_inside(ui, self.selected_bucket.is_some(), |ui| {
+                ui.label(format!("{} spans", selected.len()));
+                TableBuilder::new(ui)
+                    .auto_shrink(false)
+                    .striped(true)
+                    .sense(egui::Sense::click())
+                    .column(Column::auto())
+                    .column(Column::remainder())
+                    .cell_layout(egui::Layout::left_to_right(Align::Center))
+                    .header(20.0, |mut header| {
+                        header.col(|ui| {
+                            ui.strong("Time");
+                        });
+                        header.col(|ui| {
+                            ui.strong("Duration");
+                        });
+                    })
+                    .body(|body| {
+                        body.rows(18.0, selected.len(), |mut row| {
+                            let (index, opened, closed) = selected[row.index()];
+                            row.set_selected(matches!(
+                                &viewer.state.selected_item,
+                                Some(SelectedItem::Span { tape, span_index })
+                                    if *tape == self.tape && *span_index == index
+                            ));
+                            row.col(|ui| {
+                                let offset =
+                                    loaded_tape.timestamp_to_global_offset(opened, global_start);
+                                ui.label(format!("{:.6}", offset));
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.1}", Duration::nanoseconds(closed - opened)));
+                            });
+                            if row.response().clicked() {
+                                clicked_span = Some((index, opened, closed));
+                            }
+                        });
+                    });
+            });
+
+        let id = egui::Id::new("histogram");
+        let plot = egui_plot::Plot::new(self.id().with("plot"))
+            .allow_boxed_zoom(false)
+            .allow_scroll(false)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_double_click_reset(false)
+            .x_axis_formatter(|mark, _| {
+                format!("{}", Duration::nanoseconds(2f64.powf(mark.value) as i64))
+            })
+            .label_formatter(|_, point| {
+                let bucket = bucket_x((point.x * BUCKETS_PER_DOUBLING).floor() as i64);
+                format!(
+                    "{:.1} to {:.1}",
+                    Duration::nanoseconds(2f64.powf(bucket) as i64),
+                    Duration::nanoseconds(2f64.powf(bucket + 1.0 / BUCKETS_PER_DOUBLING) as i64),
+                )
+            })
+            .show(ui, |ui| {
+                ui.bar_chart(egui_plot::BarChart::new("histogram", bars).id(id));
+                if !durations.is_empty() {
+                    for (name, x) in percentiles {
+                        ui.vline(egui_plot::VLine::new(name, x).color(egui::Color32::GRAY));
+                    }
+                }
+            });
+
+        if plot.response.clicked() {
+            let bucket = plot
+                .response
+                .hover_pos()
+                .filter(|_| plot.hovered_plot_item == Some(id))
+                .map(|pos| {
+                    let x = plot.transform.value_from_position(pos).x;
+                    (x * BUCKETS_PER_DOUBLING).floor() as i64
+                });
+            self.selected_bucket = if bucket == self.selected_bucket {
+                None
+            } else {
+                bucket
+            };
+
+            let highlighted = spans
+                .iter()
+                .filter(|(bucket, _, _)| self.selected_bucket == Some(*bucket))
+                .map(|(_, index, _)| *index)
+                .collect::<ahash::HashSet<NodeIndex<usize>>>();
+            if highlighted.is_empty() {
+                viewer.state.highlighted_spans.remove(&self.tape);
+            } else {
+                viewer
+                    .state
+                    .highlighted_spans
+                    .insert(self.tape.clone(), highlighted);
+            }
+        }
+
+        if let Some((span_index, opened, closed)) = clicked_span {
+            let range = loaded_tape.timestamp_to_global_offset(opened, global_start)
+                ..=loaded_tape.timestamp_to_global_offset(closed, global_start);
+            viewer.state.selected_item = Some(SelectedItem::Span {
+                tape: self.tape.clone(),
+                span_index,
+            });
+            viewer.state.center_timeline(range);
+        }
+    }
+}
+
+#[test]
+fn test_bins() {
+    let durations = [0, 1, 2, 3, 3, 4, 1000, 1023, 1024];
+    let bins = bins(durations.map(bucket));
+    assert_eq!(bins, [(0, 2), (4, 1), (6, 2), (8, 1), (39, 2), (40, 1)]);
+
+    // Each bucket covers a quarter of a doubling.
+    for duration in &durations[1..] {
+        let bucket = bucket(*duration);
+        let duration = (*duration as f64).log2();
+        assert!(bucket_x(bucket) <= duration && duration < bucket_x(bucket + 1));
+    }
+}
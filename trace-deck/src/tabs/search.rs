@@ -0,0 +1,478 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crossbeam_channel::{Receiver, TryRecvError};
+use egui::Align;
+use egui_extras::{Column, TableBuilder};
+use serde::{Deserialize, Serialize};
+use time::Duration;
+use tracing_tape_parser::{query::FieldPredicate, Callsite, Tape, Value};
+
+use crate::utils;
+
+use super::{SelectedItem, TabViewer};
+
+/// At most this many hits are shown, the first ones in time.
+const MAX_HITS: usize = 10_000;
+
+enum Pattern {
+    /// Case-insensitive substring.
+    Text(String),
+    Regex(regex::Regex),
+    Field(FieldPredicate),
+}
+
+impl Pattern {
+    fn parse(query: &str, regex: bool) -> Result<Self, String> {
+        // Predicates are only recognized if the field is an identifier, such
+        // that free text containing an operator is still searched as text.
+        if let Ok(predicate) = query.parse::<FieldPredicate>() {
+            let is_identifier = predicate
+                .field
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+            if is_identifier {
+                return Ok(Pattern::Field(predicate));
+            }
+        }
+
+        if regex {
+            regex::Regex::new(query)
+                .map(Pattern::Regex)
+                .map_err(|error| error.to_string())
+        } else {
+            Ok(Pattern::Text(query.to_lowercase()))
+        }
+    }
+
+    fn matches_text(&self, text: &str) -> bool {
+        match self {
+            Pattern::Text(pattern) => text.to_lowercase().contains(pattern),
+            Pattern::Regex(regex) => regex.is_match(text),
+            Pattern::Field(_) => false,
+        }
+    }
+
+    /// Matches the callsite name and the values of a span or event.
+    fn matches(&self, callsite: &Callsite, values: &[Value]) -> bool {
+        match self {
+            Pattern::Field(predicate) => predicate.matches(callsite, values),
+            _ => {
+                self.matches_text(callsite.name())
+                    || values.iter().any(|value| {
+                        !matches!(value, Value::Empty) && self.matches_text(&value.to_string())
+                    })
+            }
+        }
+    }
+}
+
+/// Formats the values of a span or event, the message is shown without its
+/// field name.
+fn describe(callsite: &Callsite, values: &[Value]) -> String {
+    let mut text = callsite.name().to_string();
+    for (name, value) in callsite.fields().iter().zip(values.iter()) {
+        if matches!(value, Value::Empty) {
+            continue;
+        }
+        if &**name == "message" {
+            text.push_str(&format!(": {value}"));
+        } else {
+            text.push_str(&format!(" {name}={value}"));
+        }
+    }
+    text
+}
+
+struct Hit {
+    tape: PathBuf,
+    item: SelectedItem,
+    /// Time range of the span or the timestamp of the event.
+    timestamps: (i64, i64),
+    text: String,
+}
+
+/// Finds the first hits in time, at most [MAX_HITS] of them, and whether there
+/// were more.
+fn find_hits(tapes: Vec<(PathBuf, Arc<Tape>, Duration)>, pattern: Pattern) -> (Vec<Hit>, bool) {
+    let mut hits = Vec::new();
+    for (path, tape, global_offset) in &tapes {
+        let callsites = tape.callsites();
+
+        // Events and spans are both visited in time order, so the first hits
+        // of the tape are among the first ones of either.
+        let events = tape
+            .events()
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| pattern.matches(&callsites[event.callsite_index], &event.values))
+            .take(MAX_HITS + 1)
+            .map(|(event_index, event)| {
+                let item = SelectedItem::Event {
+                    tape: path.clone(),
+                    event_index,
+                };
+                (item, (event.timestamp, event.timestamp))
+            });
+        let spans = tape
+            .spans_in_range(i64::MIN..=i64::MAX)
+            .filter(|span_index| {
+                let span = &tape.spans()[*span_index];
+                pattern.matches(&callsites[span.callsite_index], &span.values)
+            })
+            .take(MAX_HITS + 1)
+            .map(|span_index| {
+                let span = &tape.spans()[span_index];
+                let item = SelectedItem::Span {
+                    tape: path.clone(),
+                    span_index,
+                };
+                (item, (span.opened, span.closed))
+            });
+
+        let mut tape_hits = events.chain(spans).collect::<Vec<_>>();
+        tape_hits.sort_by_key(|(_, timestamps)| timestamps.0);
+        tape_hits.truncate(MAX_HITS + 1);
+        hits.extend(tape_hits.into_iter().map(|(item, timestamps)| {
+            let offset = *global_offset + Duration::nanoseconds(timestamps.0);
+            (offset, path, tape, item, timestamps)
+        }));
+    }
+
+    hits.sort_by_key(|(offset, ..)| *offset);
+    let truncated = hits.len() > MAX_HITS;
+    hits.truncate(MAX_HITS);
+
+    // Only the hits that are shown are formatted.
+    let hits = hits
+        .into_iter()
+        .map(|(_, path, tape, item, timestamps)| {
+            let callsites = tape.callsites();
+            let text = match &item {
+                SelectedItem::Event { event_index, .. } => {
+                    let event = &tape.events()[*event_index];
+                    let callsite = &callsites[event.callsite_index];
+                    format!("{} {}", callsite.level(), describe(callsite, &event.values))
+                }
+                SelectedItem::Span { span_index, .. } => {
+                    let span = &tape.spans()[*span_index];
+                    describe(&callsites[span.callsite_index], &span.values)
+                }
+                SelectedItem::Callsite(_) => unreachable!(),
+            };
+            Hit {
+                tape: path.clone(),
+                item,
+                timestamps,
+                text,
+            }
+        })
+        .collect();
+    (hits, truncated)
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Search {
+    query: String,
+    regex: bool,
+    #[serde(skip)]
+    error: Option<String>,
+    #[serde(skip)]
+    hits: Vec<Hit>,
+    #[serde(skip)]
+    truncated: bool,
+    #[serde(skip)]
+    current: Option<usize>,
+    #[serde(skip)]
+    pending: Option<Receiver<(Vec<Hit>, bool)>>,
+}
+
+impl Search {
+    pub fn id(&self) -> &str {
+        "search"
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        "Search".into()
+    }
+
+    /// Starts searching the loaded tapes in the background, a search that is
+    /// still running is discarded.
+    fn search(&mut self, ctx: &egui::Context, viewer: &TabViewer) {
+        self.hits.clear();
+        self.current = None;
+        self.truncated = false;
+        self.error = None;
+        self.pending = None;
+        if self.query.trim().is_empty() {
+            return;
+        }
+
+        let pattern = match Pattern::parse(self.query.trim(), self.regex) {
+            Ok(pattern) => pattern,
+            Err(error) => {
+                self.error = Some(error);
+                return;
+            }
+        };
+
+        let global_start = viewer.global_time_span.start;
+        let tapes = viewer
+            .state
+            .loaded_tapes
+            .iter()
+            .map(|loaded_tape| {
+                (
+                    loaded_tape.path.clone(),
+                    loaded_tape.tape.clone(),
+                    loaded_tape.global_offset(global_start),
+                )
+            })
+            .collect();
+        self.pending = Some(utils::spawn(ctx, move || find_hits(tapes, pattern)));
+    }
+
+    /// Selects a hit and moves the timeline to it.
+    fn go_to(&mut self, viewer: &mut TabViewer, index: usize) {
+        let Some(hit) = self.hits.get(index) else {
+            return;
+        };
+        let Some(loaded_tape) = viewer.state.loaded_tapes.get(&hit.tape) else {
+            return;
+        };
+
+        let global_start = viewer.global_time_span.start;
+        let range = loaded_tape.timestamp_to_global_offset(hit.timestamps.0, global_start)
+            ..=loaded_tape.timestamp_to_global_offset(hit.timestamps.1, global_start);
+        viewer.state.center_timeline(range);
+        viewer.state.selected_item = Some(hit.item.clone());
+        self.current = Some(index);
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.query)
+                    .hint_text("text, regex, or field=value"),
+            );
+            ui.checkbox(&mut self.regex, "Regex");
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Search").clicked() || submitted {
+                self.search(ui.ctx(), viewer);
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+        });
+
+        if let Some(receiver) = &self.pending {
+            match receiver.try_recv() {
+                Ok((hits, truncated)) => {
+                    self.hits = hits;
+                    self.truncated = truncated;
+                    self.pending = None;
+                    self.go_to(viewer, 0);
+                }
+                Err(TryRecvError::Disconnected) => self.pending = None,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        ui.horizontal(|ui| {
+            let count = self.hits.len();
+            ui.add_enabled_ui(count > 0, |ui| {
+                if ui.button("⏶ Previous").clicked() {
+                    let index = self.current.map_or(count - 1, |i| (i + count - 1) % count);
+                    self.go_to(viewer, index);
+                }
+                if ui.button("Next ⏷").clicked() {
+                    let index = self.current.map_or(0, |i| (i + 1) % count);
+                    self.go_to(viewer, index);
+                }
+            });
+            match self.current {
+                Some(current) => ui.label(format!("{} of {}", current + 1, count)),
+                None => ui.label(format!("{} results", count)),
+            };
+            if self.truncated {
+                ui.label(format!("(only the first {MAX_HITS} are shown)"));
+            }
+        });
+
+        let global_start = viewer.global_time_span.start;
+        let mut clicked = None;
+        TableBuilder::new(ui)
+            .auto_shrink(false)
+            .striped(true)
+            .sense(egui::Sense::click())
+            .column(Column::auto())
+            .column(Column::auto())
+            .column(Column::remainder())
+            .cell_layout(egui::Layout::left_to_right(Align::Center))
+            .header(20.0, |mut header| {
+                header.col(|ui| {
+                    ui.strong("Time");
+                });
+                header.col(|ui| {
+                    ui.strong("Tape");
+                });
+                header.col(|ui| {
+                    ui.strong("Match");
+                });
+            })
+            .body(|body| {
+                body.rows(18.0, self.hits.len(), |mut row| {
+                    let index = row.index();
+                    let hit = &self.hits[index];
+                    row.set_selected(self.current == Some(index));
+                    row.col(|ui| {
+                        if let Some(loaded_tape) = viewer.state.loaded_tapes.get(&hit.tape) {
+                            let offset = loaded_tape
+                                .timestamp_to_global_offset(hit.timestamps.0, global_start);
+                            ui.label(format!("{:.6}", offset));
+                        }
+                    });
+                    row.col(|ui| {
+                        ui.label(hit.tape.file_name().unwrap_or_default().to_string_lossy());
+                    });
+                    row.col(|ui| {
+                        ui.label(&hit.text);
+                    });
+                    if row.response().clicked() {
+                        clicked = Some(index);
+                    }
+                });
+            });
+
+        if let Some(index) = clicked {
+            self.go_to(viewer, index);
+        }
+    }
+}
+
+#[test]
+fn test_pattern_parse() {
+    assert!(matches!(
+        Pattern::parse("n=2", false),
+        Ok(Pattern::Field(_))
+    ));
+    assert!(matches!(
+        Pattern::parse("request.id >= 5", false),
+        Ok(Pattern::Field(_))
+    ));
+    // Free text containing an operator is still searched as text.
+    let Ok(Pattern::Text(text)) = Pattern::parse("Retry after 5 s == 1", false) else {
+        panic!("expected a text pattern");
+    };
+    assert_eq!(text, "retry after 5 s == 1");
+    assert!(matches!(
+        Pattern::parse("wor+k", true),
+        Ok(Pattern::Regex(_))
+    ));
+    assert!(Pattern::parse("(", true).is_err());
+}
+
+#[test]
+fn test_find_hits() {
+    use tracing_tape_parser::{Event, Metadata, TapeBuilder};
+
+    use crate::testing::{callsite, span, state};
+
+    let mut a = TapeBuilder::new(16, 0);
+    let work = a.callsite(callsite("work", &["n"]));
+    let first = a.span(None, span(work, 0..10, &[Value::I64(1)]));
+    let second = a.span(None, span(work, 20..30, &[Value::I64(2)]));
+
+    // The second tape starts 5ns after the first one.
+    let mut b = TapeBuilder::new(16, 5);
+    let tick = b.callsite(Callsite::Event(Metadata {
+        level: tracing::Level::INFO,
+        name: "tick".into(),
+        target: "test".into(),
+        module_path: "test".into(),
+        file: None,
+        line: None,
+        fields: Arc::new(["message".into()]),
+    }));
+    let other = b.callsite(callsite("other", &["n"]));
+    b.event(Event {
+        timestamp: 0,
+        callsite_index: tick,
+        thread_id: 1,
+        span: None,
+        values: Arc::new([Value::String("work done".into())]),
+    });
+    let third = b.span(None, span(other, 10..20, &[Value::I64(3)]));
+
+    let loaded = state(vec![("a.tape", a.finish()), ("b.tape", b.finish())]);
+    let tapes = || {
+        loaded
+            .loaded_tapes
+            .iter()
+            .map(|loaded_tape| {
+                (
+                    loaded_tape.path.clone(),
+                    loaded_tape.tape.clone(),
+                    loaded_tape.global_offset(time::OffsetDateTime::UNIX_EPOCH),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let span_hit = |tape: &str, span_index| SelectedItem::Span {
+        tape: tape.into(),
+        span_index,
+    };
+
+    // Hits of both tapes are ordered by their global time.
+    let (hits, truncated) = find_hits(tapes(), Pattern::parse("work", false).unwrap());
+    assert!(!truncated);
+    let items = hits.iter().map(|hit| &hit.item).collect::<Vec<_>>();
+    assert_eq!(
+        items,
+        [
+            &span_hit("a.tape", first),
+            &SelectedItem::Event {
+                tape: "b.tape".into(),
+                event_index: 0,
+            },
+            &span_hit("a.tape", second),
+        ]
+    );
+    let texts = hits.iter().map(|hit| hit.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(texts, ["work n=1", "INFO tick: work done", "work n=2"]);
+    assert_eq!(hits[2].timestamps, (20, 30));
+
+    // Field predicates only match the values of the field.
+    let (hits, _) = find_hits(tapes(), Pattern::parse("n >= 2", false).unwrap());
+    let items = hits.iter().map(|hit| &hit.item).collect::<Vec<_>>();
+    assert_eq!(
+        items,
+        [&span_hit("b.tape", third), &span_hit("a.tape", second)]
+    );
+
+    let mut many = TapeBuilder::new(16, 0);
+    let work = many.callsite(callsite("work", &[]));
+    for start in 0..=MAX_HITS as i64 {
+        many.span(None, span(work, start..start + 1, &[]));
+    }
+    let many = state(vec![("many.tape", many.finish())]);
+    let tapes = many
+        .loaded_tapes
+        .iter()
+        .map(|loaded_tape| {
+            (
+                loaded_tape.path.clone(),
+                loaded_tape.tape.clone(),
+                Duration::ZERO,
+            )
+        })
+        .collect();
+    let (hits, truncated) = find_hits(tapes, Pattern::parse("work", false).unwrap());
+    assert!(truncated);
+    assert_eq!(hits.len(), MAX_HITS);
+    assert_eq!(hits[0].timestamps, (0, 1));
+    assert_eq!(hits[MAX_HITS - 1].timestamps.0, MAX_HITS as i64 - 1);
+}
@@ -0,0 +1,550 @@
+use std::{cmp::Ordering, ops::Range, path::PathBuf};
+
+use ahash::HashSet;
+use crossbeam_channel::{Receiver, TryRecvError};
+use egui::Align;
+use egui_extras::{Column, TableBuilder};
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+use time::Duration;
+use tracing_tape_parser::{Tape, Value};
+
+use crate::{
+    flamegraph::{global_callsites, CallTree, Weight},
+    state::{Callsites, LoadedTape, State},
+    utils,
+};
+
+use super::{SelectedItem, TabViewer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum SortBy {
+    /// The order in which the spans were opened or the order of the callsites.
+    Start,
+    Name,
+    Count,
+    Total,
+    SelfTime,
+    Mean,
+}
+
+impl SortBy {
+    /// The sortable columns in the order they are shown.
+    const COLUMNS: [SortBy; 5] = [
+        SortBy::Name,
+        SortBy::Count,
+        SortBy::Total,
+        SortBy::SelfTime,
+        SortBy::Mean,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            SortBy::Start => "Start",
+            SortBy::Name => "Name",
+            SortBy::Count => "Count",
+            SortBy::Total => "Total",
+            SortBy::SelfTime => "Self",
+            SortBy::Mean => "Mean",
+        }
+    }
+}
+
+/// What the rows of the tree are built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Source {
+    /// The descendants of a span.
+    Span(NodeIndex<usize>),
+    /// The spans of the tape within the selected or visible range merged by
+    /// their call path.
+    CallTree,
+}
+
+/// Identifies a row across rebuilds of the tree, e.g., to keep it expanded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum RowKey {
+    Span(NodeIndex<usize>),
+    /// The global callsite indices of the call path.
+    Path(Vec<usize>),
+}
+
+struct Row {
+    key: RowKey,
+    /// Global index of the callsite.
+    callsite_index: usize,
+    /// The span that is selected when clicking the row.
+    span: Option<NodeIndex<usize>>,
+    /// The position among the siblings when sorting by [SortBy::Start].
+    order: i64,
+    count: usize,
+    total: i64,
+    self_time: i64,
+    fields: String,
+    children: Vec<usize>,
+}
+
+impl Row {
+    fn mean(&self) -> i64 {
+        self.total / self.count.max(1) as i64
+    }
+}
+
+/// The rows of a span or call tree.
+struct Rows {
+    /// The timestamp range the call tree was built for.
+    range: Range<i64>,
+    rows: Vec<Row>,
+    roots: Vec<usize>,
+}
+
+impl Rows {
+    fn for_span(loaded_tape: &LoadedTape, callsites: &Callsites, root: NodeIndex<usize>) -> Self {
+        let tape = &loaded_tape.tape;
+        let spans = tape.spans();
+        let mut rows = Vec::<Row>::new();
+        let mut stack = vec![(root, None::<usize>)];
+        while let Some((index, parent)) = stack.pop() {
+            let span = &spans[index];
+            let fields = tape.callsites()[span.callsite_index]
+                .fields()
+                .iter()
+                .zip(span.values.iter())
+                .filter(|(_, value)| !matches!(value, Value::Empty))
+                .map(|(field, value)| format!("{}={}", field, value))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let row = rows.len();
+            rows.push(Row {
+                key: RowKey::Span(index),
+                callsite_index: callsites
+                    .tape_to_global(&loaded_tape.path, span.callsite_index)
+                    .expect("callsite"),
+                span: Some(index),
+                order: span.opened,
+                count: 1,
+                total: span.closed - span.opened,
+                self_time: Weight::SelfTime.of(tape, index, &(span.opened..span.closed)),
+                fields,
+                children: Vec::new(),
+            });
+            if let Some(parent) = parent {
+                rows[parent].children.push(row);
+            }
+            stack.extend(spans.neighbors(index).map(|child| (child, Some(row))));
+        }
+
+        Self {
+            range: 0..0,
+            rows,
+            roots: vec![0],
+        }
+    }
+
+    /// Builds the rows of the call tree from the global indices of the
+    /// callsites of the tape, e.g., in the background.
+    fn for_call_tree(tape: &Tape, global_callsites: &[usize], range: Range<i64>) -> Self {
+        let tree = CallTree::build(tape, global_callsites, range.clone(), Weight::Wall);
+        let rows = tree
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| Row {
+                key: RowKey::Path(tree.path(index)),
+                callsite_index: frame.callsite_index.unwrap_or_default(),
+                span: frame.longest.map(|(span, _)| span),
+                order: frame.callsite_index.unwrap_or_default() as i64,
+                count: frame.count,
+                total: frame.total,
+                self_time: frame.self_time,
+                fields: String::new(),
+                children: frame.children.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            range,
+            roots: rows[CallTree::ROOT].children.clone(),
+            rows,
+        }
+    }
+}
+
+/// Shows the hierarchy of a span or the call tree of a tape as a table with
+/// rows that can be expanded and sorted, e.g., for deep recursion where the
+/// spans on the timeline are too thin to read.
+#[derive(Serialize, Deserialize)]
+pub struct SpanTree {
+    title: String,
+    tape_path: PathBuf,
+    source: Source,
+    sort_by: SortBy,
+    descending: bool,
+    #[serde(skip)]
+    expanded: HashSet<RowKey>,
+    #[serde(skip)]
+    rows: Option<Box<Rows>>,
+    /// The rows of the call tree that are built in the background for a
+    /// timestamp range.
+    #[serde(skip)]
+    pending: Option<(Range<i64>, Receiver<Rows>)>,
+}
+
+impl SpanTree {
+    fn new(tape_path: PathBuf, source: Source) -> Self {
+        let short_filename = tape_path
+            .file_name()
+            .map(|f| f.to_string_lossy())
+            .unwrap_or_else(|| tape_path.to_string_lossy());
+
+        let title = match source {
+            Source::Span(span_index) => {
+                format!("Span Tree {:x} {}", span_index.index(), short_filename)
+            }
+            Source::CallTree => format!("Call Tree {}", short_filename),
+        };
+        Self {
+            title,
+            tape_path,
+            source,
+            sort_by: SortBy::Start,
+            descending: false,
+            expanded: HashSet::default(),
+            rows: None,
+            pending: None,
+        }
+    }
+
+    pub fn for_span(tape_path: PathBuf, span_index: NodeIndex<usize>) -> Self {
+        Self::new(tape_path, Source::Span(span_index))
+    }
+
+    pub fn for_call_tree(tape_path: PathBuf) -> Self {
+        Self::new(tape_path, Source::CallTree)
+    }
+
+    pub fn id(&self) -> egui::Id {
+        match self.source {
+            Source::Span(span_index) => egui::Id::new(("span_tree", &self.tape_path, span_index)),
+            Source::CallTree => egui::Id::new(("call_tree", &self.tape_path)),
+        }
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        (&self.title).into()
+    }
+
+    fn compare(&self, rows: &[Row], callsites: &Callsites, a: usize, b: usize) -> Ordering {
+        let (a, b) = (&rows[a], &rows[b]);
+        let ordering = match self.sort_by {
+            SortBy::Start => a.order.cmp(&b.order),
+            SortBy::Name => callsites[a.callsite_index]
+                .inner
+                .name()
+                .cmp(callsites[b.callsite_index].inner.name()),
+            SortBy::Count => a.count.cmp(&b.count),
+            SortBy::Total => a.total.cmp(&b.total),
+            SortBy::SelfTime => a.self_time.cmp(&b.self_time),
+            SortBy::Mean => a.mean().cmp(&b.mean()),
+        };
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    /// Whether the tape and span still exist, e.g., after restoring a session
+    /// whose tapes were recorded again.
+    pub fn is_valid(&self, state: &State) -> bool {
+        let Some(loaded_tape) = state.loaded_tapes.get(&self.tape_path) else {
+            return false;
+        };
+        match self.source {
+            Source::Span(span_index) => span_index.index() < loaded_tape.tape.spans().node_count(),
+            Source::CallTree => true,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        let Some(loaded_tape) = viewer.state.loaded_tapes.get(&self.tape_path) else {
+            return;
+        };
+        let global_start = viewer.global_time_span.start;
+        let callsites = &viewer.state.callsites;
+
+        match self.source {
+            Source::Span(span_index) => {
+                if self.rows.is_none() {
+                    self.rows = Some(Box::new(Rows::for_span(loaded_tape, callsites, span_index)));
+                    // The expanded rows are not part of a session, the root
+                    // starts expanded.
+                    if self.expanded.is_empty() {
+                        self.expanded.insert(RowKey::Span(span_index));
+                    }
+                }
+            }
+            Source::CallTree => {
+                if let Some((_, receiver)) = &self.pending {
+                    match receiver.try_recv() {
+                        Ok(rows) => {
+                            self.rows = Some(Box::new(rows));
+                            self.pending = None;
+                        }
+                        Err(TryRecvError::Disconnected) => self.pending = None,
+                        Err(TryRecvError::Empty) => {}
+                    }
+                }
+
+                // Only one tree is built at a time, the previous one is shown
+                // until it is done.
+                let range = viewer.state.focused_range();
+                let range = loaded_tape.global_offset_to_timestamp(*range.start(), global_start)
+                    ..loaded_tape.global_offset_to_timestamp(*range.end(), global_start);
+                let latest = match (&self.pending, &self.rows) {
+                    (Some((range, _)), _) => Some(range),
+                    (None, Some(rows)) => Some(&rows.range),
+                    (None, None) => None,
+                };
+                if latest != Some(&range) && self.pending.is_none() {
+                    let tape = loaded_tape.tape.clone();
+                    let global_callsites = global_callsites(loaded_tape, callsites);
+                    let receiver = utils::spawn(ui.ctx(), {
+                        let range = range.clone();
+                        move || Rows::for_call_tree(&tape, &global_callsites, range)
+                    });
+                    self.pending = Some((range, receiver));
+                }
+            }
+        }
+        let Some(rows) = self.rows.as_deref() else {
+            ui.spinner();
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            if ui.button("Expand All").clicked() {
+                self.expanded
+                    .extend(rows.rows.iter().map(|row| row.key.clone()));
+            }
+            if ui.button("Collapse All").clicked() {
+                self.expanded.clear();
+            }
+            ui.separator();
+            let title = if self.source == Source::CallTree {
+                "Callsite Order"
+            } else {
+                SortBy::Start.title()
+            };
+            if ui
+                .selectable_label(self.sort_by == SortBy::Start, title)
+                .clicked()
+            {
+                self.sort_by = SortBy::Start;
+                self.descending = false;
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+        });
+
+        // Only the rows below expanded rows are visible.
+        let mut visible = Vec::new();
+        let mut stack = Vec::new();
+        let push_children = |stack: &mut Vec<(usize, usize)>, children: &[usize], depth| {
+            let mut children = children.to_vec();
+            children.sort_by(|a, b| self.compare(&rows.rows, callsites, *a, *b));
+            stack.extend(children.into_iter().rev().map(|child| (child, depth)));
+        };
+        push_children(&mut stack, &rows.roots, 0);
+        while let Some((row, depth)) = stack.pop() {
+            visible.push((row, depth));
+            if self.expanded.contains(&rows.rows[row].key) {
+                push_children(&mut stack, &rows.rows[row].children, depth + 1);
+            }
+        }
+
+        let selected_span = match &viewer.state.selected_item {
+            Some(SelectedItem::Span { tape, span_index }) if *tape == self.tape_path => {
+                Some(*span_index)
+            }
+            _ => None,
+        };
+        let mut toggled = None;
+        let mut clicked = None;
+        let mut sort_by = None;
+        TableBuilder::new(ui)
+            .auto_shrink(false)
+            .striped(true)
+            .sense(egui::Sense::click())
+            .column(Column::initial(300.0).resizable(true).clip(true))
+            .columns(Column::auto(), SortBy::COLUMNS.len() - 1)
+            .column(Column::remainder().clip(true))
+            .cell_layout(egui::Layout::left_to_right(Align::Center))
+            .header(20.0, |mut header| {
+                for column in SortBy::COLUMNS {
+                    header.col(|ui| {
+                        let title = match (column == self.sort_by, self.descending) {
+                            (true, true) => format!("{} ⏷", column.title()),
+                            (true, false) => format!("{} ⏶", column.title()),
+                            (false, _) => column.title().to_string(),
+                        };
+                        if ui
+                            .add(
+                                egui::Label::new(egui::RichText::new(title).strong())
+                                    .sense(egui::Sense::click()),
+                            )
+                            .clicked()
+                        {
+                            sort_by = Some(column);
+                        }
+                    });
+                }
+                header.col(|ui| {
+                    ui.strong("Fields");
+                });
+            })
+            .body(|body| {
+                body.rows(18.0, visible.len(), |mut row| {
+                    let (index, depth) = visible[row.index()];
+                    let data = &rows.rows[index];
+                    row.set_selected(data.span.is_some() && data.span == selected_span);
+                    row.col(|ui| {
+                        ui.add_space(depth as f32 * 12.0);
+                        if data.children.is_empty() {
+                            ui.add_space(ui.spacing().icon_width);
+                        } else {
+                            let icon = if self.expanded.contains(&data.key) {
+                                "⏷"
+                            } else {
+                                "⏵"
+                            };
+                            if ui
+                                .add(egui::Button::new(icon).frame(false).small())
+                                .clicked()
+                            {
+                                toggled = Some(data.key.clone());
+                            }
+                        }
+                        let callsite = &callsites[data.callsite_index];
+                        ui.colored_label(callsite.color, "■");
+                        ui.label(callsite.inner.name());
+                    });
+                    row.col(|ui| {
+                        ui.label(data.count.to_string());
+                    });
+                    for value in [data.total, data.self_time, data.mean()] {
+                        row.col(|ui| {
+                            ui.label(format!("{:.1}", Duration::nanoseconds(value)));
+                        });
+                    }
+                    row.col(|ui| {
+                        ui.label(&data.fields);
+                    });
+                    if row.response().clicked() {
+                        clicked = data.span;
+                    }
+                });
+            });
+
+        if let Some(key) = toggled {
+            if !self.expanded.remove(&key) {
+                self.expanded.insert(key);
+            }
+        }
+        if let Some(column) = sort_by {
+            if self.sort_by == column {
+                self.descending = !self.descending;
+            } else {
+                self.sort_by = column;
+                self.descending = column != SortBy::Name;
+            }
+        }
+        if let Some(span_index) = clicked {
+            let span = &loaded_tape.tape.spans()[span_index];
+            let range = loaded_tape.timestamp_to_global_offset(span.opened, global_start)
+                ..=loaded_tape.timestamp_to_global_offset(span.closed, global_start);
+            viewer.state.selected_item = Some(SelectedItem::Span {
+                tape: self.tape_path.clone(),
+                span_index,
+            });
+            viewer.state.center_timeline(range);
+        }
+    }
+}
+
+#[test]
+fn test_rows_for_span() {
+    use tracing_tape_parser::TapeBuilder;
+
+    use crate::testing::{callsite, span, state};
+
+    let mut builder = TapeBuilder::new(16, 0);
+    let main = builder.callsite(callsite("main", &[]));
+    let work = builder.callsite(callsite("work", &["n"]));
+    let root = builder.span(None, span(main, 0..100, &[]));
+    let first = builder.span(Some(root), span(work, 10..40, &[Value::I64(1)]));
+    builder.span(Some(first), span(work, 20..30, &[Value::Empty]));
+    builder.span(Some(root), span(work, 50..90, &[Value::I64(2)]));
+    let state = state(vec![("test.tape", builder.finish())]);
+    let loaded_tape = &state.loaded_tapes[0];
+
+    let rows = Rows::for_span(loaded_tape, &state.callsites, root);
+    assert_eq!(rows.roots, [0]);
+    assert_eq!(rows.rows.len(), 4);
+    let row = &rows.rows[0];
+    assert_eq!(row.key, RowKey::Span(root));
+    assert_eq!((row.count, row.total, row.self_time), (1, 100, 30));
+    assert_eq!(row.children.len(), 2);
+
+    let mut children = row
+        .children
+        .iter()
+        .map(|child| &rows.rows[*child])
+        .collect::<Vec<_>>();
+    children.sort_by_key(|child| child.order);
+    let fields = children
+        .iter()
+        .map(|child| child.fields.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(fields, ["n=1", "n=2"]);
+    assert_eq!(children[0].key, RowKey::Span(first));
+    assert_eq!((children[0].total, children[0].self_time), (30, 20));
+    assert_eq!(children[0].children.len(), 1);
+    assert_eq!(rows.rows[children[0].children[0]].fields, "");
+}
+
+#[test]
+fn test_rows_for_call_tree() {
+    use tracing_tape_parser::TapeBuilder;
+
+    use crate::testing::{callsite, span, state};
+
+    let mut builder = TapeBuilder::new(16, 0);
+    let main = builder.callsite(callsite("main", &[]));
+    let work = builder.callsite(callsite("work", &[]));
+    let root = builder.span(None, span(main, 0..100, &[]));
+    builder.span(Some(root), span(work, 10..40, &[]));
+    let longest = builder.span(Some(root), span(work, 50..90, &[]));
+    let state = state(vec![("test.tape", builder.finish())]);
+    let loaded_tape = &state.loaded_tapes[0];
+    let global_callsites = global_callsites(loaded_tape, &state.callsites);
+    let (main, work) = (global_callsites[main], global_callsites[work]);
+
+    let rows = Rows::for_call_tree(&loaded_tape.tape, &global_callsites, 0..100);
+    assert_eq!(rows.range, 0..100);
+    let [root] = rows.roots[..] else {
+        panic!("expected a single root");
+    };
+    let row = &rows.rows[root];
+    assert_eq!(row.key, RowKey::Path(vec![main]));
+    assert_eq!((row.callsite_index, row.count, row.total), (main, 1, 100));
+    let [child] = row.children[..] else {
+        panic!("expected a single child");
+    };
+    let row = &rows.rows[child];
+    assert_eq!(row.key, RowKey::Path(vec![main, work]));
+    assert_eq!((row.count, row.total, row.self_time), (2, 70, 70));
+    assert_eq!(row.span, Some(longest));
+    assert!(row.children.is_empty());
+}
@@ -1,13 +1,108 @@
-use std::path::PathBuf;
+use std::{collections::BTreeSet, path::PathBuf};
 
 use egui::Align;
 use egui_extras::{Column, TableBuilder};
+use serde::{Deserialize, Serialize};
+use tracing_tape_parser::{Event, Tape, Value};
 
-use super::TabViewer;
+use crate::utils::level_color;
 
+use super::{SelectedItem, TabViewer};
+
+const LEVELS: [tracing::Level; 5] = [
+    tracing::Level::TRACE,
+    tracing::Level::DEBUG,
+    tracing::Level::INFO,
+    tracing::Level::WARN,
+    tracing::Level::ERROR,
+];
+
+/// Formats the values of an event, starting with the message.
+fn message(tape: &Tape, event: &Event) -> String {
+    let callsite = &tape.callsites()[event.callsite_index];
+    let mut message = String::new();
+    let fields = callsite.fields().iter().zip(event.values.iter());
+    for (name, value) in fields.clone() {
+        if &**name == "message" {
+            message.push_str(&value.to_string());
+        }
+    }
+    for (name, value) in fields {
+        if &**name != "message" && !matches!(value, Value::Empty) {
+            if !message.is_empty() {
+                message.push(' ');
+            }
+            message.push_str(&format!("{name}={value}"));
+        }
+    }
+    message
+}
+
+fn thread_name(tape: &Tape, thread_id: u64) -> String {
+    match tape.threads().get(&thread_id) {
+        Some(Some(name)) => name.clone(),
+        _ => format!("{:x}", thread_id),
+    }
+}
+
+/// Filters of the log view, events are shown if they pass all of them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Filter {
+    #[serde(with = "crate::session::level")]
+    min_level: tracing::Level,
+    hidden_callsites: BTreeSet<usize>,
+    hidden_threads: BTreeSet<u64>,
+    text: String,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            min_level: tracing::Level::TRACE,
+            hidden_callsites: BTreeSet::new(),
+            hidden_threads: BTreeSet::new(),
+            text: String::new(),
+        }
+    }
+}
+
+impl Filter {
+    fn matches(&self, tape: &Tape, event: &Event) -> bool {
+        let callsite = &tape.callsites()[event.callsite_index];
+        if callsite.level() > self.min_level
+            || self.hidden_callsites.contains(&event.callsite_index)
+            || self.hidden_threads.contains(&event.thread_id)
+        {
+            return false;
+        }
+        if self.text.is_empty() {
+            return true;
+        }
+
+        let text = self.text.to_lowercase();
+        callsite.target().to_lowercase().contains(&text)
+            || callsite.name().to_lowercase().contains(&text)
+            || event
+                .values
+                .iter()
+                .any(|value| value.to_string().to_lowercase().contains(&text))
+    }
+}
+
+/// Indices of the events that passed a filter within a time range.
+struct FilteredEvents {
+    filter: Filter,
+    range: (i64, i64),
+    indices: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct TapeEvents {
     title: String,
     tape_path: PathBuf,
+    filter: Filter,
+    #[serde(skip)]
+    filtered: Option<Box<FilteredEvents>>,
 }
 
 impl TapeEvents {
@@ -18,8 +113,13 @@ impl TapeEvents {
             .map(|f| f.to_string_lossy())
             .unwrap_or_else(|| tape_path.to_string_lossy());
 
-        let title = format!("Timeline {}", short_filename);
-        Self { title, tape_path }
+        let title = format!("Events {}", short_filename);
+        Self {
+            title,
+            tape_path,
+            filter: Filter::default(),
+            filtered: None,
+        }
     }
 
     pub fn id(&self) -> (&PathBuf, &str) {
@@ -30,6 +130,57 @@ impl TapeEvents {
         (&self.title).into()
     }
 
+    fn filter_ui(&mut self, ui: &mut egui::Ui, tape: &Tape) {
+        ui.horizontal_wrapped(|ui| {
+            egui::ComboBox::from_id_salt((&self.tape_path, "min_level"))
+                .selected_text(format!("≤ {}", self.filter.min_level))
+                .show_ui(ui, |ui| {
+                    for level in LEVELS {
+                        ui.selectable_value(&mut self.filter.min_level, level, level.as_str());
+                    }
+                });
+
+            ui.menu_button("Callsites", |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (index, callsite) in tape.callsites().iter().enumerate() {
+                        if callsite.kind().is_span() {
+                            continue;
+                        }
+                        let mut shown = !self.filter.hidden_callsites.contains(&index);
+                        let label = format!("{} {}", callsite.target(), callsite.name());
+                        if ui.checkbox(&mut shown, label).changed() {
+                            if shown {
+                                self.filter.hidden_callsites.remove(&index);
+                            } else {
+                                self.filter.hidden_callsites.insert(index);
+                            }
+                        }
+                    }
+                });
+            });
+
+            ui.menu_button("Threads", |ui| {
+                let mut threads = tape.threads().keys().copied().collect::<Vec<_>>();
+                threads.sort_by_key(|thread_id| thread_name(tape, *thread_id));
+                for thread_id in threads {
+                    let mut shown = !self.filter.hidden_threads.contains(&thread_id);
+                    if ui
+                        .checkbox(&mut shown, thread_name(tape, thread_id))
+                        .changed()
+                    {
+                        if shown {
+                            self.filter.hidden_threads.remove(&thread_id);
+                        } else {
+                            self.filter.hidden_threads.insert(thread_id);
+                        }
+                    }
+                }
+            });
+
+            ui.add(egui::TextEdit::singleline(&mut self.filter.text).hint_text("Filter"));
+        });
+    }
+
     pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
         let loaded_tape = if let Some(tape) = viewer.state.loaded_tapes.get(&self.tape_path) {
             tape
@@ -37,51 +188,105 @@ impl TapeEvents {
             return;
         };
 
+        self.filter_ui(ui, &loaded_tape.tape);
+
         let available_height = ui.available_height();
 
-        let start = loaded_tape.global_offset_to_timestamp(
-            *viewer.state.timeline_range.start(),
-            viewer.global_time_span.start,
-        );
-        let end = loaded_tape.global_offset_to_timestamp(
-            *viewer.state.timeline_range.end(),
-            viewer.global_time_span.start,
-        );
+        let range = viewer.state.focused_range();
+        let start =
+            loaded_tape.global_offset_to_timestamp(*range.start(), viewer.global_time_span.start);
+        let end =
+            loaded_tape.global_offset_to_timestamp(*range.end(), viewer.global_time_span.start);
 
         let events = loaded_tape.tape.events();
-        let start_index = match events.binary_search_by_key(&start, |event| event.timestamp) {
-            Ok(index) => index,
-            Err(index) => index,
-        };
-        let end_index = match events.binary_search_by_key(&end, |event| event.timestamp) {
-            Ok(index) => index,
-            Err(index) => index,
-        };
+        let up_to_date = self
+            .filtered
+            .as_ref()
+            .is_some_and(|cache| cache.filter == self.filter && cache.range == (start, end));
+        if !up_to_date {
+            let first = events.partition_point(|event| event.timestamp < start);
+            let last = events.partition_point(|event| event.timestamp <= end);
+            let indices = (first..last)
+                .filter(|index| self.filter.matches(&loaded_tape.tape, &events[*index]))
+                .collect();
+            self.filtered = Some(Box::new(FilteredEvents {
+                filter: self.filter.clone(),
+                range: (start, end),
+                indices,
+            }));
+        }
+        let filtered = &self.filtered.as_ref().unwrap().indices;
 
-        let events_in_range = &events[start_index..end_index];
+        let selected_event = match &viewer.state.selected_item {
+            Some(SelectedItem::Event { tape, event_index }) if *tape == self.tape_path => {
+                Some(*event_index)
+            }
+            _ => None,
+        };
 
+        let mut clicked = None;
         TableBuilder::new(ui)
             .auto_shrink(false)
+            .striped(true)
+            .sense(egui::Sense::click())
             .max_scroll_height(available_height)
+            .column(Column::auto())
+            .column(Column::auto())
+            .column(Column::auto().clip(true))
+            .column(Column::auto().clip(true))
             .column(Column::remainder())
-            .cell_layout(egui::Layout::left_to_right(Align::LEFT))
+            .cell_layout(egui::Layout::left_to_right(Align::Center))
+            .header(20.0, |mut header| {
+                for title in ["Time", "Level", "Target", "Thread", "Message"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
             .body(|body| {
                 let row_height = 18.0;
-                body.rows(row_height, events_in_range.len(), move |mut row| {
-                    let index = row.index();
-                    row.col(|ui| {
-                        let event = &events_in_range[index];
-                        let callsite = &loaded_tape.tape.callsites()[event.callsite_index];
+                body.rows(row_height, filtered.len(), |mut row| {
+                    let event_index = filtered[row.index()];
+                    let event = &events[event_index];
+                    let callsite = &loaded_tape.tape.callsites()[event.callsite_index];
+                    row.set_selected(selected_event == Some(event_index));
 
-                        for (name, value) in callsite.fields().iter().zip(event.values.iter()) {
-                            if &**name == "message" {
-                                ui.label(format!("{value}"));
-                            } else {
-                                ui.label(format!("{name} = {value}"));
-                            }
-                        }
+                    row.col(|ui| {
+                        let offset = loaded_tape.timestamp_to_global_offset(
+                            event.timestamp,
+                            viewer.global_time_span.start,
+                        );
+                        ui.label(format!("{:.6}", offset));
+                    });
+                    row.col(|ui| {
+                        ui.colored_label(level_color(callsite.level()), callsite.level().as_str());
                     });
+                    row.col(|ui| {
+                        ui.label(callsite.target());
+                    });
+                    row.col(|ui| {
+                        ui.label(thread_name(&loaded_tape.tape, event.thread_id));
+                    });
+                    row.col(|ui| {
+                        ui.label(message(&loaded_tape.tape, event));
+                    });
+
+                    if row.response().clicked() {
+                        clicked = Some(event_index);
+                    }
                 });
             });
+
+        if let Some(event_index) = clicked {
+            let offset = loaded_tape.timestamp_to_global_offset(
+                events[event_index].timestamp,
+                viewer.global_time_span.start,
+            );
+            viewer.state.center_timeline(offset..=offset);
+            viewer.state.selected_item = Some(SelectedItem::Event {
+                tape: self.tape_path.clone(),
+                event_index,
+            });
+        }
     }
 }
@@ -0,0 +1,254 @@
+use std::{ops::Range, path::PathBuf};
+
+use crossbeam_channel::{Receiver, TryRecvError};
+use egui::Align;
+use egui_extras::{Column, TableBuilder};
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use crate::{
+    statistics::{calculate_range_statistics, RangeStatistics, SpanSummary},
+    utils,
+};
+
+use super::{SelectedItem, TabViewer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum SortBy {
+    Name,
+    Count,
+    Total,
+    SelfTime,
+    Mean,
+    P50,
+    P95,
+    P99,
+    Max,
+}
+
+impl SortBy {
+    const ALL: [SortBy; 9] = [
+        SortBy::Name,
+        SortBy::Count,
+        SortBy::Total,
+        SortBy::SelfTime,
+        SortBy::Mean,
+        SortBy::P50,
+        SortBy::P95,
+        SortBy::P99,
+        SortBy::Max,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            SortBy::Name => "Callsite",
+            SortBy::Count => "Count",
+            SortBy::Total => "Total",
+            SortBy::SelfTime => "Self",
+            SortBy::Mean => "Mean",
+            SortBy::P50 => "p50",
+            SortBy::P95 => "p95",
+            SortBy::P99 => "p99",
+            SortBy::Max => "Max",
+        }
+    }
+
+    /// The value of the column, durations are in nanoseconds.
+    fn value(self, summary: &SpanSummary) -> i64 {
+        match self {
+            SortBy::Name => 0,
+            SortBy::Count => summary.count as i64,
+            SortBy::Total => summary.total,
+            SortBy::SelfTime => summary.self_time,
+            SortBy::Mean => summary.mean,
+            SortBy::P50 => summary.p50,
+            SortBy::P95 => summary.p95,
+            SortBy::P99 => summary.p99,
+            SortBy::Max => summary.max,
+        }
+    }
+}
+
+/// Lists the span callsites of a tape with the time spent in them within the
+/// selected or visible range. The statistics are calculated in the background
+/// and the previous ones are shown until they are ready.
+#[derive(Serialize, Deserialize)]
+pub struct TapeStatistics {
+    title: String,
+    tape_path: PathBuf,
+    sort_by: SortBy,
+    descending: bool,
+    #[serde(skip)]
+    statistics: Option<RangeStatistics>,
+    #[serde(skip)]
+    pending: Option<(Range<i64>, Receiver<RangeStatistics>)>,
+}
+
+impl TapeStatistics {
+    pub fn new<P: Into<PathBuf>>(tape_path: P) -> Self {
+        let tape_path = tape_path.into();
+        let short_filename = tape_path
+            .file_name()
+            .map(|f| f.to_string_lossy())
+            .unwrap_or_else(|| tape_path.to_string_lossy());
+
+        let title = format!("Statistics {}", short_filename);
+        Self {
+            title,
+            tape_path,
+            sort_by: SortBy::Total,
+            descending: true,
+            statistics: None,
+            pending: None,
+        }
+    }
+
+    pub fn id(&self) -> (&PathBuf, &str) {
+        (&self.tape_path, "statistics")
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        (&self.title).into()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
+        let Some(loaded_tape) = viewer.state.loaded_tapes.get(&self.tape_path) else {
+            return;
+        };
+
+        let range = viewer.state.focused_range();
+        let start =
+            loaded_tape.global_offset_to_timestamp(*range.start(), viewer.global_time_span.start);
+        let end =
+            loaded_tape.global_offset_to_timestamp(*range.end(), viewer.global_time_span.start);
+
+        if let Some((_, receiver)) = &self.pending {
+            match receiver.try_recv() {
+                Ok(statistics) => {
+                    self.statistics = Some(statistics);
+                    self.pending = None;
+                }
+                Err(TryRecvError::Disconnected) => self.pending = None,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+
+        // Only one calculation runs at a time, the range may have changed
+        // again by the time it finishes.
+        let latest = match (&self.pending, &self.statistics) {
+            (Some((range, _)), _) => Some(range),
+            (None, Some(statistics)) => Some(&statistics.range),
+            (None, None) => None,
+        };
+        if latest != Some(&(start..end)) && self.pending.is_none() {
+            let tape = loaded_tape.tape.clone();
+            let receiver = utils::spawn(ui.ctx(), move || {
+                calculate_range_statistics(&tape, start..end)
+            });
+            self.pending = Some((start..end, receiver));
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Range: {:.3}", Duration::nanoseconds(end - start)));
+            if let Some(statistics) = &self.statistics {
+                ui.label(format!("{} callsites", statistics.callsites.len()));
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+        });
+
+        let Some(statistics) = &mut self.statistics else {
+            return;
+        };
+        let callsites = &viewer.state.callsites;
+        let path = &self.tape_path;
+        let global_index = |summary: &SpanSummary| {
+            callsites
+                .tape_to_global(path, summary.callsite_index)
+                .expect("callsite")
+        };
+
+        let sort_by = self.sort_by;
+        statistics.callsites.sort_by(|a, b| {
+            let ordering = match sort_by {
+                SortBy::Name => callsites[global_index(a)]
+                    .inner
+                    .name()
+                    .cmp(callsites[global_index(b)].inner.name()),
+                _ => sort_by.value(a).cmp(&sort_by.value(b)),
+            };
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+
+        let selected = match viewer.state.selected_item {
+            Some(SelectedItem::Callsite(callsite_index)) => Some(callsite_index),
+            _ => None,
+        };
+        let mut clicked = None;
+        TableBuilder::new(ui)
+            .auto_shrink(false)
+            .striped(true)
+            .sense(egui::Sense::click())
+            .column(Column::remainder().clip(true))
+            .columns(Column::auto(), SortBy::ALL.len() - 1)
+            .cell_layout(egui::Layout::left_to_right(Align::Center))
+            .header(20.0, |mut header| {
+                for column in SortBy::ALL {
+                    header.col(|ui| {
+                        let title = match (column == self.sort_by, self.descending) {
+                            (true, true) => format!("{} ⏷", column.title()),
+                            (true, false) => format!("{} ⏶", column.title()),
+                            (false, _) => column.title().to_string(),
+                        };
+                        if ui
+                            .add(
+                                egui::Label::new(egui::RichText::new(title).strong())
+                                    .sense(egui::Sense::click()),
+                            )
+                            .clicked()
+                        {
+                            if self.sort_by == column {
+                                self.descending = !self.descending;
+                            } else {
+                                self.sort_by = column;
+                                self.descending = column != SortBy::Name;
+                            }
+                        }
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(18.0, statistics.callsites.len(), |mut row| {
+                    let summary = &statistics.callsites[row.index()];
+                    let callsite_index = global_index(summary);
+                    row.set_selected(selected == Some(callsite_index));
+                    row.col(|ui| {
+                        ui.label(callsites[callsite_index].inner.name());
+                    });
+                    row.col(|ui| {
+                        ui.label(summary.count.to_string());
+                    });
+                    for column in &SortBy::ALL[2..] {
+                        row.col(|ui| {
+                            ui.label(format!(
+                                "{:.1}",
+                                Duration::nanoseconds(column.value(summary))
+                            ));
+                        });
+                    }
+                    if row.response().clicked() {
+                        clicked = Some(callsite_index);
+                    }
+                });
+            });
+
+        if let Some(callsite_index) = clicked {
+            viewer.state.selected_item = Some(SelectedItem::Callsite(callsite_index));
+        }
+    }
+}
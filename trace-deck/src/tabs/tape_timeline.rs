@@ -1,6 +1,12 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
+use ahash::HashMap;
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
 use time::Duration;
+use tracing_tape_parser::Tape;
+
+use crate::{correlation::LinkAnchor, state::Action, utils::level_color};
 
 use super::{SelectedItem, TabViewer};
 
@@ -62,6 +68,10 @@ use super::{SelectedItem, TabViewer};
 //     thread_span_events
 // }
 
+/// Width in points of the blocks that summarize spans too short to be drawn.
+const DENSITY_BLOCK_WIDTH: f32 = 4.0;
+
+#[derive(Serialize, Deserialize)]
 pub struct TapeTimeline {
     title: String,
     tape_path: PathBuf,
@@ -104,16 +114,7 @@ impl TapeTimeline {
             viewer.global_time_span.start,
         );
 
-        let mut relevant_root_spans = Vec::new();
         let spans = loaded_tape.tape.spans();
-        for span_id in loaded_tape.tape.root_spans() {
-            let span = spans.node_weight(*span_id).unwrap();
-            // println!("{start}-{end}: {}-{}", span.opened, span.closed);
-            if span.opened > end || span.closed < start {
-                continue;
-            }
-            relevant_root_spans.push(*span_id);
-        }
 
         let mut threads = loaded_tape
             .tape
@@ -141,110 +142,274 @@ impl TapeTimeline {
             }
         });
 
+        let measure_start = match viewer.state.current_action {
+            Action::Measure { from } => Some(from),
+            Action::None => None,
+        };
         let mut timeline =
             crate::timeline::Timeline::new(&self.tape_path, viewer.state.timeline_range.clone())
-                .with_selected_range(viewer.state.selected_range.clone());
+                .with_selected_range(viewer.state.selected_range.clone())
+                .with_measure_start(measure_start);
         for (thread_name, _) in &threads {
             timeline = timeline.with_row_header(thread_name.clone());
         }
 
+        let (selected_span, selected_event) = match &viewer.state.selected_item {
+            Some(SelectedItem::Span { tape, span_index }) if *tape == self.tape_path => {
+                (Some(*span_index), None)
+            }
+            Some(SelectedItem::Event { tape, event_index }) if *tape == self.tape_path => {
+                (None, Some(*event_index))
+            }
+            _ => (None, None),
+        };
+
+        let highlighted_spans = viewer.state.highlighted_spans.get(&self.tape_path);
+        let tape_index = viewer.state.loaded_tapes.index_of(&self.tape_path);
+        let correlations = &viewer.state.correlations;
+        let mut link_anchors = Vec::new();
+
+        // The visible events are partitioned by thread once instead of being
+        // filtered for every row.
+        let callsites = loaded_tape.tape.callsites();
+        let events = loaded_tape.tape.events();
+        let first_event = events.partition_point(|event| event.timestamp < start);
+        let last_event = events.partition_point(|event| event.timestamp <= end);
+        let mut thread_events = HashMap::<u64, Vec<usize>>::default();
+        for (index, event) in events[first_event..last_event].iter().enumerate() {
+            thread_events
+                .entry(event.thread_id)
+                .or_default()
+                .push(first_event + index);
+        }
+
         // // let modifiers = ui.input(|i| i.modifiers);
-        let mut selected_range = viewer.state.selected_range.clone();
         // let mut span_relevant = Vec::new();
 
         let respone = timeline.show(ui, |timeline_ui, i| {
             let thread_id = threads[i].1;
-            let mut level = Vec::new();
-
-            petgraph::visit::depth_first_search(spans, relevant_root_spans.clone(), |event| {
-                match event {
-                    petgraph::visit::DfsEvent::Discover(n, _) => {
-                        let span = spans.node_weight(n).unwrap();
-                        level.push(false);
-                        if span.opened > end || span.closed < start {
-                            return petgraph::visit::Control::<()>::Prune;
-                        }
-                        let opened = loaded_tape
-                            .timestamp_to_global_offset(span.opened, viewer.global_time_span.start);
-                        let closed = loaded_tape
-                            .timestamp_to_global_offset(span.closed, viewer.global_time_span.start);
+            // Spans smaller than a point are not drawn individually, instead
+            // the time spent in them is shown as blocks whose opacity is the
+            // busy fraction of the block.
+            let min_duration = timeline_ui.dx2dt(1.0).whole_nanoseconds() as i64;
+            let thread_spans = loaded_tape
+                .tape
+                .spans_in_range_on_thread(thread_id, start..=end)
+                .min_duration(min_duration)
+                .collect::<Vec<_>>();
+
+            let block_duration =
+                (timeline_ui.dx2dt(DENSITY_BLOCK_WIDTH).whole_nanoseconds() as i64).max(1);
+            let first_block = start.div_euclid(block_duration) * block_duration;
+            let block_count = ((end - first_block) / block_duration + 1) as usize;
+            let block_range = |block: usize| {
+                let block_start = first_block + block as i64 * block_duration;
+                block_start..block_start + block_duration
+            };
+
+            let mut density = (0..loaded_tape.tape.thread_depth(thread_id))
+                .map(|depth| {
+                    if loaded_tape
+                        .tape
+                        .busy_time_on_thread(thread_id, depth, start..end + 1)
+                        == 0
+                    {
+                        return Vec::new();
+                    }
+                    (0..block_count)
+                        .map(|block| {
+                            loaded_tape.tape.busy_time_on_thread(
+                                thread_id,
+                                depth,
+                                block_range(block),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+
+            // Remove the time of the spans that are drawn individually.
+            for thread_span in &thread_spans {
+                let span = spans.node_weight(thread_span.span).unwrap();
+                let entrance = &span.entrances[thread_span.entrance];
+                let blocks = &mut density[thread_span.depth];
+                if blocks.is_empty() {
+                    continue;
+                }
+                let first =
+                    ((entrance.entered.max(first_block) - first_block) / block_duration) as usize;
+                let last = ((entrance.exited - first_block) / block_duration) as usize;
+                for block in first..=last.min(blocks.len() - 1) {
+                    let range = block_range(block);
+                    blocks[block] -=
+                        entrance.exited.min(range.end) - entrance.entered.max(range.start);
+                }
+            }
 
-                        if span.entrances.iter().any(|e| e.thread_id == thread_id) {
-                            level.iter_mut().for_each(|l| *l = true);
-                        }
+            for (depth, blocks) in density.iter().enumerate() {
+                for (block, busy) in blocks.iter().enumerate() {
+                    if *busy <= 0 {
+                        continue;
+                    }
+                    let range = block_range(block);
+                    let fraction = *busy as f32 / block_duration as f32;
+                    timeline_ui.block(
+                        depth,
+                        egui::Color32::GRAY.linear_multiply(fraction),
+                        loaded_tape
+                            .timestamp_to_global_offset(range.start, viewer.global_time_span.start)
+                            ..=loaded_tape.timestamp_to_global_offset(
+                                range.end,
+                                viewer.global_time_span.start,
+                            ),
+                    );
+                }
+            }
+
+            for thread_span in thread_spans {
+                let span = spans.node_weight(thread_span.span).unwrap();
+                let entrance = &span.entrances[thread_span.entrance];
+                let callsite = viewer
+                    .state
+                    .callsites
+                    .get_for_tape(&self.tape_path, span.callsite_index)
+                    .unwrap();
+
+                let entered = loaded_tape
+                    .timestamp_to_global_offset(entrance.entered, viewer.global_time_span.start);
+                let exited = loaded_tape
+                    .timestamp_to_global_offset(entrance.exited, viewer.global_time_span.start);
+
+                let width = timeline_ui.dt2dx(exited - entered);
+                let color = if width < 10.0 {
+                    callsite.color.linear_multiply((width - 1.0) / 9.0)
+                } else {
+                    callsite.color
+                };
+
+                let response = timeline_ui.item(
+                    thread_span.depth,
+                    callsite.inner.name().to_string(),
+                    color,
+                    entered..=exited,
+                );
+
+                if selected_span == Some(thread_span.span)
+                    || highlighted_spans.is_some_and(|spans| spans.contains(&thread_span.span))
+                {
+                    timeline_ui.highlight(response.rect);
+                }
 
-                        if timeline_ui.dt2dx(closed - opened) > 1.0 {
-                            petgraph::visit::Control::<()>::Continue
-                        } else {
-                            petgraph::visit::Control::<()>::Prune
+                if let Some(tape_index) = tape_index {
+                    if let Some(group) = correlations.group((tape_index, thread_span.span)) {
+                        if response.interact_rect.is_positive() {
+                            link_anchors.push(LinkAnchor {
+                                group,
+                                tape_index,
+                                rect: response.interact_rect,
+                            });
                         }
                     }
-                    petgraph::visit::DfsEvent::Finish(n, _) => {
-                        if level.pop().unwrap() {
-                            let span = spans.node_weight(n).unwrap();
-                            let callsite = viewer
-                                .state
-                                .callsites
-                                .get_for_tape(&self.tape_path, span.callsite_index)
-                                .unwrap();
-
-                            let opened = loaded_tape.timestamp_to_global_offset(
-                                span.opened,
-                                viewer.global_time_span.start,
-                            );
-                            let closed = loaded_tape.timestamp_to_global_offset(
-                                span.closed,
-                                viewer.global_time_span.start,
-                            );
-
-                            let width = timeline_ui.dt2dx(closed - opened);
-                            if width > 1.0 {
-                                let color = if width < 10.0 {
-                                    callsite.color.linear_multiply((width - 1.0) / 9.0)
-                                } else {
-                                    callsite.color
-                                };
-
-                                let response = timeline_ui.item(
-                                    level.len(),
-                                    callsite.inner.name().to_string(),
-                                    color,
-                                    opened..=closed,
-                                );
-
-                                let mut text = format!(
-                                    "{} ({:.1})\n{}",
-                                    callsite.inner.name(),
-                                    Duration::nanoseconds(span.closed - span.opened),
-                                    callsite.inner.target()
-                                );
-                                if let (Some(file), Some(line)) =
-                                    (&callsite.inner.file(), callsite.inner.line())
-                                {
-                                    text.push_str(&format!("\n{}:{}", file, line));
-                                }
-
-                                for (field, value) in
-                                    callsite.inner.fields().iter().zip(span.values.iter())
-                                {
-                                    text.push_str(&format!("\n{} = {}", field, value));
-                                }
-                                let response = response.on_hover_text_at_pointer(text);
-
-                                if response.clicked() {
-                                    viewer.state.selected_item = Some(SelectedItem::Span {
-                                        span_index: n,
-                                        tape: self.tape_path.clone(),
-                                    });
-                                }
-                            }
+                }
+
+                // The text is only built for the hovered span.
+                let response = response.on_hover_ui_at_pointer(|ui| {
+                    let mut text = format!(
+                        "{} ({:.1})\n{}",
+                        callsite.inner.name(),
+                        Duration::nanoseconds(span.closed - span.opened),
+                        callsite.inner.target()
+                    );
+                    if let (Some(file), Some(line)) =
+                        (&callsite.inner.file(), callsite.inner.line())
+                    {
+                        text.push_str(&format!("\n{}:{}", file, line));
+                    }
+
+                    for (field, value) in callsite.inner.fields().iter().zip(span.values.iter()) {
+                        text.push_str(&format!("\n{} = {}", field, value));
+                    }
+                    ui.label(text);
+                });
+
+                if response.clicked() {
+                    viewer.state.selected_item = Some(SelectedItem::Span {
+                        span_index: thread_span.span,
+                        tape: self.tape_path.clone(),
+                    });
+                }
+            }
+
+            // Events are drawn below the innermost span that was entered when
+            // they were recorded. Where events are closer than a point, only
+            // the selected or most severe one is drawn.
+            let mut depths = HashMap::<(NodeIndex<usize>, usize), usize>::default();
+            let mut markers = BTreeMap::<(i64, usize), usize>::new();
+            for &event_index in thread_events.get(&thread_id).into_iter().flatten() {
+                let event = &events[event_index];
+                let point = (event.timestamp - start) / min_duration.max(1);
+                let depth = event.span.map_or(0, |span| {
+                    let entrance = spans[span].entrances.iter().position(|entrance| {
+                        entrance.thread_id == thread_id
+                            && (entrance.entered..=entrance.exited).contains(&event.timestamp)
+                    });
+                    *depths
+                        .entry((span, entrance.unwrap_or(0)))
+                        .or_insert_with(|| {
+                            event_depth(&loaded_tape.tape, span, thread_id, event.timestamp)
+                        })
+                });
+                let level = callsites[event.callsite_index].level();
+                markers
+                    .entry((point, depth))
+                    .and_modify(|marker| {
+                        if selected_event == Some(event_index)
+                            || (selected_event != Some(*marker)
+                                && level < callsites[events[*marker].callsite_index].level())
+                        {
+                            *marker = event_index;
                         }
+                    })
+                    .or_insert(event_index);
+            }
+
+            for ((_, depth), event_index) in markers {
+                let event = &events[event_index];
+                let callsite = &callsites[event.callsite_index];
+                let level = callsite.level();
+                let size = if level <= tracing::Level::WARN {
+                    0.8
+                } else {
+                    0.5
+                };
+
+                let response = timeline_ui.marker(
+                    depth,
+                    level_color(level),
+                    loaded_tape
+                        .timestamp_to_global_offset(event.timestamp, viewer.global_time_span.start),
+                    size,
+                );
+
+                if selected_event == Some(event_index) {
+                    timeline_ui.highlight(response.rect);
+                }
 
-                        petgraph::visit::Control::<()>::Continue
+                let response = response.on_hover_ui_at_pointer(|ui| {
+                    let mut text = format!("{} {}\n{}", level, callsite.name(), callsite.target());
+                    for (field, value) in callsite.fields().iter().zip(event.values.iter()) {
+                        text.push_str(&format!("\n{} = {}", field, value));
                     }
-                    _ => petgraph::visit::Control::<()>::Continue,
+                    ui.label(text);
+                });
+
+                if response.clicked() {
+                    viewer.state.selected_item = Some(SelectedItem::Event {
+                        tape: self.tape_path.clone(),
+                        event_index,
+                    });
                 }
-            });
+            }
 
             //     let events = if let Some(event) =
             // thread_span_events.get_mut(&threads[i].1) {
@@ -333,11 +498,68 @@ impl TapeTimeline {
             //     }
         });
 
+        viewer.state.link_anchors.extend(link_anchors);
+
+        let mut selected_range = respone.selected_range;
+        let mut visible_range = respone.visible_range;
         if respone.response.clicked() {
             selected_range = None;
         }
+        respone.response.context_menu(|ui| {
+            if ui
+                .add_enabled(
+                    selected_range.is_some(),
+                    egui::Button::new("Zoom to Selection"),
+                )
+                .clicked()
+            {
+                visible_range = selected_range.clone().unwrap();
+                ui.close();
+            }
+            if ui
+                .add_enabled(
+                    selected_range.is_some(),
+                    egui::Button::new("Clear Selection"),
+                )
+                .clicked()
+            {
+                selected_range = None;
+                ui.close();
+            }
+            if ui
+                .add_enabled(
+                    !viewer.state.highlighted_spans.is_empty(),
+                    egui::Button::new("Clear Highlights"),
+                )
+                .clicked()
+            {
+                viewer.state.highlighted_spans.clear();
+                ui.close();
+            }
+            ui.separator();
+            ui.label("Shift-drag to select a range");
+        });
 
+        viewer.state.current_action = match respone.measure_start {
+            Some(from) => Action::Measure { from },
+            None => Action::None,
+        };
         viewer.state.selected_range = selected_range;
-        viewer.state.timeline_range = respone.visible_range;
+        viewer.state.timeline_range = visible_range;
     }
 }
+
+/// Returns the number of spans that were entered on the thread at the
+/// timestamp, counting the span and those of its ancestors that were entered
+/// on the same thread.
+fn event_depth(tape: &Tape, span: NodeIndex<usize>, thread_id: u64, timestamp: i64) -> usize {
+    std::iter::once(span)
+        .chain(tape.ancestors(span))
+        .filter(|span| {
+            tape.spans()[*span].entrances.iter().any(|entrance| {
+                entrance.thread_id == thread_id
+                    && (entrance.entered..=entrance.exited).contains(&timestamp)
+            })
+        })
+        .count()
+}
@@ -1,5 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+use crate::session::Open;
+
 use super::TabViewer;
 
+#[derive(Serialize, Deserialize)]
 pub struct Welcome;
 
 impl Welcome {
@@ -11,14 +16,31 @@ impl Welcome {
         "Welcome".into()
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui, _viewer: &mut TabViewer) {
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &mut TabViewer) {
         ui.heading("Welcome");
         ui.horizontal(|ui| {
             ui.spacing_mut().item_spacing.x = 0.0;
 
-            ui.label("Drop files generated by the tracing tape recorder here to get started.");
+            ui.label(
+                "Drop files generated by the tracing tape recorder or traces in the Trace Event \
+                 Format (.json) here to get started.",
+            );
             // ui.hyperlink_to("tracing tape recorder", "https://docs.rs/tracing-tape-recorder/");
             // ui.label(" here to get started.");
         });
+
+        if viewer.has_last_session && ui.button("Restore Last Session").clicked() {
+            viewer.open = Some(Open::LastSession);
+        }
+
+        if !viewer.recent_files.is_empty() {
+            ui.add_space(8.0);
+            ui.strong("Recent Files");
+            for path in viewer.recent_files {
+                if ui.link(path.to_string_lossy()).clicked() {
+                    viewer.open = Some(Open::Files(vec![path.clone()]));
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,48 @@
+//! Helpers for building small tapes in tests.
+
+use std::{ops::Range, sync::Arc};
+
+use tracing_tape_parser::{Callsite, Metadata, Span, SpanEntrance, Tape, Value};
+
+use crate::state::{LoadedTape, State};
+
+/// A span callsite whose fields have the given names.
+pub fn callsite(name: &str, fields: &[&str]) -> Callsite {
+    Callsite::Span(Metadata {
+        level: tracing::Level::INFO,
+        name: name.into(),
+        target: "test".into(),
+        module_path: "test".into(),
+        file: Some("test.rs".into()),
+        line: None,
+        fields: fields.iter().map(|field| Arc::from(*field)).collect(),
+    })
+}
+
+/// A span that was entered on thread 1 from opening until closing it.
+pub fn span(callsite_index: usize, range: Range<i64>, values: &[Value]) -> Span {
+    Span {
+        opened: range.start,
+        closed: range.end,
+        callsite_index,
+        entrances: Arc::new([SpanEntrance {
+            entered: range.start,
+            exited: range.end,
+            thread_id: 1,
+        }]),
+        values: values.into(),
+    }
+}
+
+/// The state after loading the tapes with the given paths.
+pub fn state(tapes: Vec<(&str, Tape)>) -> State {
+    tapes
+        .into_iter()
+        .map(|(path, tape)| LoadedTape {
+            path: path.into(),
+            tape: Arc::new(tape),
+            time_offset: time::Duration::ZERO,
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
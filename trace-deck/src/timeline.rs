@@ -8,10 +8,15 @@ use egui::{PointerButton, Response};
 pub type Duration = time::Duration;
 pub type TimeRange = std::ops::RangeInclusive<Duration>;
 
+/// Maximum distance in points between the pointer and the edge of an item to
+/// snap the selection to it.
+const SNAP_DISTANCE: f32 = 6.0;
+
 pub struct Timeline {
     id: egui::Id,
     visible_range: TimeRange,
     selected_range: Option<TimeRange>,
+    measure_start: Option<Duration>,
     row_headers: Vec<String>,
     allow_drag: bool,
     background: bool,
@@ -23,6 +28,7 @@ impl Timeline {
             id: egui::Id::new(id_source),
             visible_range,
             selected_range: None,
+            measure_start: None,
             row_headers: Vec::new(),
             allow_drag: true,
             background: true,
@@ -34,6 +40,13 @@ impl Timeline {
         self
     }
 
+    /// The time a range selection started at if one is in progress, e.g.,
+    /// in another timeline.
+    pub fn with_measure_start(mut self, measure_start: Option<Duration>) -> Self {
+        self.measure_start = measure_start;
+        self
+    }
+
     pub fn without_drag(mut self) -> Self {
         self.allow_drag = false;
         self
@@ -110,9 +123,11 @@ impl Timeline {
 
         let item_height = font_id.size + 2.0 * spacing.button_padding.y;
 
+        let modifiers = ui.input(|i| i.modifiers);
+        let mut measure_start = self.measure_start;
         let mut timeline_ui = TimelineUi {
-            // select_mode: ui.input(|i| i.modifiers) == egui::Modifiers::SHIFT,
-            select_mode: false,
+            select_mode: self.allow_drag
+                && (modifiers == egui::Modifiers::SHIFT || measure_start.is_some()),
             ui,
             data_painter: painter.with_clip_rect(data_rect),
             painter,
@@ -121,6 +136,8 @@ impl Timeline {
             data_rect,
             visible_range: self.visible_range.clone(),
             selected_range: self.selected_range.clone(),
+            edges: Vec::new(),
+            ruler: self.allow_drag,
             text_color: egui::Color32::WHITE,
             base_offset: axis_rect.height(),
             item_height,
@@ -132,17 +149,16 @@ impl Timeline {
         };
 
         if self.allow_drag {
-            if response.dragged_by(PointerButton::Primary) {
-                let modifiers = timeline_ui.ui.input(|i| i.modifiers);
-
+            // Dragging with shift selects a range, which is handled once the
+            // items are known to snap to their edges.
+            if response.dragged_by(PointerButton::Primary)
+                && modifiers == egui::Modifiers::NONE
+                && measure_start.is_none()
+            {
                 let dragged_points = response.drag_delta().x;
-                if modifiers == egui::Modifiers::NONE {
-                    let dragged_duration = timeline_ui.dx2dt(dragged_points);
-                    timeline_ui.visible_range = *timeline_ui.visible_range.start()
-                        - dragged_duration
-                        ..=*timeline_ui.visible_range.end() - dragged_duration;
-                } else if modifiers == egui::Modifiers::SHIFT {
-                }
+                let dragged_duration = timeline_ui.dx2dt(dragged_points);
+                timeline_ui.visible_range = *timeline_ui.visible_range.start() - dragged_duration
+                    ..=*timeline_ui.visible_range.end() - dragged_duration;
             }
             if let Some(hover_pos) = response.hover_pos() {
                 let (scroll, zoom) = timeline_ui
@@ -172,12 +188,32 @@ impl Timeline {
             timeline_ui.end_row();
         }
 
+        if self.allow_drag && response.dragged_by(PointerButton::Primary) {
+            let press_origin = timeline_ui.ui.input(|i| i.pointer.press_origin());
+            if measure_start.is_none() && modifiers == egui::Modifiers::SHIFT {
+                measure_start = press_origin.map(|origin| timeline_ui.snap(origin.x));
+            }
+            if let (Some(from), Some(pointer)) = (measure_start, response.interact_pointer_pos()) {
+                let to = timeline_ui.snap(pointer.x);
+                timeline_ui.selected_range = Some(from.min(to)..=from.max(to));
+            }
+        }
+        if response.drag_stopped() {
+            measure_start = None;
+        }
+
+        if timeline_ui.select_mode {
+            if let Some(hover_pos) = response.hover_pos() {
+                timeline_ui.paint_snap_guide(hover_pos.x);
+            }
+        }
         timeline_ui.paint_selection();
 
         TimelineResponse {
             response,
             visible_range: timeline_ui.visible_range,
-            // selected_range: self.selected_range,
+            selected_range: timeline_ui.selected_range,
+            measure_start,
         }
     }
 }
@@ -192,6 +228,10 @@ pub struct TimelineUi<'a> {
     data_rect: egui::Rect,
     visible_range: TimeRange,
     selected_range: Option<TimeRange>,
+    /// Start and end times of the items to snap the selection to.
+    edges: Vec<Duration>,
+    /// Whether to show the duration of the selected range.
+    ruler: bool,
     text_color: egui::Color32,
     base_offset: f32,
     item_height: f32,
@@ -256,6 +296,35 @@ fn axis_tick_width(range: &TimeRange) -> Duration {
     Duration::NANOSECOND
 }
 
+/// The x coordinate of a time on an axis spanning the visible range.
+fn time_to_x(visible_range: &TimeRange, axis: egui::Rangef, t: Duration) -> f32 {
+    let offset = t - *visible_range.start();
+    let rel = offset / (*visible_range.end() - *visible_range.start());
+    axis.min + rel as f32 * axis.span()
+}
+
+/// The time at an x coordinate on an axis spanning the visible range.
+fn x_to_time(visible_range: &TimeRange, axis: egui::Rangef, x: f32) -> Duration {
+    let rel = (x - axis.min) / axis.span();
+    *visible_range.start() + (*visible_range.end() - *visible_range.start()) * rel
+}
+
+/// The time at the x coordinate or the closest of the edges within
+/// [SNAP_DISTANCE] on an axis spanning the visible range.
+fn snap_to_edges(
+    visible_range: &TimeRange,
+    axis: egui::Rangef,
+    edges: &[Duration],
+    x: f32,
+) -> Duration {
+    edges
+        .iter()
+        .map(|edge| (*edge, (time_to_x(visible_range, axis, *edge) - x).abs()))
+        .filter(|(_, distance)| *distance <= SNAP_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or_else(|| x_to_time(visible_range, axis, x), |(edge, _)| edge)
+}
+
 struct TimelineAxisFormat {
     time: Duration,
     tick_width: Duration,
@@ -331,21 +400,28 @@ impl TimelineUi<'_> {
         rel as f32 * self.axis_rect.width()
     }
 
-    fn dx2dt(&self, points: f32) -> Duration {
+    pub fn dx2dt(&self, points: f32) -> Duration {
         let rel = points / self.data_rect.width();
         (*self.visible_range.end() - *self.visible_range.start()) * rel
     }
 
     fn t2x(&self, t: Duration) -> f32 {
-        let offset = t - *self.visible_range.start();
-        let rel = offset / (*self.visible_range.end() - *self.visible_range.start());
-        self.axis_rect.left() + rel as f32 * self.axis_rect.width()
+        time_to_x(&self.visible_range, self.axis_rect.x_range(), t)
     }
 
     fn x2t(&self, x: f32) -> Duration {
-        let rel = (x - self.axis_rect.left()) / self.axis_rect.width();
-        *self.visible_range.start()
-            + (*self.visible_range.end() - *self.visible_range.start()) * rel
+        x_to_time(&self.visible_range, self.axis_rect.x_range(), x)
+    }
+
+    /// The time at the x coordinate or the closest edge of an item within
+    /// [SNAP_DISTANCE].
+    fn snap(&self, x: f32) -> Duration {
+        snap_to_edges(
+            &self.visible_range,
+            self.axis_rect.x_range(),
+            &self.edges,
+            x,
+        )
     }
 
     fn ty2pos(&self, t: Duration, y: f32) -> egui::Pos2 {
@@ -417,13 +493,7 @@ impl TimelineUi<'_> {
             + self.ui.style().spacing.item_spacing.y
     }
 
-    pub fn item(
-        &mut self,
-        level: usize,
-        text: String,
-        color: egui::Color32,
-        span: TimeRange,
-    ) -> Response {
+    fn item_rect(&mut self, level: usize, span: &TimeRange) -> egui::Rect {
         let level_offset = self.level_offset(level);
         if level > self.max_level {
             self.fill_vertical(
@@ -436,10 +506,85 @@ impl TimelineUi<'_> {
         let top = self.rect.top() + level_offset + self.ui.style().spacing.button_padding.y;
         let bottom = top + self.item_height;
 
-        let rect = egui::Rect::from_min_max(
+        egui::Rect::from_min_max(
             egui::Pos2::new(self.t2x(*span.start()), top),
             egui::Pos2::new(self.t2x(*span.end()), bottom),
+        )
+    }
+
+    /// Paints a block that summarizes items too small to be shown
+    /// individually. Unlike [Self::item], blocks do not interact and have no
+    /// text.
+    pub fn block(&mut self, level: usize, color: egui::Color32, span: TimeRange) {
+        let rect = self.item_rect(level, &span);
+        self.data_painter.rect_filled(rect, 0.0, color);
+    }
+
+    /// Outlines an item or marker, e.g., the selected one.
+    pub fn highlight(&mut self, rect: egui::Rect) {
+        let stroke = egui::Stroke::new(2.0, self.text_color);
+        self.data_painter.rect_stroke(
+            rect.expand(1.0),
+            self.ui.style().noninteractive().corner_radius,
+            stroke,
+            egui::StrokeKind::Outside,
+        );
+    }
+
+    /// Makes an item draggable. Unlike the response of [Self::item], whose id
+    /// depends on its time range, the id stays the same while the item moves.
+    pub fn drag_handle(&mut self, id: egui::Id, rect: egui::Rect) -> Response {
+        self.ui
+            .interact(rect, id, egui::Sense::drag())
+            .on_hover_cursor(egui::CursorIcon::ResizeHorizontal)
+    }
+
+    /// Paints a diamond centered at the given time, e.g., for events. The
+    /// size is relative to the item height.
+    pub fn marker(
+        &mut self,
+        level: usize,
+        color: egui::Color32,
+        time: Duration,
+        size: f32,
+    ) -> Response {
+        let rect = self.item_rect(level, &(time..=time));
+        let radius = 0.5 * size * rect.height();
+        let center = rect.center();
+
+        let response = self.ui.interact(
+            egui::Rect::from_center_size(center, egui::Vec2::splat(2.0 * radius)),
+            egui::Id::new((self.current_row, level, time, "marker")),
+            egui::Sense::click(),
         );
+        let stroke_color = if response.hovered() {
+            self.text_color
+        } else {
+            egui::Color32::BLACK
+        };
+        self.data_painter.add(egui::Shape::convex_polygon(
+            vec![
+                center + egui::vec2(0.0, -radius),
+                center + egui::vec2(radius, 0.0),
+                center + egui::vec2(0.0, radius),
+                center + egui::vec2(-radius, 0.0),
+            ],
+            color,
+            egui::Stroke::new(1.0, stroke_color),
+        ));
+
+        response
+    }
+
+    pub fn item(
+        &mut self,
+        level: usize,
+        text: String,
+        color: egui::Color32,
+        span: TimeRange,
+    ) -> Response {
+        let rect = self.item_rect(level, &span);
+        self.edges.extend([*span.start(), *span.end()]);
 
         let response = self.ui.interact(
             rect,
@@ -535,8 +680,58 @@ impl TimelineUi<'_> {
         }
     }
 
+    /// Paints a vertical line where a selection would start or end.
+    fn paint_snap_guide(&mut self, x: f32) {
+        let x = self.t2x(self.snap(x));
+        self.data_painter.vline(
+            x,
+            self.data_rect.y_range(),
+            egui::Stroke::new(1.0, self.text_color.linear_multiply(0.5)),
+        );
+    }
+
+    /// Paints the duration of the selected range as a ruler below the axis.
+    fn paint_ruler(&mut self, selected_range: &TimeRange) {
+        let left = self.t2x(*selected_range.start());
+        let right = self.t2x(*selected_range.end());
+        let stroke = egui::Stroke::new(1.0, self.text_color);
+        for x in [left, right] {
+            self.data_painter.vline(x, self.data_rect.y_range(), stroke);
+        }
+
+        let y = self.data_rect.top() + 0.5 * self.item_height;
+        let tick = 0.25 * self.item_height;
+        self.data_painter.hline(left..=right, y, stroke);
+        for x in [left, right] {
+            self.data_painter.vline(x, y - tick..=y + tick, stroke);
+        }
+
+        let font_id = &self.ui.style().text_styles[&egui::TextStyle::Body];
+        let galley = self.data_painter.layout_no_wrap(
+            format!("{:.3}", *selected_range.end() - *selected_range.start()),
+            font_id.clone(),
+            self.text_color,
+        );
+        let padding = self.ui.style().spacing.button_padding;
+        let center = egui::pos2(
+            (0.5 * (left + right)).clamp(self.data_rect.left(), self.data_rect.right()),
+            y,
+        );
+        let text_rect = egui::Rect::from_center_size(center, galley.size() + 2.0 * padding);
+        self.data_painter.rect_filled(
+            text_rect,
+            self.ui.style().noninteractive().corner_radius,
+            self.ui.style().visuals.extreme_bg_color,
+        );
+        self.data_painter
+            .galley(text_rect.min + padding, galley, self.text_color);
+    }
+
     fn paint_selection(&mut self) {
-        if let Some(selected_range) = &self.selected_range {
+        if let Some(selected_range) = self.selected_range.clone() {
+            if self.ruler {
+                self.paint_ruler(&selected_range);
+            }
             let top = self.rect.top();
             let bottom = self.rect.bottom();
             let left = self.data_rect.left();
@@ -563,4 +758,29 @@ impl TimelineUi<'_> {
 pub struct TimelineResponse {
     pub response: egui::Response,
     pub visible_range: TimeRange,
+    pub selected_range: Option<TimeRange>,
+    /// The time the range selection started at while it is in progress.
+    pub measure_start: Option<Duration>,
+}
+
+#[test]
+fn test_snap_to_edges() {
+    // 10 nanoseconds per point.
+    let visible_range = Duration::ZERO..=Duration::nanoseconds(1000);
+    let axis = egui::Rangef::new(0.0, 100.0);
+    let edges = [Duration::nanoseconds(200), Duration::nanoseconds(240)];
+    let snap = |x| snap_to_edges(&visible_range, axis, &edges, x).whole_nanoseconds();
+
+    // Snaps to the closest edge.
+    assert_eq!(snap(21.0), 200);
+    assert_eq!(snap(23.0), 240);
+    assert_eq!(snap(15.0), 200);
+    assert_eq!(snap(29.5), 240);
+    // Keeps the time under the pointer beyond the snap distance.
+    assert_eq!(snap(13.5), 135);
+    assert_eq!(snap(30.5), 305);
+    assert_eq!(
+        snap_to_edges(&visible_range, axis, &[], 21.0),
+        Duration::nanoseconds(210)
+    );
 }
@@ -16,3 +16,39 @@ impl Iterator for AutoColor {
     }
 }
 
+/// Color for events of the given level, errors and warnings stand out.
+pub fn level_color(level: tracing::Level) -> egui::Color32 {
+    match level {
+        tracing::Level::ERROR => egui::Color32::from_rgb(0xe0, 0x30, 0x30),
+        tracing::Level::WARN => egui::Color32::from_rgb(0xf0, 0xb0, 0x20),
+        tracing::Level::INFO => egui::Color32::from_rgb(0x40, 0xb0, 0x60),
+        tracing::Level::DEBUG => egui::Color32::from_rgb(0x40, 0x80, 0xd0),
+        tracing::Level::TRACE => egui::Color32::from_rgb(0x90, 0x90, 0x90),
+    }
+}
+
+/// Runs the job on the rayon thread pool and requests a repaint once its
+/// result can be received. On the web, the job runs right away as rayon has no
+/// threads to run it on.
+pub fn spawn<T, F>(ctx: &egui::Context, job: F) -> crossbeam_channel::Receiver<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let ctx = ctx.clone();
+        rayon::spawn(move || {
+            // The receiver is gone if the result is no longer needed.
+            let _ = sender.send(job());
+            ctx.request_repaint();
+        });
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = ctx;
+        let _ = sender.send(job());
+    }
+    receiver
+}
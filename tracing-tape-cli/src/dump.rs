@@ -0,0 +1,92 @@
+use std::io::Write;
+
+use crate::records::{self, Record};
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum Format {
+    /// Human readable, one record per line.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Prints the records of a tape file in the order they were written.
+pub fn print(out: &mut impl Write, data: &[u8], format: Format) -> std::io::Result<()> {
+    for record in records::records(data) {
+        let record = record?;
+        match format {
+            Format::Text => text(out, &record)?,
+            Format::Json => {
+                serde_json::to_writer(&mut *out, &json(&record))?;
+                writeln!(out)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn text(out: &mut impl Write, record: &Record) -> std::io::Result<()> {
+    write!(
+        out,
+        "{:>10} {:<14}",
+        record.offset,
+        records::kind_name(record.kind).unwrap_or("Unknown"),
+    )?;
+    for (name, value) in record.fields() {
+        write!(out, " {}={}", name, value)?;
+    }
+    writeln!(out)
+}
+
+fn json(record: &Record) -> serde_json::Value {
+    let kind = match records::kind_name(record.kind) {
+        Some(name) => name.to_lowercase().replace([' ', '-'], "_"),
+        None => "unknown".into(),
+    };
+    let mut object = serde_json::json!({
+        "offset": record.offset,
+        "kind": kind,
+    });
+    for (name, value) in record.fields() {
+        object[name] = value;
+    }
+    object
+}
+
+#[test]
+fn test_dump() {
+    let tape = tracing_tape_parser::test_tape();
+    let mut data = vec![];
+    tape.write(&mut data).unwrap();
+
+    let mut out = vec![];
+    print(&mut out, &data, Format::Json).unwrap();
+    let records = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        records[0],
+        serde_json::json!({
+            "offset": std::mem::size_of::<tracing_tape::intro::Intro>(),
+            "kind": "thread_name",
+            "thread": 1,
+            "name": "main",
+        })
+    );
+    let event_value = records
+        .iter()
+        .find(|record| record["kind"] == "event_value")
+        .unwrap();
+    assert_eq!(event_value["value"], "hello");
+    assert_eq!(records.last().unwrap()["kind"], "no_op");
+
+    let mut out = vec![];
+    print(&mut out, &data, Format::Text).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.lines().count(), records.len());
+    assert!(out.contains(" Span follows   span=3 follows=1\n"));
+}
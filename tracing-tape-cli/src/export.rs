@@ -0,0 +1,109 @@
+use std::{io::Write, path::PathBuf};
+
+use tracing_tape_parser::{export, Tape};
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Format {
+    /// Trace Event Format as used by chrome://tracing and Perfetto.
+    Chrome,
+    /// Folded stacks for flamegraph.pl and inferno, multiple tapes are merged.
+    Folded,
+    /// OTLP/JSON as written by the file exporter of the OpenTelemetry Collector.
+    Otlp,
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum Weight {
+    /// The time spans have been entered.
+    #[default]
+    Busy,
+    /// The time between opening and closing spans.
+    Wall,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct FoldedArgs {
+    /// The time attributed to spans in folded stacks.
+    #[clap(long, value_enum, default_value_t)]
+    weight: Weight,
+
+    /// Includes the values of the field in the frame names of folded stacks.
+    #[clap(long = "field", value_name = "FIELD")]
+    fields: Vec<String>,
+}
+
+pub fn write(
+    out: &mut impl Write,
+    tapes: &[(PathBuf, Tape)],
+    format: Format,
+    folded: FoldedArgs,
+) -> std::io::Result<()> {
+    match format {
+        Format::Chrome => export::chrome::write(&named(tapes), out),
+        Format::Folded => {
+            let options = export::folded::Options {
+                weight: match folded.weight {
+                    Weight::Busy => export::folded::Weight::Busy,
+                    Weight::Wall => export::folded::Weight::Wall,
+                },
+                fields: folded.fields,
+            };
+            match tapes {
+                [(_, tape)] => export::folded::write(tape, &options, out),
+                tapes => {
+                    let merged = Tape::merge(tapes.iter().map(|(_, tape)| tape));
+                    export::folded::write(&merged, &options, out)
+                }
+            }
+        }
+        Format::Otlp => export::otlp::write(&named(tapes), out),
+    }
+}
+
+/// Pairs the tapes with their file names, which are used as process or
+/// service names.
+fn named(tapes: &[(PathBuf, Tape)]) -> Vec<(&str, &Tape)> {
+    tapes
+        .iter()
+        .map(|(path, tape)| {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("tape");
+            (name, tape)
+        })
+        .collect()
+}
+
+#[test]
+fn test_export() {
+    let tapes = [
+        (
+            PathBuf::from("dir/a.tape"),
+            tracing_tape_parser::test_tape(),
+        ),
+        (PathBuf::from("b.tape"), tracing_tape_parser::test_tape()),
+    ];
+    let folded = || FoldedArgs {
+        weight: Weight::Wall,
+        fields: vec![],
+    };
+
+    let mut out = vec![];
+    write(&mut out, &tapes, Format::Chrome, folded()).unwrap();
+    let trace = serde_json::from_slice::<serde_json::Value>(&out).unwrap();
+    let processes = trace["traceEvents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|event| event["name"] == "process_name")
+        .map(|event| event["args"]["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(processes, ["a.tape", "b.tape"]);
+
+    // Folded stacks of multiple tapes are merged.
+    let mut out = vec![];
+    write(&mut out, &tapes, Format::Folded, folded()).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.lines().any(|line| line == "outer;inner 180"));
+}
@@ -0,0 +1,23 @@
+use tracing_tape_parser::Tape;
+
+/// Formats an absolute time given in nanoseconds since the unix epoch.
+pub fn date_time(nanos: i128) -> String {
+    let format = time::macros::format_description!(
+        "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:9] UTC"
+    );
+    time::OffsetDateTime::from_unix_timestamp_nanos(nanos)
+        .ok()
+        .and_then(|date_time| date_time.format(&format).ok())
+        .unwrap_or_else(|| nanos.to_string())
+}
+
+pub fn duration(nanos: i64) -> String {
+    format!("{:.1}", time::Duration::nanoseconds(nanos))
+}
+
+pub fn thread(tape: &Tape, thread_id: u64) -> String {
+    match tape.threads().get(&thread_id) {
+        Some(Some(name)) => name.clone(),
+        _ => format!("{:016x}", thread_id),
+    }
+}
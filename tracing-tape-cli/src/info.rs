@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use tracing_tape_parser::Tape;
+
+use crate::{format, records};
+
+/// Prints a summary of the tape, the record counts are only printed if the
+/// data of the tape file is given.
+pub fn print(
+    out: &mut impl Write,
+    path: &Path,
+    tape: &Tape,
+    data: Option<&[u8]>,
+) -> std::io::Result<()> {
+    let intro = tape.intro();
+    writeln!(out, "{}", path.display())?;
+    writeln!(
+        out,
+        "  Version:      {}.{}",
+        intro.version.major, intro.version.minor
+    )?;
+    writeln!(
+        out,
+        "  Chapter size: {} KiB",
+        (1usize << intro.chapter_size.0) / 1024
+    )?;
+
+    if tape.events().is_empty() && tape.spans().node_count() == 0 {
+        writeln!(out, "  The tape does not contain any events or spans.")?;
+    } else {
+        let range = tape.timestamp_range();
+        let base = tape.timestamp_base();
+        writeln!(
+            out,
+            "  Start:        {}",
+            format::date_time(base + *range.start() as i128)
+        )?;
+        writeln!(
+            out,
+            "  End:          {}",
+            format::date_time(base + *range.end() as i128)
+        )?;
+        writeln!(
+            out,
+            "  Duration:     {}",
+            format::duration(range.end() - range.start())
+        )?;
+    }
+
+    let mut threads = tape.threads().keys().copied().collect::<Vec<_>>();
+    threads.sort_by_key(|thread_id| format::thread(tape, *thread_id));
+    writeln!(out, "  Threads:      {}", threads.len())?;
+    for thread_id in threads {
+        writeln!(out, "    {}", format::thread(tape, thread_id))?;
+    }
+
+    // Traces in the Trace Event Format do not consist of records.
+    let Some(data) = data else {
+        return Ok(());
+    };
+    let mut counts = BTreeMap::<u8, (usize, usize)>::new();
+    for record in records::records(data) {
+        let record = record?;
+        let (count, bytes) = counts.entry(record.kind).or_default();
+        *count += 1;
+        *bytes += record.bytes.len();
+    }
+
+    writeln!(out, "  {:<18}{:>12}{:>12}", "Records:", "Count", "Bytes")?;
+    let unknown = counts
+        .keys()
+        .copied()
+        .filter(|kind| !records::KINDS.contains(kind))
+        .collect::<Vec<_>>();
+    for kind in records::KINDS.into_iter().chain(unknown) {
+        let (count, bytes) = counts.get(&kind).copied().unwrap_or_default();
+        let name = match records::kind_name(kind) {
+            Some(name) => name.to_string(),
+            None => format!("Unknown ({:#04x})", kind),
+        };
+        writeln!(out, "    {:<16}{:>12}{:>12}", name, count, bytes)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_info() {
+    let tape = tracing_tape_parser::test_tape();
+    let mut data = vec![];
+    tape.write(&mut data).unwrap();
+    let mut out = vec![];
+    print(&mut out, Path::new("test.tape"), &tape, Some(&data)).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains("  Duration:     290.0ns\n"));
+    assert!(out.contains("  Threads:      1\n    main\n"));
+    let count = |kind: &str| {
+        let line = out
+            .lines()
+            .find(|line| line.trim_start().starts_with(&format!("{kind}  ")))
+            .unwrap();
+        line.split_whitespace()
+            .rev()
+            .nth(1)
+            .unwrap()
+            .parse::<usize>()
+            .unwrap()
+    };
+    assert_eq!(count("Thread name"), 1);
+    assert_eq!(count("Callsite"), 3);
+    assert_eq!(count("Callsite field"), 3);
+    assert_eq!(count("Event"), 1);
+    assert_eq!(count("Event value"), 1);
+    assert_eq!(count("Span open"), 4);
+    assert_eq!(count("Span enter"), 4);
+    assert_eq!(count("Span exit"), 4);
+    assert_eq!(count("Span close"), 4);
+    // Empty values are not written.
+    assert_eq!(count("Span value"), 2);
+    assert_eq!(count("Span follows"), 1);
+    // The end of the chapter is padded.
+    assert_eq!(count("No-op"), 1);
+
+    let mut out = vec![];
+    print(&mut out, Path::new("test.json"), &tape, None).unwrap();
+    assert!(!String::from_utf8(out).unwrap().contains("Records"));
+}
@@ -0,0 +1,141 @@
+//! Command-line tool for inspecting tape files recorded by the
+//! [tracing-tape-recorder](https://crates.io/crates/tracing-tape-recorder) crate.
+//!
+//! It is meant for environments without a GUI, e.g., remote machines or CI
+//! pipelines. Use [trace-deck](https://crates.io/crates/trace-deck) for an
+//! interactive view of the tapes.
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, Subcommand};
+use tracing_tape_parser::Tape;
+
+mod dump;
+mod export;
+mod format;
+mod info;
+mod records;
+mod stats;
+
+#[derive(Debug, Parser)]
+#[command(name = "tape", version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Shows the version, time range, threads, and record counts of tapes.
+    Info { tape_files: Vec<PathBuf> },
+
+    /// Prints the records of a tape in the order they were written.
+    Dump {
+        tape_file: PathBuf,
+
+        #[clap(short, long, value_enum, default_value_t)]
+        format: dump::Format,
+    },
+
+    /// Prints duration statistics for each span callsite.
+    Stats { tape_file: PathBuf },
+
+    /// Converts tapes into a format understood by other tools.
+    Export {
+        #[clap(required = true)]
+        tape_files: Vec<PathBuf>,
+
+        #[clap(short, long, value_enum)]
+        format: export::Format,
+
+        /// Output file, the result is written to stdout if omitted.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        #[clap(flatten)]
+        folded: export::FoldedArgs,
+    },
+}
+
+/// Returns whether the file is a trace in the Trace Event Format rather than a
+/// tape.
+fn is_trace_event_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+/// Parses the data of a tape file or a trace in the Trace Event Format.
+fn parse_tape(path: &Path, data: &[u8]) -> std::io::Result<Tape> {
+    if is_trace_event_file(path) {
+        tracing_tape_parser::import::chrome::read(data)
+    } else {
+        Tape::parse(data)
+    }
+}
+
+/// Reads a tape file or a trace in the Trace Event Format (`.json`).
+fn read_tape(path: &Path) -> std::io::Result<Tape> {
+    parse_tape(path, &std::fs::read(path)?)
+}
+
+fn run(command: Command, out: &mut impl Write) -> std::io::Result<()> {
+    match command {
+        Command::Info { tape_files } => {
+            for (index, path) in tape_files.iter().enumerate() {
+                if index > 0 {
+                    writeln!(out)?;
+                }
+                let data = std::fs::read(path)?;
+                let records = (!is_trace_event_file(path)).then_some(&data[..]);
+                info::print(out, path, &parse_tape(path, &data)?, records)?;
+            }
+        }
+        Command::Dump { tape_file, format } => {
+            if is_trace_event_file(&tape_file) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "traces in the Trace Event Format do not contain records",
+                ));
+            }
+            dump::print(out, &std::fs::read(&tape_file)?, format)?;
+        }
+        Command::Stats { tape_file } => {
+            stats::print(out, &read_tape(&tape_file)?)?;
+        }
+        Command::Export {
+            tape_files,
+            format,
+            output,
+            folded,
+        } => {
+            let tapes = tape_files
+                .into_iter()
+                .map(|path| {
+                    let tape = read_tape(&path)?;
+                    Ok((path, tape))
+                })
+                .collect::<std::io::Result<Vec<_>>>()?;
+            match output {
+                Some(output) => {
+                    let mut file = BufWriter::new(std::fs::File::create(output)?);
+                    export::write(&mut file, &tapes, format, folded)?;
+                }
+                None => export::write(out, &tapes, format, folded)?,
+            }
+        }
+    }
+    out.flush()
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let mut out = BufWriter::new(std::io::stdout().lock());
+
+    match run(args.command, &mut out) {
+        // Output piped into e.g. `head` should not result in an error.
+        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
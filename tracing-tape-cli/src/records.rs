@@ -0,0 +1,309 @@
+//! Walks the raw records of a tape file without parsing them into spans and
+//! events.
+
+use serde_json::{json, Value};
+use tracing_tape::{
+    intro::Intro,
+    record::{
+        field_type, parent_kind, record_kind, CallsiteFieldRecord, CallsiteRecord, EventRecord,
+        EventValueRecord, RecordHeader, SpanCloseRecord, SpanEnterRecord, SpanExitRecord,
+        SpanFollowsRecord, SpanOpenRecord, SpanOpenRecord2, SpanValueRecord, ThreadNameRecord,
+    },
+};
+use zerocopy::FromBytes;
+
+/// The record kinds in the order they are listed in.
+pub const KINDS: [u8; 12] = [
+    record_kind::NOOP,
+    record_kind::THREAD_NAME,
+    record_kind::CALLSITE,
+    record_kind::CALLSITE_FIELD,
+    record_kind::EVENT,
+    record_kind::EVENT_VALUE,
+    record_kind::SPAN_OPEN,
+    record_kind::SPAN_ENTER,
+    record_kind::SPAN_EXIT,
+    record_kind::SPAN_CLOSE,
+    record_kind::SPAN_VALUE,
+    record_kind::SPAN_FOLLOWS,
+];
+
+/// Returns the name of a known record kind.
+pub fn kind_name(kind: u8) -> Option<&'static str> {
+    let name = match kind {
+        record_kind::NOOP => "No-op",
+        record_kind::THREAD_NAME => "Thread name",
+        record_kind::CALLSITE => "Callsite",
+        record_kind::CALLSITE_FIELD => "Callsite field",
+        record_kind::EVENT => "Event",
+        record_kind::EVENT_VALUE => "Event value",
+        record_kind::SPAN_OPEN => "Span open",
+        record_kind::SPAN_ENTER => "Span enter",
+        record_kind::SPAN_EXIT => "Span exit",
+        record_kind::SPAN_CLOSE => "Span close",
+        record_kind::SPAN_VALUE => "Span value",
+        record_kind::SPAN_FOLLOWS => "Span follows",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// A record and its offset in the tape file.
+///
+/// Consecutive no-op records, i.e., the padding at the end of a chapter, are
+/// combined into a single record.
+pub struct Record<'a> {
+    pub offset: usize,
+    pub kind: u8,
+    /// The bytes of the record including its header.
+    pub bytes: &'a [u8],
+}
+
+/// Iterates over the records of a tape file.
+pub fn records(data: &[u8]) -> impl Iterator<Item = std::io::Result<Record<'_>>> {
+    let mut offset = std::mem::size_of::<Intro>().min(data.len());
+    std::iter::from_fn(move || {
+        let remaining = &data[offset..];
+        let kind = *remaining.first()?;
+        let len = if kind == record_kind::NOOP {
+            remaining
+                .iter()
+                .position(|byte| *byte != record_kind::NOOP)
+                .unwrap_or(remaining.len())
+        } else {
+            match RecordHeader::ref_from_prefix(remaining) {
+                Some(header) if (1..=remaining.len()).contains(&(header.len.get() as usize)) => {
+                    header.len.get() as usize
+                }
+                _ => {
+                    offset = data.len();
+                    return Some(Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "truncated record at offset {}",
+                            data.len() - remaining.len()
+                        ),
+                    )));
+                }
+            }
+        };
+
+        let record = Record {
+            offset,
+            kind,
+            bytes: &remaining[..len],
+        };
+        offset += len;
+        Some(Ok(record))
+    })
+}
+
+impl Record<'_> {
+    /// Returns the fields of the record by name.
+    ///
+    /// Ids are written as they are stored in the tape, they are only unique
+    /// within the tape.
+    pub fn fields(&self) -> Vec<(&'static str, Value)> {
+        let bytes = self.bytes;
+        let text = |offset: usize, len: usize| -> Value {
+            bytes
+                .get(offset..offset + len)
+                .map(|text| String::from_utf8_lossy(text).into())
+                .unwrap_or(Value::Null)
+        };
+
+        match self.kind {
+            record_kind::NOOP => vec![("len", bytes.len().into())],
+            record_kind::THREAD_NAME => {
+                let Some(record) = ThreadNameRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                vec![
+                    ("thread", record.thread_id.get().into()),
+                    (
+                        "name",
+                        text(
+                            std::mem::size_of::<ThreadNameRecord>(),
+                            record.name_len.get() as usize,
+                        ),
+                    ),
+                ]
+            }
+            record_kind::CALLSITE => {
+                let Some(record) = CallsiteRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                let mut offset = std::mem::size_of::<CallsiteRecord>();
+                let mut next = |len: u16| {
+                    let value = text(offset, len as usize);
+                    offset += len as usize;
+                    value
+                };
+                vec![
+                    ("id", record.id.get().into()),
+                    (
+                        "callsite_kind",
+                        match record.info.kind() {
+                            Some(kind) if kind.is_span() => "span".into(),
+                            Some(_) => "event".into(),
+                            None => Value::Null,
+                        },
+                    ),
+                    (
+                        "level",
+                        record
+                            .info
+                            .level()
+                            .map_or(Value::Null, |level| level.as_str().into()),
+                    ),
+                    ("name", next(record.name_len.get())),
+                    ("target", next(record.target_len.get())),
+                    ("module_path", next(record.module_path_len.get())),
+                    ("file", next(record.file_len.get())),
+                    ("line", record.line.get().into()),
+                    ("field_count", record.field_count.get().into()),
+                ]
+            }
+            record_kind::CALLSITE_FIELD => {
+                let Some(record) = CallsiteFieldRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                vec![
+                    ("callsite", record.callsite_id.get().into()),
+                    ("field", record.field_id.get().into()),
+                    (
+                        "name",
+                        text(
+                            std::mem::size_of::<CallsiteFieldRecord>(),
+                            record.field_name_len.get() as usize,
+                        ),
+                    ),
+                ]
+            }
+            record_kind::EVENT => {
+                let Some(record) = EventRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                vec![
+                    ("timestamp", record.timestamp.get().into()),
+                    ("callsite", record.callsite_id.get().into()),
+                    ("thread", record.thread_id.get().into()),
+                    ("value_count", record.value_count.get().into()),
+                ]
+            }
+            record_kind::EVENT_VALUE => {
+                let Some(record) = EventValueRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                vec![
+                    ("thread", record.thread_id.get().into()),
+                    ("field", record.field_id.get().into()),
+                    (
+                        "value",
+                        value(
+                            record.kind,
+                            &bytes[std::mem::size_of::<EventValueRecord>()..],
+                        ),
+                    ),
+                ]
+            }
+            record_kind::SPAN_OPEN => {
+                // Tapes of older versions do not store the kind of the parent.
+                let (record, parent_kind) = match SpanOpenRecord2::ref_from_prefix(bytes) {
+                    Some(record) if bytes.len() >= std::mem::size_of::<SpanOpenRecord2>() => {
+                        (&record.span_open_record, record.parent_kind)
+                    }
+                    _ => match SpanOpenRecord::ref_from_prefix(bytes) {
+                        Some(record) => (record, parent_kind::EXPLICIT),
+                        None => return vec![],
+                    },
+                };
+                let parent = match parent_kind {
+                    parent_kind::ROOT => "root".into(),
+                    parent_kind::CURRENT => "current".into(),
+                    _ => record.parent_id.get().into(),
+                };
+                vec![
+                    ("id", record.id.get().into()),
+                    ("parent", parent),
+                    ("callsite", record.callsite_id.get().into()),
+                    ("timestamp", record.timestamp.get().into()),
+                ]
+            }
+            record_kind::SPAN_ENTER => {
+                let Some(record) = SpanEnterRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                vec![
+                    ("id", record.id.get().into()),
+                    ("timestamp", record.timestamp.get().into()),
+                    ("thread", record.thread_id.get().into()),
+                ]
+            }
+            record_kind::SPAN_EXIT => {
+                let Some(record) = SpanExitRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                vec![
+                    ("id", record.id.get().into()),
+                    ("timestamp", record.timestamp.get().into()),
+                ]
+            }
+            record_kind::SPAN_CLOSE => {
+                let Some(record) = SpanCloseRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                vec![
+                    ("id", record.id.get().into()),
+                    ("timestamp", record.timestamp.get().into()),
+                ]
+            }
+            record_kind::SPAN_VALUE => {
+                let Some(record) = SpanValueRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                vec![
+                    ("span", record.span_id.get().into()),
+                    ("field", record.field_id.get().into()),
+                    (
+                        "value",
+                        value(
+                            record.kind,
+                            &bytes[std::mem::size_of::<SpanValueRecord>()..],
+                        ),
+                    ),
+                ]
+            }
+            record_kind::SPAN_FOLLOWS => {
+                let Some(record) = SpanFollowsRecord::ref_from_prefix(bytes) else {
+                    return vec![];
+                };
+                vec![
+                    ("span", record.span_id.get().into()),
+                    ("follows", record.follows_id.get().into()),
+                ]
+            }
+            _ => vec![("len", bytes.len().into())],
+        }
+    }
+}
+
+/// Decodes the value of a span or event value record.
+fn value(kind: u8, data: &[u8]) -> Value {
+    fn bytes<const N: usize>(data: &[u8]) -> Option<[u8; N]> {
+        data.get(..N)?.try_into().ok()
+    }
+
+    let value = match kind {
+        field_type::BOOL => data.first().map(|value| json!(*value != 0)),
+        field_type::I64 => bytes(data).map(|value| json!(i64::from_le_bytes(value))),
+        field_type::U64 => bytes(data).map(|value| json!(u64::from_le_bytes(value))),
+        // 128 bit integers do not fit into JSON numbers.
+        field_type::I128 => bytes(data).map(|value| json!(i128::from_le_bytes(value).to_string())),
+        field_type::U128 => bytes(data).map(|value| json!(u128::from_le_bytes(value).to_string())),
+        field_type::F64 => bytes(data).map(|value| json!(f64::from_le_bytes(value))),
+        field_type::STR | field_type::ERROR => Some(String::from_utf8_lossy(data).into()),
+        _ => None,
+    };
+    value.unwrap_or(Value::Null)
+}
@@ -0,0 +1,96 @@
+use std::io::Write;
+
+use tracing_tape_parser::{percentile, Tape};
+
+use crate::format;
+
+struct SpanStatistics<'a> {
+    name: &'a str,
+    target: &'a str,
+    total: i64,
+    /// Sorted durations of all spans of the callsite.
+    durations: Vec<i64>,
+}
+
+pub fn print(out: &mut impl Write, tape: &Tape) -> std::io::Result<()> {
+    let mut statistics = tape
+        .callsites()
+        .iter()
+        .map(|callsite| SpanStatistics {
+            name: callsite.name(),
+            target: callsite.target(),
+            total: 0,
+            durations: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+
+    for span in tape.spans().node_weights() {
+        let duration = span.closed - span.opened;
+        let statistics = &mut statistics[span.callsite_index];
+        statistics.total += duration;
+        statistics.durations.push(duration);
+    }
+
+    let mut statistics = statistics
+        .into_iter()
+        .filter(|statistics| !statistics.durations.is_empty())
+        .collect::<Vec<_>>();
+    statistics.sort_by_key(|statistics| std::cmp::Reverse(statistics.total));
+
+    let name_width = statistics
+        .iter()
+        .map(|statistics| statistics.name.len() + statistics.target.len() + 3)
+        .max()
+        .unwrap_or(0)
+        .max("Callsite".len());
+
+    writeln!(
+        out,
+        "{:<name_width$} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "Callsite", "Count", "Total", "Min", "P50", "P99", "Max",
+    )?;
+    for mut statistics in statistics {
+        statistics.durations.sort_unstable();
+        writeln!(
+            out,
+            "{:<name_width$} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            format!("{} ({})", statistics.name, statistics.target),
+            statistics.durations.len(),
+            format::duration(statistics.total),
+            format::duration(statistics.durations[0]),
+            format::duration(percentile(&statistics.durations, 0.5)),
+            format::duration(percentile(&statistics.durations, 0.99)),
+            format::duration(*statistics.durations.last().unwrap()),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_stats() {
+    let tape = tracing_tape_parser::test_tape();
+    let mut out = vec![];
+    print(&mut out, &tape).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    let rows = out
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows[0],
+        ["Callsite", "Count", "Total", "Min", "P50", "P99", "Max"]
+    );
+    // The callsite with the largest total comes first, the median of an even
+    // number of spans is the upper one of the two middle spans.
+    assert_eq!(
+        rows[1],
+        ["outer", "(test)", "2", "190.0ns", "90.0ns", "100.0ns", "100.0ns", "100.0ns"]
+    );
+    assert_eq!(
+        rows[2],
+        ["inner", "(test)", "2", "90.0ns", "40.0ns", "50.0ns", "50.0ns", "50.0ns"]
+    );
+    assert_eq!(rows.len(), 3);
+}
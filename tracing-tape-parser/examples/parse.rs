@@ -6,7 +6,7 @@ fn main() {
     println!("Read file in {:?}", now.elapsed());
 
     let now = std::time::Instant::now();
-    let tape = Tape::parse(&file);
+    let tape = Tape::parse(&file).unwrap();
     println!("Parsed tape in {:?}", now.elapsed());
 
     println!("Duration: {:?}", tape.timestamp_range());
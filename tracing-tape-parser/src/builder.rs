@@ -0,0 +1,155 @@
+use petgraph::graph::NodeIndex;
+use tracing_tape::intro::Intro;
+
+use crate::{index::SpanIndex, Callsite, Event, Span, Tape, TapeData};
+
+/// Incrementally constructs a [Tape] from data that does not originate from a
+/// tape file, e.g., traces in other formats.
+///
+/// The values of spans and events must be aligned with the fields of their
+/// callsite, i.e., contain one value for each field and [Value::Empty] for
+/// fields that have not been recorded.
+///
+/// ```
+/// use std::sync::Arc;
+/// use tracing_tape_parser::{Callsite, Event, Metadata, Span, SpanEntrance, TapeBuilder, Value};
+///
+/// let metadata = Metadata {
+///     level: tracing::Level::INFO,
+///     name: "request".into(),
+///     target: "server".into(),
+///     module_path: "server".into(),
+///     file: None,
+///     line: None,
+///     fields: Arc::new(["path".into()]),
+/// };
+///
+/// let mut builder = TapeBuilder::new(20, 0);
+/// let callsite_index = builder.callsite(Callsite::Span(metadata));
+/// builder.thread(1, Some("main".into()));
+/// builder.span(
+///     None,
+///     Span {
+///         opened: 0,
+///         closed: 1_000,
+///         callsite_index,
+///         entrances: Arc::new([SpanEntrance { entered: 0, exited: 1_000, thread_id: 1 }]),
+///         values: Arc::new([Value::String("/".into())]),
+///     },
+/// );
+/// let tape = builder.finish();
+/// assert_eq!(tape.spans().node_count(), 1);
+/// ```
+///
+/// [Value::Empty]: crate::Value::Empty
+pub struct TapeBuilder {
+    intro: Intro,
+    data: TapeData,
+}
+
+impl TapeBuilder {
+    /// Creates an empty tape with timestamps relative to `timestamp_base`
+    /// (nanoseconds since the unix epoch). The chapter size is only used when
+    /// the tape is written.
+    pub fn new(chapter_size: u8, timestamp_base: i128) -> Self {
+        Self {
+            intro: Intro::new(chapter_size, timestamp_base),
+            data: TapeData {
+                min_timestamp: i64::MAX,
+                max_timestamp: i64::MIN,
+                callsites: Vec::new(),
+                events: Vec::new(),
+                spans: Default::default(),
+                root_spans: Vec::new(),
+                follows_from: Vec::new(),
+                threads: Default::default(),
+                index: Default::default(),
+            },
+        }
+    }
+
+    /// Adds a callsite and returns its index.
+    pub fn callsite(&mut self, callsite: Callsite) -> usize {
+        self.data.callsites.push(callsite);
+        self.data.callsites.len() - 1
+    }
+
+    /// Adds a span as a child of `parent` or as a root span if `parent` is `None`.
+    pub fn span(&mut self, parent: Option<NodeIndex<usize>>, span: Span) -> NodeIndex<usize> {
+        self.timestamp(span.opened);
+        self.timestamp(span.closed);
+        for entrance in span.entrances.iter() {
+            self.thread(entrance.thread_id, None);
+        }
+
+        let index = self.data.spans.add_node(span);
+        if let Some(parent) = parent {
+            self.data.spans.add_edge(parent, index, ());
+        } else {
+            self.data.root_spans.push(index);
+        }
+        index
+    }
+
+    /// Records that `span` follows from `follows`.
+    pub fn follows_from(&mut self, span: NodeIndex<usize>, follows: NodeIndex<usize>) {
+        self.data.follows_from.push((span, follows));
+    }
+
+    /// Adds an event, events do not need to be added in chronological order.
+    pub fn event(&mut self, event: Event) {
+        self.timestamp(event.timestamp);
+        self.thread(event.thread_id, None);
+        self.data.events.push(event);
+    }
+
+    /// Registers a thread, the name is only updated if one is given.
+    pub fn thread(&mut self, thread_id: u64, name: Option<String>) {
+        let thread_name = self.data.threads.entry(thread_id).or_insert(None);
+        if name.is_some() {
+            *thread_name = name;
+        }
+    }
+
+    /// Sets the name of a thread if it has been registered before.
+    pub fn name_thread(&mut self, thread_id: u64, name: &str) {
+        if let Some(thread_name) = self.data.threads.get_mut(&thread_id) {
+            *thread_name = Some(name.to_string());
+        }
+    }
+
+    /// Moves the timestamp base to the earliest timestamp, such that all timestamps of the tape
+    /// start at zero while their absolute time stays the same.
+    pub fn rebase(&mut self) {
+        let offset = self.data.min_timestamp;
+        if offset == i64::MAX || offset == 0 {
+            return;
+        }
+
+        self.intro.timestamp_base = (self.intro.timestamp_base.get() + offset as i128).into();
+        self.data.min_timestamp -= offset;
+        self.data.max_timestamp -= offset;
+        for span in self.data.spans.node_weights_mut() {
+            *span = span.shifted(-offset);
+        }
+        for event in &mut self.data.events {
+            event.timestamp -= offset;
+        }
+    }
+
+    fn timestamp(&mut self, timestamp: i64) {
+        self.data.min_timestamp = self.data.min_timestamp.min(timestamp);
+        self.data.max_timestamp = self.data.max_timestamp.max(timestamp);
+    }
+
+    /// Returns the tape with all events sorted by their timestamp.
+    pub fn finish(mut self) -> Tape {
+        self.data.events.sort_by_key(|event| event.timestamp);
+        self.data.index = SpanIndex::new(&self.data.spans);
+
+        Tape {
+            intro: self.intro,
+            data: self.data,
+        }
+    }
+}
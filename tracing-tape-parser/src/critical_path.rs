@@ -0,0 +1,169 @@
+use std::cmp::Reverse;
+
+use ahash::{HashMap, HashSet};
+use petgraph::graph::NodeIndex;
+
+use crate::Tape;
+
+/// A part of the critical path during which the path was in `span` itself
+/// rather than in one of its children or the spans that follow from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriticalPathSegment {
+    pub span: NodeIndex<usize>,
+    pub start: i64,
+    pub end: i64,
+    /// The time the span was entered during the segment. For the rest of the
+    /// segment it was idle, e.g., waiting for I/O.
+    pub busy: i64,
+}
+
+impl CriticalPathSegment {
+    pub fn duration(&self) -> i64 {
+        self.end - self.start
+    }
+}
+
+impl Tape {
+    /// Computes the chain of spans that determined when a span closed.
+    ///
+    /// Starting at the end of the span, the path continues in the child or
+    /// the span following from it that closed last, follows that span back
+    /// to where it opened, and then continues with the next one. Time that
+    /// is not covered by such spans is attributed to the span itself. The
+    /// segments are in chronological order and cover the whole span.
+    pub fn critical_path(&self, span: NodeIndex<usize>) -> Vec<CriticalPathSegment> {
+        let mut followers = HashMap::<NodeIndex<usize>, Vec<NodeIndex<usize>>>::default();
+        for (span, follows) in self.follows_from() {
+            followers.entry(*follows).or_default().push(*span);
+        }
+
+        let mut segments = Vec::new();
+        let mut visited = HashSet::default();
+        self.critical_path_until(
+            span,
+            self.spans()[span].closed,
+            &followers,
+            &mut visited,
+            &mut segments,
+        );
+        segments.reverse();
+        segments
+    }
+
+    /// Adds the segments of the span up to `end` in reverse chronological
+    /// order.
+    fn critical_path_until(
+        &self,
+        span: NodeIndex<usize>,
+        end: i64,
+        followers: &HashMap<NodeIndex<usize>, Vec<NodeIndex<usize>>>,
+        visited: &mut HashSet<NodeIndex<usize>>,
+        segments: &mut Vec<CriticalPathSegment>,
+    ) {
+        visited.insert(span);
+        let spans = self.spans();
+        let opened = spans[span].opened;
+        let segment = |start: i64, end: i64, segments: &mut Vec<CriticalPathSegment>| {
+            if start < end {
+                let busy = spans[span]
+                    .entrances
+                    .iter()
+                    .map(|entrance| (entrance.exited.min(end) - entrance.entered.max(start)).max(0))
+                    .sum();
+                segments.push(CriticalPathSegment {
+                    span,
+                    start,
+                    end,
+                    busy,
+                });
+            }
+        };
+
+        let mut candidates = spans
+            .neighbors(span)
+            .chain(followers.get(&span).into_iter().flatten().copied())
+            .collect::<Vec<_>>();
+        candidates.sort_unstable_by_key(|candidate| Reverse(spans[*candidate].closed));
+
+        // The end only decreases, candidates that opened after it are never
+        // part of the path.
+        let mut end = end.min(spans[span].closed);
+        for candidate in candidates {
+            if end <= opened {
+                break;
+            }
+            if spans[candidate].opened >= end || visited.contains(&candidate) {
+                continue;
+            }
+            let candidate_end = spans[candidate].closed.min(end);
+            segment(candidate_end, end, segments);
+            self.critical_path_until(candidate, candidate_end, followers, visited, segments);
+            end = spans[candidate].opened;
+        }
+        segment(opened, end, segments);
+    }
+}
+
+#[test]
+fn test_critical_path() {
+    use std::sync::Arc;
+
+    use crate::{Callsite, Metadata, Span, SpanEntrance, TapeBuilder};
+
+    let metadata = Metadata {
+        level: tracing::Level::INFO,
+        name: "work".into(),
+        target: "test".into(),
+        module_path: "test".into(),
+        file: None,
+        line: None,
+        fields: Arc::new([]),
+    };
+    let span = |opened, closed, entrances: &[(i64, i64)], thread_id| Span {
+        opened,
+        closed,
+        callsite_index: 0,
+        entrances: entrances
+            .iter()
+            .map(|(entered, exited)| SpanEntrance {
+                entered: *entered,
+                exited: *exited,
+                thread_id,
+            })
+            .collect(),
+        values: Arc::new([]),
+    };
+
+    let mut builder = TapeBuilder::new(16, 0);
+    builder.callsite(Callsite::Span(metadata));
+    let root = builder.span(None, span(0, 100, &[(0, 5), (95, 100)], 1));
+    let first = builder.span(Some(root), span(10, 40, &[(10, 40)], 1));
+    let second = builder.span(Some(root), span(20, 60, &[(20, 60)], 2));
+    // Spawned by the root, e.g., a task on another thread.
+    let spawned = builder.span(None, span(50, 95, &[(50, 95)], 3));
+    builder.follows_from(spawned, root);
+    // Opened after the root closed, i.e., not on the path.
+    let late = builder.span(None, span(100, 120, &[(100, 120)], 3));
+    builder.follows_from(late, root);
+    let tape = builder.finish();
+
+    let path = tape
+        .critical_path(root)
+        .into_iter()
+        .map(|segment| (segment.span, segment.start, segment.end, segment.busy))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        path,
+        vec![
+            (root, 0, 10, 5),
+            (first, 10, 20, 10),
+            (second, 20, 50, 30),
+            (spawned, 50, 95, 45),
+            (root, 95, 100, 5),
+        ]
+    );
+
+    let path = tape.critical_path(first);
+    assert_eq!(path.len(), 1);
+    assert_eq!(path[0].duration(), 30);
+}
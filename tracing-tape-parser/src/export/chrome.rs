@@ -0,0 +1,299 @@
+//! Export to the [Trace Event Format] used by `chrome://tracing` and
+//! [Perfetto](https://ui.perfetto.dev).
+//!
+//! Each tape becomes a process and each thread of a tape becomes a thread of
+//! that process. Every entrance of a span is exported as a complete (`X`)
+//! event, events are exported as instant (`i`) events, and follows-from links
+//! are exported as flow events between the linked spans.
+//!
+//! [Trace Event Format]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+
+use std::io::Write;
+
+use ahash::HashMap;
+use serde_json::json;
+
+use crate::Tape;
+
+use super::{json_fields, message};
+
+/// Key in `otherData` that contains the absolute time in nanoseconds since
+/// the unix epoch that the timestamps of the trace are relative to.
+pub(crate) const START_TIME_KEY: &str = "startTimeUnixNano";
+
+/// Thread id used for spans that have never been entered.
+const UNENTERED_TID: usize = 0;
+
+/// Name and category of the flow events of follows-from links.
+pub(crate) const FLOW_NAME: &str = "follows_from";
+
+/// Writes the given tapes as a single trace in the Trace Event Format.
+///
+/// Each tape is paired with the name of its process, e.g., the file name of
+/// the tape. The tapes keep their absolute time relative to each other.
+///
+/// ```no_run
+/// # let tape = tracing_tape_parser::TapeBuilder::new(16, 0).finish();
+/// let file = std::fs::File::create("trace.json")?;
+/// tracing_tape_parser::export::chrome::write(&[("my-app", &tape)], std::io::BufWriter::new(file))?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn write<W: Write>(tapes: &[(&str, &Tape)], writer: W) -> std::io::Result<()> {
+    let mut writer = TraceWriter {
+        writer,
+        first: true,
+    };
+    writer.writer.write_all(b"{\"traceEvents\":[")?;
+
+    let start_time = tapes
+        .iter()
+        .map(|(_, tape)| tape.timestamp_base() + *tape.timestamp_range().start() as i128)
+        .min()
+        .unwrap_or(0);
+
+    for (index, (name, tape)) in tapes.iter().enumerate() {
+        let pid = index + 1;
+        // Timestamps are given in microseconds.
+        let ts = |timestamp: i64| {
+            (tape.timestamp_base() + timestamp as i128 - start_time) as f64 / 1000.0
+        };
+
+        writer.event(json!({
+            "ph": "M",
+            "name": "process_name",
+            "pid": pid,
+            "args": { "name": name },
+        }))?;
+
+        // Thread ids are hashes that exceed the integer precision of most
+        // trace viewers, so they are replaced by small numbers.
+        let mut threads = tape.threads().keys().copied().collect::<Vec<_>>();
+        threads.sort_unstable();
+        let tids = threads
+            .iter()
+            .enumerate()
+            .map(|(index, thread_id)| (*thread_id, index + 1))
+            .collect::<HashMap<_, _>>();
+        for thread_id in threads {
+            let name = match &tape.threads()[&thread_id] {
+                Some(name) => name.clone(),
+                None => format!("{:016x}", thread_id),
+            };
+            writer.event(json!({
+                "ph": "M",
+                "name": "thread_name",
+                "pid": pid,
+                "tid": tids[&thread_id],
+                "args": { "name": name },
+            }))?;
+        }
+        if tape
+            .spans()
+            .node_weights()
+            .any(|span| span.entrances.is_empty())
+        {
+            writer.event(json!({
+                "ph": "M",
+                "name": "thread_name",
+                "pid": pid,
+                "tid": UNENTERED_TID,
+                "args": { "name": "Not entered" },
+            }))?;
+        }
+
+        for span in tape.spans().node_weights() {
+            let callsite = &tape.callsites()[span.callsite_index];
+            let args = json_fields(callsite, &span.values);
+            let mut complete = |timestamp: i64, duration: i64, tid: usize| {
+                writer.event(json!({
+                    "ph": "X",
+                    "name": callsite.name(),
+                    "cat": callsite.target(),
+                    "ts": ts(timestamp),
+                    "dur": duration as f64 / 1000.0,
+                    "pid": pid,
+                    "tid": tid,
+                    "args": args,
+                }))
+            };
+
+            if span.entrances.is_empty() {
+                complete(span.opened, span.closed - span.opened, UNENTERED_TID)?;
+            }
+            for entrance in span.entrances.iter() {
+                complete(
+                    entrance.entered,
+                    entrance.exited - entrance.entered,
+                    tids[&entrance.thread_id],
+                )?;
+            }
+        }
+
+        for event in tape.events() {
+            let callsite = &tape.callsites()[event.callsite_index];
+            let message = message(callsite, &event.values);
+            let mut args = json_fields(callsite, &event.values);
+            args.insert("level".into(), callsite.level().as_str().into());
+
+            writer.event(json!({
+                "ph": "i",
+                "s": "t",
+                "name": message.as_deref().unwrap_or(callsite.name()),
+                "cat": callsite.target(),
+                "ts": ts(event.timestamp),
+                "pid": pid,
+                "tid": tids[&event.thread_id],
+                "args": args,
+            }))?;
+        }
+
+        // Flow events are bound to the enclosing slices, i.e., they start at
+        // the last entrance of the cause and end at the first entrance of the
+        // span that follows from it.
+        for (flow_id, (span, follows)) in tape.follows_from().iter().enumerate() {
+            let (span, follows) = (&tape.spans()[*span], &tape.spans()[*follows]);
+            let (Some(start), Some(end)) = (follows.entrances.last(), span.entrances.first())
+            else {
+                continue;
+            };
+            // Both ends of a flow are paired by their id, category, and name,
+            // so neither may depend on the span.
+            let flow = |phase: &str, timestamp: i64, thread_id: u64| {
+                json!({
+                    "ph": phase,
+                    "id": format!("{}:{}", pid, flow_id),
+                    "name": FLOW_NAME,
+                    "cat": FLOW_NAME,
+                    "ts": ts(timestamp),
+                    "pid": pid,
+                    "tid": tids[&thread_id],
+                })
+            };
+
+            writer.event(flow("s", start.entered, start.thread_id))?;
+            let mut end = flow("f", end.entered, end.thread_id);
+            end["bp"] = "e".into();
+            writer.event(end)?;
+        }
+    }
+
+    // Timestamps are relative to the start of the trace, its absolute time is
+    // stored separately to be able to restore it when importing the trace.
+    write!(
+        writer.writer,
+        "],\"displayTimeUnit\":\"ns\",\"otherData\":{{\"{}\":\"{}\"}}}}",
+        START_TIME_KEY, start_time
+    )?;
+    writer.writer.flush()
+}
+
+/// Writes the elements of the `traceEvents` array one at a time, such that
+/// large tapes do not need to be converted in memory.
+struct TraceWriter<W: Write> {
+    writer: W,
+    first: bool,
+}
+
+impl<W: Write> TraceWriter<W> {
+    fn event(&mut self, event: serde_json::Value) -> std::io::Result<()> {
+        if !self.first {
+            self.writer.write_all(b",\n")?;
+        }
+        self.first = false;
+        serde_json::to_writer(&mut self.writer, &event)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_chrome_export() {
+    let tape = crate::writer::test_tape();
+    let mut data = vec![];
+    write(&[("first", &tape), ("second", &tape)], &mut data).unwrap();
+
+    let trace: serde_json::Value = serde_json::from_slice(&data).unwrap();
+    let events = trace["traceEvents"].as_array().unwrap();
+    let count = |phase: &str| events.iter().filter(|event| event["ph"] == phase).count();
+    assert_eq!(count("X"), 8);
+    assert_eq!(count("i"), 2);
+    assert_eq!(count("s"), 2);
+    assert_eq!(count("f"), 2);
+
+    assert_eq!(
+        trace["otherData"][START_TIME_KEY],
+        (tape.timestamp_base() + 10).to_string()
+    );
+
+    let instant = events.iter().find(|event| event["ph"] == "i").unwrap();
+    assert_eq!(instant["name"], "hello");
+    assert_eq!(instant["ts"], 0.01);
+    assert_eq!(instant["tid"], 1);
+
+    let root = events
+        .iter()
+        .find(|event| event["ph"] == "X" && event["args"]["b"] == -1)
+        .unwrap();
+    assert_eq!(root["ts"], 0.0);
+    assert_eq!(root["dur"], 0.09);
+
+    // Both ends of a link between callsites of different targets are paired.
+    let tape = test_tape_with_link();
+    let mut data = vec![];
+    write(&[("test", &tape)], &mut data).unwrap();
+
+    let trace: serde_json::Value = serde_json::from_slice(&data).unwrap();
+    let events = trace["traceEvents"].as_array().unwrap();
+    let flow = |phase: &str| events.iter().find(|event| event["ph"] == phase).unwrap();
+    let (start, finish) = (flow("s"), flow("f"));
+    assert_eq!(
+        (&start["id"], &start["cat"]),
+        (&finish["id"], &finish["cat"])
+    );
+    assert_eq!(start["cat"], FLOW_NAME);
+    assert_eq!(start["name"], finish["name"]);
+    assert_eq!(
+        (start["ts"].as_f64(), finish["ts"].as_f64()),
+        (Some(0.0), Some(0.02))
+    );
+    assert_ne!(start["tid"], finish["tid"]);
+}
+
+/// A tape with a follows-from link between spans of callsites with different
+/// targets on different threads.
+#[cfg(test)]
+pub(crate) fn test_tape_with_link() -> Tape {
+    use std::sync::Arc;
+
+    use crate::{Callsite, Metadata, Span, SpanEntrance, TapeBuilder};
+
+    let callsite = |name: &str, target: &str| {
+        Callsite::Span(Metadata {
+            level: tracing::Level::INFO,
+            name: name.into(),
+            target: target.into(),
+            module_path: target.into(),
+            file: None,
+            line: None,
+            fields: Arc::new([]),
+        })
+    };
+    let span = |callsite_index, opened, closed, thread_id| Span {
+        opened,
+        closed,
+        callsite_index,
+        entrances: Arc::new([SpanEntrance {
+            entered: opened,
+            exited: closed,
+            thread_id,
+        }]),
+        values: Arc::new([]),
+    };
+
+    let mut builder = TapeBuilder::new(16, 0);
+    let send = builder.callsite(callsite("send", "client"));
+    let handle = builder.callsite(callsite("handle", "server"));
+    let cause = builder.span(None, span(send, 0, 10, 1));
+    let effect = builder.span(None, span(handle, 20, 30, 2));
+    builder.follows_from(effect, cause);
+    builder.finish()
+}
@@ -0,0 +1,127 @@
+//! Export to the folded stack format used by
+//! [flamegraph.pl](https://github.com/brendangregg/FlameGraph) and
+//! [inferno](https://github.com/jonhoo/inferno).
+//!
+//! Each line contains the names of the spans from a root span down to a
+//! span, separated by semicolons, followed by the self time of that stack in
+//! nanoseconds:
+//!
+//! ```text
+//! main;handle_request;query 1500
+//! ```
+
+use std::io::Write;
+
+use ahash::HashMap;
+use petgraph::graph::NodeIndex;
+
+use crate::{Span, Tape, Value};
+
+/// The time that is attributed to a span.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Weight {
+    /// The time the span has been entered.
+    #[default]
+    Busy,
+    /// The time between opening and closing the span.
+    Wall,
+}
+
+impl Weight {
+    fn of(self, span: &Span) -> i64 {
+        match self {
+            Weight::Busy => span
+                .entrances
+                .iter()
+                .map(|entrance| entrance.exited - entrance.entered)
+                .sum(),
+            Weight::Wall => span.closed - span.opened,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub weight: Weight,
+    /// Names of fields whose values are appended to the frame names, e.g.,
+    /// `query{table=users}`. This splits frames with different values.
+    pub fields: Vec<String>,
+}
+
+/// Writes the folded stacks of all spans of the tape.
+///
+/// The weight of a stack is the self time of its innermost span, i.e., its
+/// weight minus the weight of its children. Identical stacks are combined and
+/// the lines are sorted.
+pub fn write<W: Write>(tape: &Tape, options: &Options, mut writer: W) -> std::io::Result<()> {
+    let spans = tape.spans();
+    let mut stacks = HashMap::<String, i64>::default();
+    let mut nodes: Vec<(NodeIndex<usize>, usize)> =
+        tape.root_spans().iter().map(|root| (*root, 0)).collect();
+    let mut stack = Vec::<String>::new();
+
+    while let Some((node, depth)) = nodes.pop() {
+        let span = &spans[node];
+        stack.truncate(depth);
+        stack.push(frame(tape, span, &options.fields));
+
+        let children_weight = spans
+            .neighbors(node)
+            .map(|child| options.weight.of(&spans[child]))
+            .sum::<i64>();
+        // Children on other threads can take longer than their parent.
+        let self_weight = (options.weight.of(span) - children_weight).max(0);
+        if self_weight > 0 {
+            *stacks.entry(stack.join(";")).or_default() += self_weight;
+        }
+
+        nodes.extend(spans.neighbors(node).map(|child| (child, depth + 1)));
+    }
+
+    let mut stacks = stacks.into_iter().collect::<Vec<_>>();
+    stacks.sort_unstable();
+    for (stack, weight) in stacks {
+        writeln!(writer, "{} {}", stack, weight)?;
+    }
+    writer.flush()
+}
+
+fn frame(tape: &Tape, span: &Span, fields: &[String]) -> String {
+    let callsite = &tape.callsites()[span.callsite_index];
+    let values = callsite
+        .fields()
+        .iter()
+        .zip(span.values.iter())
+        .filter(|(field, value)| {
+            !matches!(value, Value::Empty) && fields.iter().any(|name| name == &***field)
+        })
+        .map(|(field, value)| format!("{}={}", field, value))
+        .collect::<Vec<_>>();
+
+    let frame = if values.is_empty() {
+        callsite.name().to_string()
+    } else {
+        format!("{}{{{}}}", callsite.name(), values.join(","))
+    };
+    // Semicolons separate frames and line breaks separate stacks.
+    frame.replace([';', '\n', '\r'], "_")
+}
+
+#[test]
+fn test_folded_export() {
+    let tape = crate::writer::test_tape();
+    let folded = |options: &Options| {
+        let mut data = vec![];
+        write(&tape, options, &mut data).unwrap();
+        String::from_utf8(data).unwrap()
+    };
+
+    assert_eq!(folded(&Options::default()), "outer 100\nouter;inner 90\n");
+    assert_eq!(
+        folded(&Options {
+            weight: Weight::Wall,
+            fields: vec!["b".into()],
+        }),
+        "outer 100\nouter{b=-1};inner 90\n"
+    );
+}
@@ -0,0 +1,51 @@
+//! Conversion of tapes into formats understood by other tools.
+
+use crate::{Callsite, Value};
+
+pub mod chrome;
+pub mod folded;
+pub mod otlp;
+
+impl From<&Value> for serde_json::Value {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Empty => serde_json::Value::Null,
+            Value::Bool(value) => (*value).into(),
+            Value::I64(value) => (*value).into(),
+            Value::U64(value) => (*value).into(),
+            // Numbers that do not fit into 64 bits are not supported by most JSON parsers.
+            Value::I128(value) => i64::try_from(*value)
+                .map(Into::into)
+                .unwrap_or_else(|_| value.to_string().into()),
+            Value::U128(value) => u64::try_from(*value)
+                .map(Into::into)
+                .unwrap_or_else(|_| value.to_string().into()),
+            Value::F64(value) => (*value).into(),
+            Value::String(value) | Value::Error(value) => value.to_string().into(),
+        }
+    }
+}
+
+/// Returns the recorded values of a span or event as a JSON object.
+fn json_fields(
+    callsite: &Callsite,
+    values: &[Value],
+) -> serde_json::Map<String, serde_json::Value> {
+    callsite
+        .fields()
+        .iter()
+        .zip(values.iter())
+        .filter(|(_, value)| !matches!(value, Value::Empty))
+        .map(|(field, value)| (field.to_string(), value.into()))
+        .collect()
+}
+
+/// Returns the message of an event if it has been recorded.
+fn message(callsite: &Callsite, values: &[Value]) -> Option<String> {
+    callsite
+        .fields()
+        .iter()
+        .zip(values.iter())
+        .find(|(field, value)| &***field == "message" && !matches!(value, Value::Empty))
+        .map(|(_, value)| value.to_string())
+}
@@ -0,0 +1,223 @@
+//! Export to the [OTLP/JSON] encoding of the OpenTelemetry trace data model.
+//!
+//! The result is a single `ExportTraceServiceRequest` on one line, which is
+//! the format of the OpenTelemetry Collector's file exporter and can be
+//! replayed into a collector using its file receiver or posted to the
+//! `/v1/traces` endpoint of an OTLP/HTTP receiver.
+//!
+//! Each tape becomes a resource whose `service.name` is the given name. Every
+//! root span starts a new trace, its id is derived from the timestamp base of
+//! the tape and the root span, such that repeated exports produce the same
+//! ids. Events are attached to the innermost span that was entered on their
+//! thread at the time, events outside of spans are not exported.
+//!
+//! [OTLP/JSON]: https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding
+
+use std::io::Write;
+
+use petgraph::graph::NodeIndex;
+use serde_json::json;
+
+use super::message;
+use crate::{Callsite, Tape, Value};
+
+/// `SPAN_KIND_INTERNAL`, tracing does not distinguish clients and servers.
+const SPAN_KIND_INTERNAL: u32 = 1;
+
+/// Writes the given tapes as OTLP/JSON.
+///
+/// Each tape is paired with the name of the service that recorded it.
+pub fn write<W: Write>(tapes: &[(&str, &Tape)], mut writer: W) -> std::io::Result<()> {
+    let resource_spans = tapes
+        .iter()
+        .map(|(name, tape)| {
+            json!({
+                "resource": {
+                    "attributes": [attribute("service.name", &Value::String((*name).into()))],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "tracing-tape" },
+                    "spans": spans(tape),
+                }],
+            })
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::to_writer(&mut writer, &json!({ "resourceSpans": resource_spans }))?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+fn spans(tape: &Tape) -> Vec<serde_json::Value> {
+    let spans = tape.spans();
+    let time = |timestamp: i64| (tape.timestamp_base() + timestamp as i128).to_string();
+    let span_id = |node: NodeIndex<usize>| format!("{:016x}", node.index() + 1);
+
+    // The trace of each span is determined by its root span.
+    let mut trace_ids = vec![String::new(); spans.node_count()];
+    let mut parents = vec![None; spans.node_count()];
+    for root in tape.root_spans() {
+        let trace_id = format!(
+            "{:016x}{:016x}",
+            tape.timestamp_base() as u64,
+            root.index() + 1
+        );
+        let mut stack = vec![*root];
+        while let Some(node) = stack.pop() {
+            trace_ids[node.index()] = trace_id.clone();
+            for child in spans.neighbors(node) {
+                parents[child.index()] = Some(node);
+                stack.push(child);
+            }
+        }
+    }
+
+    let mut span_events = vec![Vec::new(); spans.node_count()];
+    for event in tape.events() {
+        let Some(node) = event.span else {
+            continue;
+        };
+        let callsite = &tape.callsites()[event.callsite_index];
+        let mut attributes = attributes(callsite, &event.values);
+        attributes.push(attribute(
+            "level",
+            &Value::String(callsite.level().as_str().into()),
+        ));
+
+        span_events[node.index()].push(json!({
+            "timeUnixNano": time(event.timestamp),
+            "name": message(callsite, &event.values).unwrap_or_else(|| callsite.name().into()),
+            "attributes": attributes,
+        }));
+    }
+
+    let mut links = vec![Vec::new(); spans.node_count()];
+    for (span, follows) in tape.follows_from() {
+        links[span.index()].push(json!({
+            "traceId": trace_ids[follows.index()],
+            "spanId": span_id(*follows),
+        }));
+    }
+
+    spans
+        .node_indices()
+        .map(|node| {
+            let span = &spans[node];
+            let callsite = &tape.callsites()[span.callsite_index];
+
+            let mut attributes = attributes(callsite, &span.values);
+            attributes.push(attribute(
+                "code.namespace",
+                &Value::String(callsite.module_path().into()),
+            ));
+            if let Some(file) = callsite.file() {
+                attributes.push(attribute("code.filepath", &Value::String(file.into())));
+            }
+            if let Some(line) = callsite.line() {
+                attributes.push(attribute("code.lineno", &Value::U64(line.into())));
+            }
+            if let Some(entrance) = span.entrances.first() {
+                attributes.push(attribute("thread.id", &Value::U64(entrance.thread_id)));
+                if let Some(Some(name)) = tape.threads().get(&entrance.thread_id) {
+                    attributes.push(attribute(
+                        "thread.name",
+                        &Value::String(name.as_str().into()),
+                    ));
+                }
+            }
+
+            let mut object = json!({
+                "traceId": trace_ids[node.index()],
+                "spanId": span_id(node),
+                "name": callsite.name(),
+                "kind": SPAN_KIND_INTERNAL,
+                "startTimeUnixNano": time(span.opened),
+                "endTimeUnixNano": time(span.closed),
+                "attributes": attributes,
+                "events": std::mem::take(&mut span_events[node.index()]),
+                "links": std::mem::take(&mut links[node.index()]),
+            });
+            if let Some(parent) = parents[node.index()] {
+                object["parentSpanId"] = span_id(parent).into();
+            }
+            object
+        })
+        .collect()
+}
+
+fn attributes(callsite: &Callsite, values: &[Value]) -> Vec<serde_json::Value> {
+    callsite
+        .fields()
+        .iter()
+        .zip(values.iter())
+        .filter(|(_, value)| !matches!(value, Value::Empty))
+        .map(|(field, value)| attribute(field, value))
+        .collect()
+}
+
+fn attribute(key: &str, value: &Value) -> serde_json::Value {
+    // 64 bit integers are encoded as strings in OTLP/JSON.
+    let value = match value {
+        Value::Bool(value) => json!({ "boolValue": value }),
+        Value::I64(value) => json!({ "intValue": value.to_string() }),
+        Value::U64(value) => match i64::try_from(*value) {
+            Ok(value) => json!({ "intValue": value.to_string() }),
+            Err(_) => json!({ "stringValue": value.to_string() }),
+        },
+        Value::I128(value) => match i64::try_from(*value) {
+            Ok(value) => json!({ "intValue": value.to_string() }),
+            Err(_) => json!({ "stringValue": value.to_string() }),
+        },
+        Value::U128(value) => match i64::try_from(*value) {
+            Ok(value) => json!({ "intValue": value.to_string() }),
+            Err(_) => json!({ "stringValue": value.to_string() }),
+        },
+        Value::F64(value) => json!({ "doubleValue": value }),
+        Value::Empty | Value::String(_) | Value::Error(_) => {
+            json!({ "stringValue": value.to_string() })
+        }
+    };
+    json!({ "key": key, "value": value })
+}
+
+#[test]
+fn test_otlp_export() {
+    let tape = crate::writer::test_tape();
+    let mut data = vec![];
+    write(&[("test", &tape)], &mut data).unwrap();
+
+    let request: serde_json::Value = serde_json::from_slice(&data).unwrap();
+    let spans = request["resourceSpans"][0]["scopeSpans"][0]["spans"]
+        .as_array()
+        .unwrap();
+    assert_eq!(spans.len(), 4);
+
+    let root = spans
+        .iter()
+        .find(|span| span["startTimeUnixNano"] == "1000010" && span["name"] == "outer")
+        .unwrap();
+    assert!(root.get("parentSpanId").is_none());
+    assert_eq!(root["attributes"][0]["value"]["intValue"], "-1");
+
+    let children = spans
+        .iter()
+        .filter(|span| span["parentSpanId"] == root["spanId"])
+        .collect::<Vec<_>>();
+    assert_eq!(children.len(), 2);
+    assert!(children
+        .iter()
+        .all(|child| child["traceId"] == root["traceId"]));
+
+    // The event happened at 20 inside the first inner span.
+    let first = children
+        .iter()
+        .find(|child| child["startTimeUnixNano"] == "1000010")
+        .unwrap();
+    assert_eq!(first["events"][0]["name"], "hello");
+
+    let second = children
+        .iter()
+        .find(|child| child["startTimeUnixNano"] == "1000050")
+        .unwrap();
+    assert_eq!(second["links"][0]["spanId"], root["spanId"]);
+}
@@ -0,0 +1,473 @@
+//! Import of traces in the [Trace Event Format], e.g., recorded by browsers or
+//! exported by [crate::export::chrome].
+//!
+//! The following events are supported:
+//! - Duration (`B`/`E`) and complete (`X`) events become spans. They are
+//!   nested according to their time on each thread.
+//! - Instant events (`i`/`I`) become events.
+//! - Flow events (`s`/`f`) become follows-from links between the spans that
+//!   enclose them.
+//! - The `process_name` and `thread_name` metadata events name the threads.
+//!
+//! All other events are ignored. Since every process of a trace has its own
+//! threads, processes are combined into a single tape with one thread per
+//! process and thread id. Callsites are created for each combination of name
+//! and category (which becomes the target) and have a field for each argument
+//! key. A `level` argument is used as the level of the callsite.
+//!
+//! Timestamps of the Trace Event Format have no defined origin. Traces
+//! exported by this crate store the absolute start time in their `otherData`.
+//! Other traces get a time base of zero and their timestamps are shifted to
+//! start at zero, i.e., they are placed at the unix epoch. Viewers should align
+//! them with other tapes, e.g., trace-deck starts them together with the first
+//! loaded tape.
+//!
+//! [Trace Event Format]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+
+use std::{io::Read, sync::Arc};
+
+use ahash::HashMap;
+use petgraph::graph::NodeIndex;
+use serde_json::Map;
+
+use crate::{
+    export::chrome::START_TIME_KEY, Callsite, Event, Metadata, Span, SpanEntrance, Tape,
+    TapeBuilder, Value,
+};
+
+/// Reads a trace in the Trace Event Format, either in the JSON object or the
+/// JSON array format.
+pub fn read<R: Read>(reader: R) -> std::io::Result<Tape> {
+    let trace: serde_json::Value = serde_json::from_reader(reader)?;
+    let (events, start_time) = match &trace {
+        serde_json::Value::Array(events) => (events, None),
+        serde_json::Value::Object(object) => match object.get("traceEvents") {
+            Some(serde_json::Value::Array(events)) => {
+                let start_time = object
+                    .get("otherData")
+                    .and_then(|other_data| other_data.get(START_TIME_KEY))
+                    .and_then(|start_time| start_time.as_str())
+                    .and_then(|start_time| start_time.parse().ok());
+                (events, start_time)
+            }
+            _ => return Err(invalid_data("missing `traceEvents` array")),
+        },
+        _ => return Err(invalid_data("expected a JSON object or array")),
+    };
+
+    let mut importer = Importer {
+        start_time,
+        ..Default::default()
+    };
+    for event in events.iter().filter_map(|event| event.as_object()) {
+        importer.event(event);
+    }
+    Ok(importer.finish())
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Span,
+    Event,
+}
+
+/// Identifies a callsite, the fields are collected across all its events.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CallsiteKey {
+    kind: Kind,
+    name: String,
+    category: String,
+    level: tracing::Level,
+}
+
+/// A span or event that has been read but not added to the tape yet.
+struct Record<'a> {
+    callsite: usize,
+    thread_id: u64,
+    start: i64,
+    end: i64,
+    args: Vec<(&'a str, Value)>,
+}
+
+/// The thread and timestamp of the start and finish of a flow.
+#[derive(Default)]
+struct Flow {
+    start: Option<(u64, i64)>,
+    finish: Option<(u64, i64)>,
+}
+
+#[derive(Default)]
+struct Importer<'a> {
+    /// The absolute time the timestamps are relative to, if the trace has one.
+    start_time: Option<i128>,
+    callsites: Vec<(CallsiteKey, Vec<&'a str>)>,
+    callsite_indices: HashMap<CallsiteKey, usize>,
+    spans: Vec<Record<'a>>,
+    events: Vec<Record<'a>>,
+    /// Duration events that have begun but not ended yet for each thread.
+    open_spans: HashMap<u64, Vec<Record<'a>>>,
+    /// Start and finish of flows by their id.
+    flows: HashMap<(String, &'a str), Flow>,
+    process_names: HashMap<u64, &'a str>,
+    thread_names: HashMap<u64, &'a str>,
+}
+
+impl<'a> Importer<'a> {
+    fn event(&mut self, event: &'a Map<String, serde_json::Value>) {
+        let str_field = |key: &str| event.get(key).and_then(|value| value.as_str());
+        let u64_field = |key: &str| event.get(key).and_then(|value| value.as_u64());
+        // Timestamps are given in microseconds.
+        let time_field = |key: &str| {
+            event
+                .get(key)
+                .and_then(|value| value.as_f64())
+                .map(|value| (value * 1000.0).round() as i64)
+        };
+
+        let Some(phase) = str_field("ph") else {
+            return;
+        };
+        let pid = u64_field("pid").unwrap_or(0);
+        let tid = u64_field("tid").unwrap_or(0);
+        let thread_id = (pid << 32) ^ tid;
+        let timestamp = time_field("ts").unwrap_or(0);
+
+        match phase {
+            "B" | "X" | "i" | "I" => {
+                let kind = if phase == "B" || phase == "X" {
+                    Kind::Span
+                } else {
+                    Kind::Event
+                };
+                let (level, args) = args(event);
+                let callsite = self.callsite(
+                    CallsiteKey {
+                        kind,
+                        name: str_field("name").unwrap_or_default().to_string(),
+                        category: str_field("cat").unwrap_or_default().to_string(),
+                        level,
+                    },
+                    &args,
+                );
+                let record = Record {
+                    callsite,
+                    thread_id,
+                    start: timestamp,
+                    end: timestamp + time_field("dur").unwrap_or(0),
+                    args,
+                };
+
+                match phase {
+                    "B" => self.open_spans.entry(thread_id).or_default().push(record),
+                    "X" => self.spans.push(record),
+                    _ => self.events.push(record),
+                }
+            }
+            "E" => {
+                if let Some(mut record) = self
+                    .open_spans
+                    .get_mut(&thread_id)
+                    .and_then(|open_spans| open_spans.pop())
+                {
+                    // Arguments of the end event are merged into the span.
+                    let (_, args) = args(event);
+                    self.callsite(self.callsites[record.callsite].0.clone(), &args);
+                    record.args.extend(args);
+                    record.end = timestamp;
+                    self.spans.push(record);
+                }
+            }
+            "s" | "f" => {
+                let id = match event.get("id") {
+                    Some(serde_json::Value::String(id)) => id.clone(),
+                    Some(id) => id.to_string(),
+                    None => return,
+                };
+                let flow = self
+                    .flows
+                    .entry((id, str_field("cat").unwrap_or_default()))
+                    .or_default();
+                if phase == "s" {
+                    flow.start = Some((thread_id, timestamp));
+                } else {
+                    flow.finish = Some((thread_id, timestamp));
+                }
+            }
+            "M" => {
+                let name = event
+                    .get("args")
+                    .and_then(|args| args.get("name"))
+                    .and_then(|name| name.as_str());
+                match (str_field("name"), name) {
+                    (Some("process_name"), Some(name)) => {
+                        self.process_names.insert(pid, name);
+                    }
+                    (Some("thread_name"), Some(name)) => {
+                        self.thread_names.insert(thread_id, name);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the index of the callsite and adds missing fields to it.
+    fn callsite(&mut self, key: CallsiteKey, args: &[(&'a str, Value)]) -> usize {
+        let index = *self.callsite_indices.entry(key.clone()).or_insert_with(|| {
+            self.callsites.push((key, Vec::new()));
+            self.callsites.len() - 1
+        });
+        let fields = &mut self.callsites[index].1;
+        for (name, _) in args {
+            if !fields.contains(name) {
+                fields.push(name);
+            }
+        }
+        index
+    }
+
+    fn finish(mut self) -> Tape {
+        // Without a start time, the base is chosen such that rebasing moves it
+        // to zero.
+        let start_time = self.start_time.unwrap_or_else(|| {
+            let min_timestamp = self
+                .spans
+                .iter()
+                .chain(self.events.iter())
+                .chain(self.open_spans.values().flatten())
+                .map(|record| record.start)
+                .min()
+                .unwrap_or(0);
+            -(min_timestamp as i128)
+        });
+        let mut builder = TapeBuilder::new(20, start_time);
+
+        for (key, fields) in &self.callsites {
+            let metadata = Metadata {
+                level: key.level,
+                name: key.name.as_str().into(),
+                target: key.category.as_str().into(),
+                module_path: key.category.as_str().into(),
+                file: None,
+                line: None,
+                fields: fields.iter().map(|field| Arc::from(*field)).collect(),
+            };
+            builder.callsite(match key.kind {
+                Kind::Span => Callsite::Span(metadata),
+                Kind::Event => Callsite::Event(metadata),
+            });
+        }
+        let values = |record: &Record| -> Arc<[Value]> {
+            self.callsites[record.callsite]
+                .1
+                .iter()
+                .map(|field| {
+                    record
+                        .args
+                        .iter()
+                        .rev()
+                        .find(|(name, _)| name == field)
+                        .map(|(_, value)| value.clone())
+                        .unwrap_or(Value::Empty)
+                })
+                .collect()
+        };
+
+        // Spans that have not ended are closed at the end of the trace.
+        let end = self
+            .spans
+            .iter()
+            .chain(self.events.iter())
+            .map(|record| record.end)
+            .max()
+            .unwrap_or(0);
+        for mut record in self.open_spans.into_values().flatten() {
+            record.end = record.start.max(end);
+            self.spans.push(record);
+        }
+
+        // Spans are nested if they are on the same thread and the time of one
+        // is contained in the other. Sorting them by thread, start, and
+        // descending end visits parents before their children.
+        self.spans.sort_by_key(|record| {
+            (
+                record.thread_id,
+                record.start,
+                std::cmp::Reverse(record.end),
+            )
+        });
+        let mut nodes = Vec::with_capacity(self.spans.len());
+        let mut stack: Vec<(u64, i64, NodeIndex<usize>)> = Vec::new();
+        for record in &self.spans {
+            while stack.last().is_some_and(|(thread_id, end, _)| {
+                *thread_id != record.thread_id || *end < record.end
+            }) {
+                stack.pop();
+            }
+
+            let node = builder.span(
+                stack.last().map(|(_, _, node)| *node),
+                Span {
+                    opened: record.start,
+                    closed: record.end,
+                    callsite_index: record.callsite,
+                    entrances: Arc::new([SpanEntrance {
+                        entered: record.start,
+                        exited: record.end,
+                        thread_id: record.thread_id,
+                    }]),
+                    values: values(record),
+                },
+            );
+            nodes.push((record.thread_id, record.start, record.end, node));
+            stack.push((record.thread_id, record.end, node));
+        }
+
+        // Instant and flow events are bound to the innermost span that
+        // encloses them.
+        let enclosing = |(thread_id, timestamp): (u64, i64)| {
+            let start = nodes.partition_point(|(thread, start, _, _)| {
+                (*thread, *start) <= (thread_id, timestamp)
+            });
+            nodes[..start]
+                .iter()
+                .rev()
+                .take_while(|(thread, _, _, _)| *thread == thread_id)
+                .find(|(_, _, end, _)| *end >= timestamp)
+                .map(|(_, _, _, node)| *node)
+        };
+        for record in &self.events {
+            builder.event(Event {
+                timestamp: record.start,
+                callsite_index: record.callsite,
+                thread_id: record.thread_id,
+                span: enclosing((record.thread_id, record.start)),
+                values: values(record),
+            });
+        }
+
+        let mut flows = self.flows.into_values().collect::<Vec<_>>();
+        flows.sort_by_key(|flow| flow.start);
+        for flow in flows {
+            if let (Some(start), Some(finish)) = (flow.start, flow.finish) {
+                if let (Some(follows), Some(span)) = (enclosing(start), enclosing(finish)) {
+                    if follows != span {
+                        builder.follows_from(span, follows);
+                    }
+                }
+            }
+        }
+
+        let mut threads = self.thread_names.keys().copied().collect::<Vec<_>>();
+        threads.sort_unstable();
+        for thread_id in threads {
+            let name = self.thread_names[&thread_id];
+            let name = match self.process_names.get(&(thread_id >> 32)) {
+                Some(process) => format!("{}: {}", process, name),
+                None => name.to_string(),
+            };
+            builder.name_thread(thread_id, &name);
+        }
+
+        builder.rebase();
+        builder.finish()
+    }
+}
+
+/// Returns the level and the remaining arguments of an event.
+fn args(event: &Map<String, serde_json::Value>) -> (tracing::Level, Vec<(&str, Value)>) {
+    let mut level = tracing::Level::INFO;
+    let mut args = Vec::new();
+    if let Some(serde_json::Value::Object(object)) = event.get("args") {
+        for (key, value) in object {
+            if key == "level" {
+                if let Some(parsed) = value.as_str().and_then(|value| value.parse().ok()) {
+                    level = parsed;
+                    continue;
+                }
+            }
+            args.push((key.as_str(), json_value(value)));
+        }
+    }
+    (level, args)
+}
+
+fn json_value(value: &serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Empty,
+        serde_json::Value::Bool(value) => Value::Bool(*value),
+        serde_json::Value::Number(number) => {
+            if let Some(value) = number.as_i64() {
+                Value::I64(value)
+            } else if let Some(value) = number.as_u64() {
+                Value::U64(value)
+            } else {
+                Value::F64(number.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        serde_json::Value::String(value) => Value::String(value.as_str().into()),
+        value => Value::String(value.to_string().into()),
+    }
+}
+
+#[test]
+fn test_chrome_round_trip() {
+    let tape = crate::writer::test_tape();
+    let mut data = vec![];
+    crate::export::chrome::write(&[("test", &tape)], &mut data).unwrap();
+
+    let imported = read(&data[..]).unwrap();
+    assert_eq!(imported.spans().node_count(), 4);
+    assert_eq!(imported.root_spans().len(), 2);
+    assert_eq!(imported.events().len(), 1);
+    assert_eq!(imported.follows_from().len(), 1);
+    assert_eq!(imported.timestamp_range(), 0..=290);
+    assert_eq!(imported.timestamp_base(), tape.timestamp_base() + 10);
+
+    let root = imported
+        .root_spans()
+        .iter()
+        .find(|root| imported.spans().neighbors(**root).count() == 2)
+        .unwrap();
+    let root = &imported.spans()[*root];
+    let callsite = &imported.callsites()[root.callsite_index];
+    assert_eq!(callsite.name(), "outer");
+    assert_eq!(callsite.target(), "test");
+    assert_eq!(callsite.fields().len(), 2);
+    assert!(root.values.contains(&Value::I64(-1)));
+
+    let thread_names = imported.threads().values().flatten().collect::<Vec<_>>();
+    assert_eq!(thread_names, ["test: main"]);
+
+    // Links between callsites of different targets survive the round trip.
+    let tape = crate::export::chrome::test_tape_with_link();
+    let mut data = vec![];
+    crate::export::chrome::write(&[("test", &tape)], &mut data).unwrap();
+
+    let imported = read(&data[..]).unwrap();
+    let [(span, follows)] = imported.follows_from() else {
+        panic!("expected a single follows-from link");
+    };
+    let target = |span: petgraph::graph::NodeIndex<usize>| {
+        imported.callsites()[imported.spans()[span].callsite_index].target()
+    };
+    assert_eq!((target(*span), target(*follows)), ("server", "client"));
+}
+
+#[test]
+fn test_chrome_without_start_time() {
+    let trace = serde_json::json!([
+        {"name": "outer", "cat": "test", "ph": "X", "ts": 1000.0, "dur": 5.0, "pid": 1, "tid": 1},
+        {"name": "message", "cat": "test", "ph": "i", "ts": 1002.0, "pid": 1, "tid": 1},
+    ]);
+
+    let imported = read(trace.to_string().as_bytes()).unwrap();
+    assert_eq!(imported.timestamp_base(), 0);
+    assert_eq!(imported.timestamp_range(), 0..=5000);
+    assert_eq!(imported.events()[0].timestamp, 2000);
+}
@@ -0,0 +1,3 @@
+//! Conversion of traces recorded by other tools into tapes.
+
+pub mod chrome;
@@ -0,0 +1,318 @@
+//! Interval index for looking up the spans within a time range without
+//! visiting all spans of a tape.
+
+use std::ops::{Range, RangeInclusive};
+
+use ahash::HashMap;
+use petgraph::graph::NodeIndex;
+
+use crate::{Span, Tape};
+
+/// An entrance of a span on a thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadSpan {
+    pub span: NodeIndex<usize>,
+    /// The index of the entrance in [Span::entrances].
+    pub entrance: usize,
+    /// The number of spans that were entered on the thread when the span was
+    /// entered, i.e., `0` for spans entered on an idle thread.
+    pub depth: usize,
+}
+
+/// Intervals sorted by their start with an implicit binary tree on top that
+/// stores the maximum end and duration of its subtrees. This allows skipping
+/// subtrees that end before a range or only contain short intervals.
+#[derive(Debug)]
+pub(crate) struct IntervalTree<T> {
+    starts: Vec<i64>,
+    items: Vec<T>,
+    /// Number of leaves, the nodes `1..leaves` are inner nodes with the
+    /// children `2 * i` and `2 * i + 1`.
+    leaves: usize,
+    max_end: Vec<i64>,
+    max_duration: Vec<i64>,
+}
+
+impl<T> IntervalTree<T> {
+    fn new(mut intervals: Vec<(i64, i64, T)>) -> Self {
+        intervals.sort_by_key(|(start, end, _)| (*start, std::cmp::Reverse(*end)));
+
+        let leaves = intervals.len().next_power_of_two();
+        let mut max_end = vec![i64::MIN; 2 * leaves];
+        let mut max_duration = vec![-1; 2 * leaves];
+        let mut starts = Vec::with_capacity(intervals.len());
+        let mut items = Vec::with_capacity(intervals.len());
+        for (index, (start, end, item)) in intervals.into_iter().enumerate() {
+            max_end[leaves + index] = end;
+            max_duration[leaves + index] = end - start;
+            starts.push(start);
+            items.push(item);
+        }
+        for node in (1..leaves).rev() {
+            max_end[node] = max_end[2 * node].max(max_end[2 * node + 1]);
+            max_duration[node] = max_duration[2 * node].max(max_duration[2 * node + 1]);
+        }
+
+        Self {
+            starts,
+            items,
+            leaves,
+            max_end,
+            max_duration,
+        }
+    }
+}
+
+impl<T> Default for IntervalTree<T> {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+/// Iterator over the intervals that overlap a range, ordered by their start.
+pub struct Intervals<'a, T> {
+    tree: Option<&'a IntervalTree<T>>,
+    range: RangeInclusive<i64>,
+    min_duration: i64,
+    /// Intervals from this index on start after the range.
+    end: usize,
+    /// Nodes that remain to be visited with the range of leaves they cover.
+    stack: Vec<(usize, usize, usize)>,
+}
+
+impl<'a, T> Intervals<'a, T> {
+    fn new(tree: Option<&'a IntervalTree<T>>, range: RangeInclusive<i64>) -> Self {
+        let (end, stack) = match tree {
+            Some(tree) if !tree.items.is_empty() => (
+                tree.starts.partition_point(|start| start <= range.end()),
+                vec![(1, 0, tree.leaves)],
+            ),
+            _ => (0, Vec::new()),
+        };
+        Self {
+            tree,
+            range,
+            min_duration: 0,
+            end,
+            stack,
+        }
+    }
+
+    /// Skips intervals shorter than the given number of nanoseconds, e.g.,
+    /// spans that would be smaller than a pixel.
+    pub fn min_duration(mut self, nanos: i64) -> Self {
+        self.min_duration = nanos;
+        self
+    }
+}
+
+impl<T: Copy> Iterator for Intervals<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let tree = self.tree?;
+        while let Some((node, first, last)) = self.stack.pop() {
+            if first >= self.end
+                || tree.max_end[node] < *self.range.start()
+                || tree.max_duration[node] < self.min_duration
+            {
+                continue;
+            }
+            if node >= tree.leaves {
+                return Some(tree.items[first]);
+            }
+
+            let middle = (first + last) / 2;
+            self.stack.push((2 * node + 1, middle, last));
+            self.stack.push((2 * node, first, middle));
+        }
+        None
+    }
+}
+
+/// The entrances at one depth of a thread with the cumulative time spent in
+/// them. Entrances at the same depth never overlap, so the busy time within
+/// any range, and thereby the busy fraction of buckets of any width, can be
+/// computed with two binary searches.
+#[derive(Debug, Default)]
+struct BusyTime {
+    entered: Vec<i64>,
+    exited: Vec<i64>,
+    /// `cumulative[i]` is the time spent in the first `i` entrances.
+    cumulative: Vec<i64>,
+}
+
+impl BusyTime {
+    fn push(&mut self, entered: i64, exited: i64) {
+        let total = self.cumulative.last().copied().unwrap_or(0);
+        if self.cumulative.is_empty() {
+            self.cumulative.push(0);
+        }
+        self.entered.push(entered);
+        self.exited.push(exited);
+        self.cumulative.push(total + exited - entered);
+    }
+
+    fn within(&self, range: &Range<i64>) -> i64 {
+        let first = self.exited.partition_point(|exited| *exited <= range.start);
+        let last = self.entered.partition_point(|entered| *entered < range.end);
+        if first >= last {
+            return 0;
+        }
+        self.cumulative[last]
+            - self.cumulative[first]
+            - (range.start - self.entered[first]).max(0)
+            - (self.exited[last - 1] - range.end).max(0)
+    }
+}
+
+/// Interval trees over all spans and over the entrances on each thread.
+#[derive(Debug, Default)]
+pub(crate) struct SpanIndex {
+    spans: IntervalTree<NodeIndex<usize>>,
+    threads: HashMap<u64, IntervalTree<ThreadSpan>>,
+    /// Busy time of each thread by depth.
+    busy: HashMap<u64, Vec<BusyTime>>,
+}
+
+impl SpanIndex {
+    pub(crate) fn new(spans: &petgraph::Graph<Span, (), petgraph::Directed, usize>) -> Self {
+        let mut entrances = HashMap::<u64, Vec<(i64, i64, NodeIndex<usize>, usize)>>::default();
+        for node in spans.node_indices() {
+            for (index, entrance) in spans[node].entrances.iter().enumerate() {
+                entrances.entry(entrance.thread_id).or_default().push((
+                    entrance.entered,
+                    entrance.exited,
+                    node,
+                    index,
+                ));
+            }
+        }
+
+        let mut busy = HashMap::<u64, Vec<BusyTime>>::default();
+        let threads = entrances
+            .into_iter()
+            .map(|(thread_id, mut entrances)| {
+                let busy = busy.entry(thread_id).or_default();
+                // Entrances on a thread are properly nested, sorting them by
+                // their start and descending end visits outer ones first.
+                entrances
+                    .sort_by_key(|(entered, exited, _, _)| (*entered, std::cmp::Reverse(*exited)));
+                let mut context: Vec<i64> = Vec::new();
+                let intervals = entrances
+                    .into_iter()
+                    .map(|(entered, exited, span, entrance)| {
+                        while context.last().is_some_and(|outer| *outer < exited) {
+                            context.pop();
+                        }
+                        let depth = context.len();
+                        context.push(exited);
+                        if busy.len() <= depth {
+                            busy.resize_with(depth + 1, Default::default);
+                        }
+                        busy[depth].push(entered, exited);
+                        (
+                            entered,
+                            exited,
+                            ThreadSpan {
+                                span,
+                                entrance,
+                                depth,
+                            },
+                        )
+                    })
+                    .collect();
+                (thread_id, IntervalTree::new(intervals))
+            })
+            .collect();
+
+        Self {
+            spans: IntervalTree::new(
+                spans
+                    .node_indices()
+                    .map(|node| (spans[node].opened, spans[node].closed, node))
+                    .collect(),
+            ),
+            threads,
+            busy,
+        }
+    }
+}
+
+impl Tape {
+    /// Returns the spans that are open at some point within the given
+    /// timestamp range, ordered by the time they were opened.
+    pub fn spans_in_range(&self, range: RangeInclusive<i64>) -> Intervals<'_, NodeIndex<usize>> {
+        Intervals::new(Some(&self.data.index.spans), range)
+    }
+
+    /// Returns the entrances on the given thread that overlap the timestamp
+    /// range, ordered by the time they were entered.
+    pub fn spans_in_range_on_thread(
+        &self,
+        thread_id: u64,
+        range: RangeInclusive<i64>,
+    ) -> Intervals<'_, ThreadSpan> {
+        Intervals::new(self.data.index.threads.get(&thread_id), range)
+    }
+
+    /// Returns the number of nesting levels of spans on the given thread,
+    /// i.e., one more than the largest [ThreadSpan::depth].
+    pub fn thread_depth(&self, thread_id: u64) -> usize {
+        self.data.index.busy.get(&thread_id).map_or(0, Vec::len)
+    }
+
+    /// Returns the number of nanoseconds within the timestamp range the
+    /// given thread spent in spans at the given depth.
+    ///
+    /// This is precomputed for every thread and depth when the tape is parsed
+    /// and takes logarithmic time, which makes it suitable for summarizing
+    /// spans that are too short to be drawn individually.
+    pub fn busy_time_on_thread(&self, thread_id: u64, depth: usize, range: Range<i64>) -> i64 {
+        self.data
+            .index
+            .busy
+            .get(&thread_id)
+            .and_then(|busy| busy.get(depth))
+            .map_or(0, |busy| busy.within(&range))
+    }
+}
+
+#[test]
+fn test_spans_in_range() {
+    let tape = crate::writer::test_tape();
+    let opened = |range: RangeInclusive<i64>| {
+        tape.spans_in_range(range)
+            .map(|span| tape.spans()[span].opened)
+            .collect::<Vec<_>>()
+    };
+    assert!(opened(0..=5).is_empty());
+    assert_eq!(opened(0..=10), [10, 10]);
+    assert_eq!(opened(50..=50), [10, 10, 50]);
+    assert!(opened(101..=199).is_empty());
+    assert_eq!(opened(150..=1000), [200]);
+    assert_eq!(tape.spans_in_range(0..=1000).min_duration(60).count(), 2);
+
+    let depths = tape
+        .spans_in_range_on_thread(1, 40..=60)
+        .map(|span| (tape.spans()[span.span].opened, span.depth))
+        .collect::<Vec<_>>();
+    assert_eq!(depths, [(10, 0), (10, 1), (50, 1)]);
+    assert_eq!(tape.spans_in_range_on_thread(2, 0..=1000).count(), 0);
+}
+
+#[test]
+fn test_busy_time_on_thread() {
+    let tape = crate::writer::test_tape();
+    assert_eq!(tape.thread_depth(1), 2);
+    assert_eq!(tape.thread_depth(2), 0);
+
+    assert_eq!(tape.busy_time_on_thread(1, 0, 0..1000), 190);
+    assert_eq!(tape.busy_time_on_thread(1, 0, 20..30), 10);
+    assert_eq!(tape.busy_time_on_thread(1, 1, 0..1000), 90);
+    assert_eq!(tape.busy_time_on_thread(1, 1, 45..55), 10);
+    assert_eq!(tape.busy_time_on_thread(1, 1, 30..60), 30);
+    assert_eq!(tape.busy_time_on_thread(1, 0, 100..200), 0);
+    assert_eq!(tape.busy_time_on_thread(1, 2, 0..1000), 0);
+    assert_eq!(tape.busy_time_on_thread(2, 0, 0..1000), 0);
+}
@@ -11,13 +11,33 @@ use tracing_tape::{
     record::{
         field_type, record_kind, CallsiteFieldRecord, CallsiteRecord, EventRecord,
         EventValueRecord, RecordHeader, SpanCloseRecord, SpanEnterRecord, SpanExitRecord,
-        SpanOpenRecord, SpanOpenRecord2, SpanValueRecord,
+        SpanFollowsRecord, SpanOpenRecord, SpanOpenRecord2, SpanValueRecord, ThreadNameRecord,
     },
 };
 use zerocopy::FromBytes;
 
-#[derive(Debug)]
+mod builder;
+mod critical_path;
+pub mod export;
+pub mod import;
+mod index;
+pub mod query;
+mod statistics;
+mod transform;
+mod writer;
+
+pub use builder::TapeBuilder;
+pub use critical_path::CriticalPathSegment;
+pub use index::{Intervals, ThreadSpan};
+pub use statistics::percentile;
+#[cfg(feature = "testing")]
+#[doc(hidden)]
+pub use writer::test_tape;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
+    /// A field that was declared on the callsite but never recorded.
+    Empty,
     Bool(bool),
     I64(i64),
     U64(u64),
@@ -29,14 +49,25 @@ pub enum Value {
 }
 
 impl Value {
-    fn parse(kind: u8, data: &[u8]) -> Self {
-        match kind {
-            field_type::BOOL => Value::Bool(data[0] != 0),
-            field_type::I64 => Value::I64(i64::from_le_bytes(data.try_into().unwrap())),
-            field_type::U64 => Value::U64(u64::from_le_bytes(data.try_into().unwrap())),
-            field_type::I128 => Value::I128(i128::from_le_bytes(data.try_into().unwrap())),
-            field_type::U128 => Value::U128(u128::from_le_bytes(data.try_into().unwrap())),
-            field_type::F64 => Value::F64(f64::from_le_bytes(data.try_into().unwrap())),
+    fn parse(kind: u8, data: &[u8]) -> std::io::Result<Self> {
+        let invalid_value = || invalid_data(format!("invalid value of field type {}", kind));
+        let value = match kind {
+            field_type::BOOL => Value::Bool(*data.first().ok_or_else(invalid_value)? != 0),
+            field_type::I64 => Value::I64(i64::from_le_bytes(
+                data.try_into().map_err(|_| invalid_value())?,
+            )),
+            field_type::U64 => Value::U64(u64::from_le_bytes(
+                data.try_into().map_err(|_| invalid_value())?,
+            )),
+            field_type::I128 => Value::I128(i128::from_le_bytes(
+                data.try_into().map_err(|_| invalid_value())?,
+            )),
+            field_type::U128 => Value::U128(u128::from_le_bytes(
+                data.try_into().map_err(|_| invalid_value())?,
+            )),
+            field_type::F64 => Value::F64(f64::from_le_bytes(
+                data.try_into().map_err(|_| invalid_value())?,
+            )),
             field_type::STR => {
                 let value = Arc::from(String::from_utf8_lossy(data));
                 Value::String(value)
@@ -46,9 +77,10 @@ impl Value {
                 Value::Error(value)
             }
             _ => {
-                panic!("unknown field type: {}", kind);
+                return Err(invalid_data(format!("unknown field type: {}", kind)));
             }
-        }
+        };
+        Ok(value)
     }
 }
 
@@ -63,6 +95,7 @@ impl Display for Value {
             Value::F64(value) => value.fmt(f),
             Value::String(value) => value.fmt(f),
             Value::Error(value) => value.fmt(f),
+            Value::Empty => Ok(()),
         }
     }
 }
@@ -92,6 +125,12 @@ struct Intermediate {
     opened_spans: HashMap<u64, petgraph::stable_graph::NodeIndex<usize>>,
     context: HashMap<u64, Vec<petgraph::stable_graph::NodeIndex<usize>>>,
     threads: HashMap<u64, Option<String>>,
+
+    /// Pairs of spans where the first span follows from the second one.
+    follows_from: Vec<(
+        petgraph::stable_graph::NodeIndex<usize>,
+        petgraph::stable_graph::NodeIndex<usize>,
+    )>,
 }
 
 impl Default for Intermediate {
@@ -111,32 +150,69 @@ impl Default for Intermediate {
             opened_spans: HashMap::default(),
             threads: HashMap::default(),
             context: HashMap::default(),
+            follows_from: Vec::new(),
+        }
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Splits the record at the start of the data into the record of type `T`, the
+/// bytes of the whole record, and the remaining data.
+fn split_record<T: FromBytes>(data: &[u8]) -> std::io::Result<(&T, &[u8], &[u8])> {
+    let len = RecordHeader::ref_from_prefix(data).map(|header| header.len.get() as usize);
+    match len {
+        Some(len) if len >= std::mem::size_of::<T>() && len <= data.len() => {
+            let (bytes, remaining) = data.split_at(len);
+            let record = T::ref_from_prefix(bytes)
+                .ok_or_else(|| invalid_data(format!("invalid record of kind {}", data[0])))?;
+            Ok((record, bytes, remaining))
         }
+        _ => Err(invalid_data(format!(
+            "truncated record of kind {}",
+            data[0]
+        ))),
     }
 }
 
+/// Returns the bytes of a string of the record.
+fn record_str(bytes: &[u8], offset: usize, len: usize) -> std::io::Result<&[u8]> {
+    bytes
+        .get(offset..offset + len)
+        .ok_or_else(|| invalid_data("string exceeds its record"))
+}
+
+fn unknown_span(id: u64) -> std::io::Error {
+    invalid_data(format!("unknown span {}", id))
+}
+
 impl Intermediate {
-    fn callsite<'a>(&mut self, slice: &'a [u8]) -> &'a [u8] {
-        let (callsite, remaining) = IntermediateCallsite::parse(slice);
+    fn callsite<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (callsite, remaining) = IntermediateCallsite::parse(slice)?;
 
         if callsite.fields.capacity() == 0 {
             self.callsites.push(callsite);
         } else {
             self.intermediate_callsites.insert(callsite.id, callsite);
         }
-        remaining
+        Ok(remaining)
     }
 
-    fn callsite_field<'a>(&mut self, slice: &'a [u8]) -> &'a [u8] {
-        let callsite_field_record = CallsiteFieldRecord::ref_from_prefix(slice).unwrap();
+    fn callsite_field<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (callsite_field_record, bytes, remaining) = split_record::<CallsiteFieldRecord>(slice)?;
 
         let name_len = callsite_field_record.field_name_len.get() as usize;
         let offset = std::mem::size_of::<CallsiteFieldRecord>();
-        let name = &slice[offset..offset + name_len];
+        let name = record_str(bytes, offset, name_len)?;
         let name = Arc::from(String::from_utf8_lossy(name));
 
         let callsite_id = callsite_field_record.callsite_id.get();
-        let mut callsite = self.intermediate_callsites.remove(&callsite_id).unwrap();
+        let mut callsite = self
+            .intermediate_callsites
+            .remove(&callsite_id)
+            .ok_or_else(|| invalid_data(format!("field of unknown callsite {}", callsite_id)))?;
         callsite.fields.push(Field {
             name,
             id: callsite_field_record.field_id.get(),
@@ -147,25 +223,47 @@ impl Intermediate {
             self.intermediate_callsites.insert(callsite_id, callsite);
         }
 
-        &slice[callsite_field_record.header.len.get() as usize..]
+        Ok(remaining)
+    }
+
+    fn thread_name<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (thread_name_record, bytes, remaining) = split_record::<ThreadNameRecord>(slice)?;
+
+        let name_len = thread_name_record.name_len.get() as usize;
+        let offset = std::mem::size_of::<ThreadNameRecord>();
+        let name = String::from_utf8_lossy(record_str(bytes, offset, name_len)?).into_owned();
+        self.threads
+            .insert(thread_name_record.thread_id.get(), Some(name));
+
+        Ok(remaining)
     }
 
-    fn event<'a>(&mut self, slice: &'a [u8]) -> &'a [u8] {
-        let event_record = EventRecord::ref_from_prefix(slice).unwrap();
+    fn event<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (event_record, _, remaining) = split_record::<EventRecord>(slice)?;
 
         // TODO: change once try_insert is stable
         self.threads
             .entry(event_record.thread_id.get())
             .or_insert(None);
 
+        let thread_id = event_record.thread_id.get();
         let event = IntermediateEvent {
             timestamp: event_record.timestamp.get(),
             callsite_id: event_record.callsite_id.get(),
+            thread_id,
+            span: self
+                .context
+                .get(&thread_id)
+                .and_then(|context| context.last().copied()),
             values: Vec::with_capacity(event_record.value_count.get() as usize),
         };
 
-        let thread_id = event_record.thread_id.get();
-        assert!(!self.intermediate_events.contains_key(&thread_id));
+        if self.intermediate_events.contains_key(&thread_id) {
+            return Err(invalid_data(format!(
+                "event on thread {} before the values of the previous one",
+                thread_id
+            )));
+        }
 
         if event.values.capacity() == 0 {
             self.events.push(event);
@@ -176,21 +274,21 @@ impl Intermediate {
         self.min_timestamp = self.min_timestamp.min(event_record.timestamp.get());
         self.max_timestamp = self.max_timestamp.max(event_record.timestamp.get());
 
-        &slice[event_record.header.len.get() as usize..]
+        Ok(remaining)
     }
 
-    fn event_value<'a>(&mut self, slice: &'a [u8]) -> &'a [u8] {
-        let event_value_record = EventValueRecord::ref_from_prefix(slice).unwrap();
-
-        let value_len =
-            event_value_record.header.len.get() as usize - std::mem::size_of::<EventValueRecord>();
-        let value = &slice[std::mem::size_of::<EventValueRecord>()..][..value_len];
+    fn event_value<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (event_value_record, bytes, remaining) = split_record::<EventValueRecord>(slice)?;
 
+        let value = &bytes[std::mem::size_of::<EventValueRecord>()..];
         let kind = event_value_record.kind;
-        let value = Value::parse(kind, value);
+        let value = Value::parse(kind, value)?;
 
         let thread_id = event_value_record.thread_id.get();
-        let mut event = self.intermediate_events.remove(&thread_id).unwrap();
+        let mut event = self
+            .intermediate_events
+            .remove(&thread_id)
+            .ok_or_else(|| invalid_data(format!("value of no event on thread {}", thread_id)))?;
 
         // TODO: use push_within_capacity once it's stable
         event.values.push(IntermediateValue {
@@ -204,19 +302,15 @@ impl Intermediate {
             self.intermediate_events.insert(thread_id, event);
         }
 
-        &slice[event_value_record.header.len.get() as usize..]
+        Ok(remaining)
     }
 
-    fn open_span<'a>(&mut self, slice: &'a [u8]) -> &'a [u8] {
-        let span_record = if slice.len() >= std::mem::size_of::<SpanOpenRecord2>() {
-            SpanOpenRecord2::ref_from_prefix(slice).unwrap().clone()
-        } else if slice.len() >= std::mem::size_of::<SpanOpenRecord>() {
-            SpanOpenRecord::ref_from_prefix(slice)
-                .unwrap()
-                .clone()
-                .into()
-        } else {
-            panic!("invalid span record");
+    fn open_span<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        // Tapes of older versions do not store the kind of the parent.
+        let (span_record, _, remaining) = split_record::<SpanOpenRecord>(slice)?;
+        let span_record = match split_record::<SpanOpenRecord2>(slice) {
+            Ok((span_record, _, _)) => *span_record,
+            Err(_) => (*span_record).into(),
         };
 
         let span = IntermediateSpan {
@@ -240,17 +334,21 @@ impl Intermediate {
         let index = self.span_graph.add_node(span);
         self.opened_spans.insert(span_id, index);
 
-        &slice[span_record.span_open_record.header.len.get() as usize..]
+        Ok(remaining)
     }
 
-    fn enter_span<'a>(&mut self, slice: &'a [u8]) -> &'a [u8] {
-        let span_enter_record = SpanEnterRecord::ref_from_prefix(slice).unwrap();
+    fn enter_span<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (span_enter_record, _, remaining) = split_record::<SpanEnterRecord>(slice)?;
 
         self.threads
             .entry(span_enter_record.thread_id.get())
             .or_insert(None);
 
-        let index = self.opened_spans[&span_enter_record.id.get()];
+        let span_id = span_enter_record.id.get();
+        let index = *self
+            .opened_spans
+            .get(&span_id)
+            .ok_or_else(|| unknown_span(span_id))?;
         let span = &mut self.span_graph[index];
         let thread_id = span_enter_record.thread_id.get();
         span.entrances.push(SpanEntrance {
@@ -263,35 +361,41 @@ impl Intermediate {
             .or_insert_with(Vec::new)
             .push(index);
 
-        &slice[span_enter_record.header.len.get() as usize..]
+        Ok(remaining)
     }
 
-    fn exit_span<'a>(&mut self, slice: &'a [u8]) -> &'a [u8] {
-        let span_exit_record = SpanExitRecord::ref_from_prefix(slice).unwrap();
+    fn exit_span<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (span_exit_record, _, remaining) = split_record::<SpanExitRecord>(slice)?;
 
-        let index = self.opened_spans[&span_exit_record.id.get()];
+        let span_id = span_exit_record.id.get();
+        let index = *self
+            .opened_spans
+            .get(&span_id)
+            .ok_or_else(|| unknown_span(span_id))?;
         let span = &mut self.span_graph[index];
-        let last_entrance = span.entrances.last_mut().unwrap();
+        let not_entered = || invalid_data(format!("exit of span {} that is not entered", span_id));
+        let last_entrance = span.entrances.last_mut().ok_or_else(not_entered)?;
         last_entrance.exited = span_exit_record.timestamp.get();
 
-        assert_eq!(
-            self.context
-                .get_mut(&last_entrance.thread_id)
-                .unwrap()
-                .pop(),
-            Some(index)
-        );
+        let context = self.context.get_mut(&last_entrance.thread_id);
+        if context.and_then(|context| context.pop()) != Some(index) {
+            return Err(not_entered());
+        }
 
-        &slice[span_exit_record.header.len.get() as usize..]
+        Ok(remaining)
     }
 
-    fn close_span<'a>(&mut self, slice: &'a [u8]) -> &'a [u8] {
-        let span_record = SpanCloseRecord::ref_from_prefix(slice).unwrap();
+    fn close_span<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (span_record, _, remaining) = split_record::<SpanCloseRecord>(slice)?;
 
         self.min_timestamp = self.min_timestamp.min(span_record.timestamp.get());
         self.max_timestamp = self.max_timestamp.max(span_record.timestamp.get());
 
-        let span_index = self.opened_spans.remove(&span_record.id.get()).unwrap();
+        let span_id = span_record.id.get();
+        let span_index = self
+            .opened_spans
+            .remove(&span_id)
+            .ok_or_else(|| unknown_span(span_id))?;
         let span = &mut self.span_graph[span_index];
         span.closed = span_record.timestamp.get();
 
@@ -299,7 +403,8 @@ impl Intermediate {
             self.root_nodes.push(span_index);
         } else if let Some(last_entrance) = span.entrances.last() {
             let thread_id = last_entrance.thread_id;
-            if let Some(parent_index) = self.context[&thread_id].last() {
+            let context = self.context.get(&thread_id);
+            if let Some(parent_index) = context.and_then(|context| context.last()) {
                 self.span_graph.add_edge(*parent_index, span_index, ());
             } else {
                 self.root_nodes.push(span_index);
@@ -308,28 +413,40 @@ impl Intermediate {
             self.root_nodes.push(span_index);
         }
 
-        &slice[span_record.header.len.get() as usize..]
+        Ok(remaining)
     }
 
-    fn span_value<'a>(&mut self, slice: &'a [u8]) -> &'a [u8] {
-        let span_value_record = SpanValueRecord::ref_from_prefix(slice).unwrap();
-
-        let value_len =
-            span_value_record.header.len.get() as usize - std::mem::size_of::<SpanValueRecord>();
-        let value = &slice[std::mem::size_of::<SpanValueRecord>()..][..value_len];
+    fn span_value<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (span_value_record, bytes, remaining) = split_record::<SpanValueRecord>(slice)?;
 
+        let value = &bytes[std::mem::size_of::<SpanValueRecord>()..];
         let kind = span_value_record.kind;
-        let value = Value::parse(kind, value);
+        let value = Value::parse(kind, value)?;
 
         let span_id = span_value_record.span_id.get();
-        let index = self.opened_spans[&span_id];
+        let index = *self
+            .opened_spans
+            .get(&span_id)
+            .ok_or_else(|| unknown_span(span_id))?;
         let span = &mut self.span_graph[index];
         span.values.insert(span_value_record.field_id.get(), value);
 
-        &slice[span_value_record.header.len.get() as usize..]
+        Ok(remaining)
     }
 
-    fn parse(&mut self, mut data: &[u8]) -> Result<(), u8> {
+    fn span_follows<'a>(&mut self, slice: &'a [u8]) -> std::io::Result<&'a [u8]> {
+        let (span_follows_record, _, remaining) = split_record::<SpanFollowsRecord>(slice)?;
+
+        let span = self.opened_spans.get(&span_follows_record.span_id.get());
+        let follows = self.opened_spans.get(&span_follows_record.follows_id.get());
+        if let (Some(span), Some(follows)) = (span, follows) {
+            self.follows_from.push((*span, *follows));
+        }
+
+        Ok(remaining)
+    }
+
+    fn parse(&mut self, mut data: &[u8]) -> std::io::Result<()> {
         while !data.is_empty() {
             let record_kind = data[0];
 
@@ -337,36 +454,42 @@ impl Intermediate {
                 record_kind::NOOP => {
                     data = &data[1..];
                 }
+                record_kind::THREAD_NAME => {
+                    data = self.thread_name(data)?;
+                }
                 record_kind::CALLSITE => {
-                    data = self.callsite(data);
+                    data = self.callsite(data)?;
                 }
                 record_kind::CALLSITE_FIELD => {
-                    data = self.callsite_field(data);
+                    data = self.callsite_field(data)?;
                 }
                 record_kind::SPAN_OPEN => {
-                    data = self.open_span(data);
+                    data = self.open_span(data)?;
                 }
                 record_kind::SPAN_ENTER => {
-                    data = self.enter_span(data);
+                    data = self.enter_span(data)?;
                 }
                 record_kind::SPAN_EXIT => {
-                    data = self.exit_span(data);
+                    data = self.exit_span(data)?;
                 }
                 record_kind::SPAN_CLOSE => {
-                    data = self.close_span(data);
+                    data = self.close_span(data)?;
                 }
                 record_kind::SPAN_VALUE => {
-                    data = self.span_value(data);
+                    data = self.span_value(data)?;
+                }
+                record_kind::SPAN_FOLLOWS => {
+                    data = self.span_follows(data)?;
                 }
                 record_kind::EVENT => {
-                    data = self.event(data);
+                    data = self.event(data)?;
                 }
                 record_kind::EVENT_VALUE => {
-                    data = self.event_value(data);
+                    data = self.event_value(data)?;
                 }
                 _ => {
-                    let header = RecordHeader::ref_from_prefix(data).unwrap();
-                    data = &data[header.len.get() as usize..];
+                    let (_, _, remaining) = split_record::<RecordHeader>(data)?;
+                    data = remaining;
                 }
             }
         }
@@ -385,6 +508,8 @@ struct IntermediateValue {
 struct IntermediateEvent {
     timestamp: i64,
     callsite_id: u64,
+    thread_id: u64,
+    span: Option<petgraph::stable_graph::NodeIndex<usize>>,
     values: Vec<IntermediateValue>,
 }
 
@@ -408,26 +533,32 @@ struct IntermediateSpan {
     values: HashMap<u64, Value>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SpanEntrance {
     pub entered: i64,
     pub exited: i64,
     pub thread_id: u64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Span {
     pub opened: i64,
     pub closed: i64,
     pub callsite_index: usize,
     pub entrances: Arc<[SpanEntrance]>,
+    /// The values of the span, one for each field of the callsite.
     pub values: Arc<[Value]>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Event {
     pub timestamp: i64,
     pub callsite_index: usize,
+    pub thread_id: u64,
+    /// The innermost span that was entered on the thread when the event was
+    /// recorded.
+    pub span: Option<petgraph::graph::NodeIndex<usize>>,
+    /// The values of the event, one for each field of the callsite.
     pub values: Arc<[Value]>,
 }
 
@@ -541,16 +672,19 @@ impl From<IntermediateCallsite> for Callsite {
 }
 
 impl IntermediateCallsite {
-    fn parse(slice: &[u8]) -> (Self, &[u8]) {
-        let callsite_record = CallsiteRecord::ref_from_prefix(slice).unwrap();
-
-        let remaining = &slice[callsite_record.header.len.get() as usize..];
-
-        let slice = &slice[std::mem::size_of::<CallsiteRecord>()..];
-        let (name, slice) = slice.split_at(callsite_record.name_len.get() as usize);
-        let (target, slice) = slice.split_at(callsite_record.target_len.get() as usize);
-        let (module_path, slice) = slice.split_at(callsite_record.module_path_len.get() as usize);
-        let (file, _) = slice.split_at(callsite_record.file_len.get() as usize);
+    fn parse(slice: &[u8]) -> std::io::Result<(Self, &[u8])> {
+        let (callsite_record, bytes, remaining) = split_record::<CallsiteRecord>(slice)?;
+
+        let mut offset = std::mem::size_of::<CallsiteRecord>();
+        let mut next = |len: u16| {
+            let text = record_str(bytes, offset, len as usize);
+            offset += len as usize;
+            text
+        };
+        let name = next(callsite_record.name_len.get())?;
+        let target = next(callsite_record.target_len.get())?;
+        let module_path = next(callsite_record.module_path_len.get())?;
+        let file = next(callsite_record.file_len.get())?;
 
         let name = Arc::from(String::from_utf8_lossy(name));
         let target = Arc::from(String::from_utf8_lossy(target));
@@ -568,8 +702,14 @@ impl IntermediateCallsite {
 
         let callsite = Self {
             id: callsite_record.id.get(),
-            kind: callsite_record.info.kind().expect("invalid kind"),
-            level: callsite_record.info.level().expect("invalid level"),
+            kind: callsite_record
+                .info
+                .kind()
+                .ok_or_else(|| invalid_data("invalid callsite kind"))?,
+            level: callsite_record
+                .info
+                .level()
+                .ok_or_else(|| invalid_data("invalid callsite level"))?,
             name,
             target,
             module_path,
@@ -578,7 +718,7 @@ impl IntermediateCallsite {
             fields: Vec::with_capacity(callsite_record.field_count.get() as usize),
         };
 
-        (callsite, remaining)
+        Ok((callsite, remaining))
     }
 }
 
@@ -590,11 +730,16 @@ pub struct TapeData {
     events: Vec<Event>,
     spans: petgraph::graph::Graph<Span, (), petgraph::Directed, usize>,
     root_spans: Vec<petgraph::graph::NodeIndex<usize>>,
+    follows_from: Vec<(
+        petgraph::graph::NodeIndex<usize>,
+        petgraph::graph::NodeIndex<usize>,
+    )>,
     threads: HashMap<u64, Option<String>>,
+    index: index::SpanIndex,
 }
 
 impl TapeData {
-    fn new(intermediate: Intermediate) -> Self {
+    fn new(intermediate: Intermediate) -> std::io::Result<Self> {
         let mut callsite_map = HashMap::default();
         let mut callsite_field_map = HashMap::default();
         let callsites = intermediate
@@ -610,29 +755,29 @@ impl TapeData {
 
                 callsite.into()
             })
-            .collect::<Vec<_>>();
+            .collect::<Vec<Callsite>>();
 
-        let mut events = intermediate.events;
-        events.sort_by_key(|event| event.timestamp);
-        let events = events
-            .into_iter()
-            .map(|event| {
-                let mut values = event.values;
-                values.sort_by_cached_key(|value| {
-                    callsite_field_map[&(event.callsite_id, value.field_id)]
-                });
-                let values = values
-                    .into_iter()
-                    .map(|value| value.value)
-                    .collect::<Vec<_>>();
+        let callsite_index = |callsite_id: u64| -> std::io::Result<usize> {
+            callsite_map
+                .get(&callsite_id)
+                .copied()
+                .ok_or_else(|| invalid_data(format!("unknown callsite {}", callsite_id)))
+        };
 
-                Event {
-                    timestamp: event.timestamp,
-                    callsite_index: callsite_map[&event.callsite_id],
-                    values: Arc::from(values.into_boxed_slice()),
+        // Values are stored in the order of the callsite fields, fields that were never recorded
+        // are filled with `Value::Empty`.
+        let field_values =
+            |callsite_id: u64, values: Vec<(u64, Value)>| -> std::io::Result<Arc<[Value]>> {
+                let field_count = callsites[callsite_index(callsite_id)?].fields().len();
+                let mut field_values = vec![Value::Empty; field_count];
+                for (field_id, value) in values {
+                    let index = callsite_field_map
+                        .get(&(callsite_id, field_id))
+                        .ok_or_else(|| invalid_data(format!("unknown field {}", field_id)))?;
+                    field_values[*index] = value;
                 }
-            })
-            .collect();
+                Ok(Arc::from(field_values.into_boxed_slice()))
+            };
 
         struct SpanMapping {
             old_children: Vec<petgraph::stable_graph::NodeIndex<usize>>,
@@ -640,6 +785,7 @@ impl TapeData {
         }
 
         let mut root_nodes = vec![];
+        let mut node_map = HashMap::default();
         let mut intermediate_graph = intermediate.span_graph;
         let mut spans = petgraph::Graph::with_capacity(
             intermediate_graph.node_count(),
@@ -655,25 +801,19 @@ impl TapeData {
             //     continue;
             // }
 
-            let callsite_index = callsite_map[&intermediate_span.callsite_id];
-            let mut values = intermediate_span.values.into_iter().collect::<Vec<_>>();
-            values.sort_by_cached_key(|(field_id, _)| {
-                callsite_field_map[&(intermediate_span.callsite_id, *field_id)]
-            });
-            let value = values
-                .into_iter()
-                .map(|(_, value)| value)
-                .collect::<Vec<_>>();
+            let callsite_index = callsite_index(intermediate_span.callsite_id)?;
+            let values = intermediate_span.values.into_iter().collect();
 
             let span = Span {
                 callsite_index,
                 opened: intermediate_span.opened,
                 closed: intermediate_span.closed,
                 entrances: Arc::from(intermediate_span.entrances.into_boxed_slice()),
-                values: Arc::from(value.into_boxed_slice()),
+                values: field_values(intermediate_span.callsite_id, values)?,
             };
 
             let span_node = spans.add_node(span);
+            node_map.insert(node, span_node);
             root_nodes.push(span_node);
             if !children.is_empty() {
                 nodes_to_process.push(SpanMapping {
@@ -695,25 +835,19 @@ impl TapeData {
                 //     continue;
                 // }
 
-                let callsite_index = callsite_map[&intermediate_span.callsite_id];
-                let mut values = intermediate_span.values.into_iter().collect::<Vec<_>>();
-                values.sort_by_cached_key(|(field_id, _)| {
-                    callsite_field_map[&(intermediate_span.callsite_id, *field_id)]
-                });
-                let value = values
-                    .into_iter()
-                    .map(|(_, value)| value)
-                    .collect::<Vec<_>>();
+                let callsite_index = callsite_index(intermediate_span.callsite_id)?;
+                let values = intermediate_span.values.into_iter().collect();
 
                 let span = Span {
                     callsite_index,
                     opened: intermediate_span.opened,
                     closed: intermediate_span.closed,
                     entrances: Arc::from(intermediate_span.entrances.into_boxed_slice()),
-                    values: Arc::from(value.into_boxed_slice()),
+                    values: field_values(intermediate_span.callsite_id, values)?,
                 };
 
                 let span_node = spans.add_node(span);
+                node_map.insert(child, span_node);
                 spans.add_edge(parent, span_node, ());
                 if !children.is_empty() {
                     nodes_to_process.push(SpanMapping {
@@ -724,15 +858,46 @@ impl TapeData {
             }
         }
 
-        Self {
+        let mut events = intermediate.events;
+        events.sort_by_key(|event| event.timestamp);
+        let events = events
+            .into_iter()
+            .map(|event| {
+                let values = event
+                    .values
+                    .into_iter()
+                    .map(|value| (value.field_id, value.value))
+                    .collect();
+
+                Ok(Event {
+                    timestamp: event.timestamp,
+                    callsite_index: callsite_index(event.callsite_id)?,
+                    thread_id: event.thread_id,
+                    // Spans that have never been closed are not part of the tape.
+                    span: event.span.and_then(|span| node_map.get(&span).copied()),
+                    values: field_values(event.callsite_id, values)?,
+                })
+            })
+            .collect::<std::io::Result<_>>()?;
+
+        Ok(Self {
             min_timestamp: intermediate.min_timestamp,
             max_timestamp: intermediate.max_timestamp,
             callsites,
             events,
+            index: index::SpanIndex::new(&spans),
             spans,
             root_spans: root_nodes,
+            // Spans that have never been closed are not part of the tape.
+            follows_from: intermediate
+                .follows_from
+                .into_iter()
+                .filter_map(|(span, follows)| {
+                    Some((*node_map.get(&span)?, *node_map.get(&follows)?))
+                })
+                .collect(),
             threads: intermediate.threads,
-        }
+        })
     }
 }
 
@@ -743,17 +908,19 @@ pub struct Tape {
 }
 
 impl Tape {
-    pub fn parse(data: &[u8]) -> Self {
-        let intro = Intro::read_from_prefix(data).unwrap();
+    /// Parses a tape, returning an [`std::io::ErrorKind::InvalidData`] error
+    /// if the data is not a valid tape.
+    pub fn parse(data: &[u8]) -> std::io::Result<Self> {
+        let intro = Intro::read_from_prefix(data)
+            .filter(|intro| intro.magic == tracing_tape::intro::MAGIC)
+            .ok_or_else(|| invalid_data("not a tape"))?;
 
         let mut intermediate = Intermediate::default();
-        intermediate
-            .parse(&data[std::mem::size_of::<Intro>()..])
-            .unwrap();
+        intermediate.parse(&data[std::mem::size_of::<Intro>()..])?;
 
-        let data = TapeData::new(intermediate);
+        let data = TapeData::new(intermediate)?;
 
-        Self { intro, data }
+        Ok(Self { intro, data })
     }
 
     pub fn time_range(&self) -> std::ops::RangeInclusive<i128> {
@@ -762,6 +929,16 @@ impl Tape {
         start..=end
     }
 
+    pub fn intro(&self) -> &Intro {
+        &self.intro
+    }
+
+    /// The absolute time in nanoseconds since the unix epoch that all
+    /// timestamps of the tape are relative to.
+    pub fn timestamp_base(&self) -> i128 {
+        self.intro.timestamp_base.get()
+    }
+
     pub fn timestamp_range(&self) -> std::ops::RangeInclusive<i64> {
         self.data.min_timestamp..=self.data.max_timestamp
     }
@@ -785,4 +962,14 @@ impl Tape {
     pub fn threads(&self) -> &HashMap<u64, Option<String>> {
         &self.data.threads
     }
+
+    /// Pairs of spans where the first span follows from the second one.
+    pub fn follows_from(
+        &self,
+    ) -> &[(
+        petgraph::graph::NodeIndex<usize>,
+        petgraph::graph::NodeIndex<usize>,
+    )] {
+        &self.data.follows_from
+    }
 }
@@ -0,0 +1,599 @@
+//! Filtering of spans and events without walking the span graph by hand.
+//!
+//! Queries are created using [Tape::query_spans] or [Tape::query_events] and
+//! narrowed down by chaining filters. All filters must match for a span or
+//! event to be returned. The results are produced lazily by [Query::iter].
+//!
+//! ```
+//! # let tape = tracing_tape_parser::TapeBuilder::new(16, 0).finish();
+//! use tracing_tape_parser::query::FieldPredicate;
+//!
+//! // Slow database queries of failed requests by bob.
+//! let requests = tape
+//!     .query_spans()
+//!     .name("request")
+//!     .field("status >= 500".parse::<FieldPredicate>()?)
+//!     .field(r#"user == "bob""#.parse::<FieldPredicate>()?);
+//! for (index, span) in tape
+//!     .query_spans()
+//!     .target("db")
+//!     .min_duration(10_000_000)
+//!     .has_ancestor(requests)
+//!     .iter()
+//! {
+//!     println!("{:?}: {}ns", index, span.closed - span.opened);
+//! }
+//! # Ok::<(), tracing_tape_parser::query::ParsePredicateError>(())
+//! ```
+
+use std::{cmp::Ordering, marker::PhantomData, ops::RangeInclusive, str::FromStr, sync::Arc};
+
+use petgraph::{graph::NodeIndex, visit::Dfs, Direction};
+
+use crate::{Callsite, Event, Span, Tape, Value};
+
+/// Marker for queries over spans.
+#[derive(Debug, Clone, Copy)]
+pub struct Spans;
+
+/// Marker for queries over events.
+#[derive(Debug, Clone, Copy)]
+pub struct Events;
+
+/// A comparison of a field value with a constant, e.g., `status >= 500`.
+///
+/// Numbers are compared by their value regardless of their type and strings
+/// are compared lexicographically. Values of other types are only equal or
+/// not equal. Spans and events that have not recorded the field never match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldPredicate {
+    pub field: String,
+    pub operator: Operator,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Operator {
+    /// Operators in the order they are searched for, such that `<=` is not
+    /// mistaken for `<`.
+    const SYMBOLS: [(&'static str, Operator); 7] = [
+        ("==", Operator::Equal),
+        ("!=", Operator::NotEqual),
+        ("<=", Operator::LessOrEqual),
+        (">=", Operator::GreaterOrEqual),
+        ("<", Operator::Less),
+        (">", Operator::Greater),
+        ("=", Operator::Equal),
+    ];
+
+    fn matches(self, ordering: Option<Ordering>) -> bool {
+        match (self, ordering) {
+            (Operator::NotEqual, None) => true,
+            (_, None) => false,
+            (Operator::Equal, Some(ordering)) => ordering.is_eq(),
+            (Operator::NotEqual, Some(ordering)) => ordering.is_ne(),
+            (Operator::Less, Some(ordering)) => ordering.is_lt(),
+            (Operator::LessOrEqual, Some(ordering)) => ordering.is_le(),
+            (Operator::Greater, Some(ordering)) => ordering.is_gt(),
+            (Operator::GreaterOrEqual, Some(ordering)) => ordering.is_ge(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePredicateError(String);
+
+impl std::fmt::Display for ParsePredicateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid field predicate `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParsePredicateError {}
+
+impl FromStr for FieldPredicate {
+    type Err = ParsePredicateError;
+
+    /// Parses predicates of the form `field <operator> value`. Values can be
+    /// numbers, `true`, `false`, or strings which may be quoted. A single `=`
+    /// is the same as `==`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParsePredicateError(s.to_string());
+        let (position, symbol, operator) = Operator::SYMBOLS
+            .iter()
+            .filter_map(|(symbol, operator)| Some((s.find(symbol)?, *symbol, *operator)))
+            .min_by_key(|(position, symbol, _)| (*position, std::cmp::Reverse(symbol.len())))
+            .ok_or_else(error)?;
+
+        let field = s[..position].trim();
+        let value = s[position + symbol.len()..].trim();
+        if field.is_empty() || value.is_empty() {
+            return Err(error());
+        }
+
+        Ok(FieldPredicate {
+            field: field.to_string(),
+            operator,
+            value: parse_literal(value),
+        })
+    }
+}
+
+fn parse_literal(literal: &str) -> Value {
+    let quoted = ['"', '\''].iter().find_map(|quote| {
+        literal
+            .strip_prefix(*quote)
+            .and_then(|literal| literal.strip_suffix(*quote))
+    });
+    if let Some(string) = quoted {
+        Value::String(string.into())
+    } else if let Ok(value) = literal.parse::<bool>() {
+        Value::Bool(value)
+    } else if let Ok(value) = literal.parse::<i64>() {
+        Value::I64(value)
+    } else if let Ok(value) = literal.parse::<i128>() {
+        Value::I128(value)
+    } else if let Ok(value) = literal.parse::<f64>() {
+        Value::F64(value)
+    } else {
+        Value::String(literal.into())
+    }
+}
+
+impl Value {
+    fn as_i128(&self) -> Option<i128> {
+        match self {
+            Value::I64(value) => Some(*value as i128),
+            Value::U64(value) => Some(*value as i128),
+            Value::I128(value) => Some(*value),
+            Value::U128(value) => i128::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a floating point number if it is numeric.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::I64(value) => Some(*value as f64),
+            Value::U64(value) => Some(*value as f64),
+            Value::I128(value) => Some(*value as f64),
+            Value::U128(value) => Some(*value as f64),
+            Value::F64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Compares values of compatible types, see [FieldPredicate].
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Empty, _) | (_, Value::Empty) => None,
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)).filter(|ordering| ordering.is_eq()),
+            (Value::String(a) | Value::Error(a), Value::String(b) | Value::Error(b)) => {
+                Some(a.cmp(b))
+            }
+            _ => match (self.as_i128(), other.as_i128()) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => self.as_f64()?.partial_cmp(&other.as_f64()?),
+            },
+        }
+    }
+}
+
+impl FieldPredicate {
+    /// Returns whether a field of a span or event with the given callsite and
+    /// values matches.
+    pub fn matches(&self, callsite: &Callsite, values: &[Value]) -> bool {
+        callsite
+            .fields()
+            .iter()
+            .zip(values.iter())
+            .any(|(field, value)| {
+                **field == *self.field
+                    && !matches!(value, Value::Empty)
+                    && self.operator.matches(value.compare(&self.value))
+            })
+    }
+}
+
+/// Filters that apply to spans as well as events.
+#[derive(Clone)]
+struct Filter {
+    callsite_index: Option<usize>,
+    name: Option<Arc<str>>,
+    target: Option<Arc<str>>,
+    level: Option<tracing::Level>,
+    range: Option<RangeInclusive<i64>>,
+    thread_id: Option<u64>,
+    fields: Vec<FieldPredicate>,
+}
+
+impl Filter {
+    fn matches_callsite(&self, index: usize, callsite: &Callsite) -> bool {
+        self.callsite_index.is_none_or(|i| i == index)
+            && self
+                .name
+                .as_deref()
+                .is_none_or(|name| callsite.name() == name)
+            && self
+                .target
+                .as_deref()
+                .is_none_or(|target| callsite.target().starts_with(target))
+            && self.level.is_none_or(|level| callsite.level() <= level)
+    }
+}
+
+/// Filters on the relations between spans.
+#[derive(Clone)]
+enum Relation<'a> {
+    DescendantOf(NodeIndex<usize>),
+    AncestorOf(NodeIndex<usize>),
+    HasAncestor(Query<'a, Spans>),
+    HasDescendant(Query<'a, Spans>),
+}
+
+/// A query over the spans or events of a tape, see the [module documentation](self).
+#[derive(Clone)]
+pub struct Query<'a, K> {
+    tape: &'a Tape,
+    filter: Filter,
+    min_duration: Option<i64>,
+    max_duration: Option<i64>,
+    relations: Vec<Relation<'a>>,
+    kind: PhantomData<K>,
+}
+
+impl Tape {
+    /// Creates a query that matches all spans of the tape.
+    pub fn query_spans(&self) -> Query<'_, Spans> {
+        Query::new(self)
+    }
+
+    /// Creates a query that matches all events of the tape.
+    pub fn query_events(&self) -> Query<'_, Events> {
+        Query::new(self)
+    }
+
+    /// Returns the parent of a span or `None` for root spans.
+    pub fn parent(&self, span: NodeIndex<usize>) -> Option<NodeIndex<usize>> {
+        self.spans()
+            .neighbors_directed(span, Direction::Incoming)
+            .next()
+    }
+
+    /// Iterates over the ancestors of a span starting with its parent.
+    pub fn ancestors(&self, span: NodeIndex<usize>) -> impl Iterator<Item = NodeIndex<usize>> + '_ {
+        std::iter::successors(self.parent(span), |span| self.parent(*span))
+    }
+
+    /// Returns the events that were recorded while the span was the innermost
+    /// entered span on their thread, ordered by time. Events of descendant
+    /// spans are not included.
+    pub fn events_in_span(&self, span: NodeIndex<usize>) -> impl Iterator<Item = &Event> + '_ {
+        let (opened, closed) = (self.spans()[span].opened, self.spans()[span].closed);
+        let events = self.events();
+        let first = events.partition_point(|event| event.timestamp < opened);
+        let last = events.partition_point(|event| event.timestamp <= closed);
+        events[first..last]
+            .iter()
+            .filter(move |event| event.span == Some(span))
+    }
+
+    /// Iterates over the descendants of a span in depth-first order, not
+    /// including the span itself.
+    pub fn descendants(
+        &self,
+        span: NodeIndex<usize>,
+    ) -> impl Iterator<Item = NodeIndex<usize>> + '_ {
+        let mut dfs = Dfs::new(self.spans(), span);
+        std::iter::from_fn(move || dfs.next(self.spans())).skip(1)
+    }
+}
+
+impl<'a, K> Query<'a, K> {
+    fn new(tape: &'a Tape) -> Self {
+        Self {
+            tape,
+            filter: Filter {
+                callsite_index: None,
+                name: None,
+                target: None,
+                level: None,
+                range: None,
+                thread_id: None,
+                fields: Vec::new(),
+            },
+            min_duration: None,
+            max_duration: None,
+            relations: Vec::new(),
+            kind: PhantomData,
+        }
+    }
+
+    /// Only matches records of the callsite with the given index.
+    pub fn callsite(mut self, callsite_index: usize) -> Self {
+        self.filter.callsite_index = Some(callsite_index);
+        self
+    }
+
+    /// Only matches records whose callsite has the given name.
+    pub fn name(mut self, name: &str) -> Self {
+        self.filter.name = Some(name.into());
+        self
+    }
+
+    /// Only matches records whose target starts with the given prefix, e.g.,
+    /// `my_crate` matches `my_crate::db` as well.
+    pub fn target(mut self, target: &str) -> Self {
+        self.filter.target = Some(target.into());
+        self
+    }
+
+    /// Only matches records with the given or a more severe level, i.e.,
+    /// `Level::INFO` matches `INFO`, `WARN`, and `ERROR`.
+    pub fn level(mut self, level: tracing::Level) -> Self {
+        self.filter.level = Some(level);
+        self
+    }
+
+    /// Only matches spans that overlap the given timestamp range or events
+    /// within the range.
+    pub fn time_range(mut self, range: RangeInclusive<i64>) -> Self {
+        self.filter.range = Some(range);
+        self
+    }
+
+    /// Only matches spans that have been entered on the given thread or events
+    /// recorded on the thread.
+    pub fn thread(mut self, thread_id: u64) -> Self {
+        self.filter.thread_id = Some(thread_id);
+        self
+    }
+
+    /// Only matches records whose field values satisfy the predicate.
+    pub fn field(mut self, predicate: FieldPredicate) -> Self {
+        self.filter.fields.push(predicate);
+        self
+    }
+
+    /// Returns `true` if the callsite can produce matching records.
+    fn matches_callsite(&self, index: usize) -> bool {
+        self.filter
+            .matches_callsite(index, &self.tape.callsites()[index])
+    }
+
+    fn matches_fields(&self, callsite_index: usize, values: &[Value]) -> bool {
+        let callsite = &self.tape.callsites()[callsite_index];
+        self.filter
+            .fields
+            .iter()
+            .all(|predicate| predicate.matches(callsite, values))
+    }
+}
+
+impl<'a> Query<'a, Spans> {
+    /// Only matches spans that are open for at least the given number of
+    /// nanoseconds.
+    pub fn min_duration(mut self, nanos: i64) -> Self {
+        self.min_duration = Some(nanos);
+        self
+    }
+
+    /// Only matches spans that are open for at most the given number of
+    /// nanoseconds.
+    pub fn max_duration(mut self, nanos: i64) -> Self {
+        self.max_duration = Some(nanos);
+        self
+    }
+
+    /// Only matches descendants of the given span.
+    pub fn descendant_of(mut self, span: NodeIndex<usize>) -> Self {
+        self.relations.push(Relation::DescendantOf(span));
+        self
+    }
+
+    /// Only matches ancestors of the given span.
+    pub fn ancestor_of(mut self, span: NodeIndex<usize>) -> Self {
+        self.relations.push(Relation::AncestorOf(span));
+        self
+    }
+
+    /// Only matches spans that have an ancestor matching the other query.
+    pub fn has_ancestor(mut self, query: Query<'a, Spans>) -> Self {
+        self.relations.push(Relation::HasAncestor(query));
+        self
+    }
+
+    /// Only matches spans that have a descendant matching the other query.
+    pub fn has_descendant(mut self, query: Query<'a, Spans>) -> Self {
+        self.relations.push(Relation::HasDescendant(query));
+        self
+    }
+
+    /// Returns `true` if the span matches all filters of the query.
+    pub fn matches(&self, index: NodeIndex<usize>) -> bool {
+        let span = &self.tape.spans()[index];
+        self.matches_callsite(span.callsite_index) && self.matches_span(index, span)
+    }
+
+    /// Checks all filters except for the callsite.
+    fn matches_span(&self, index: NodeIndex<usize>, span: &Span) -> bool {
+        let duration = span.closed - span.opened;
+        self.filter
+            .range
+            .as_ref()
+            .is_none_or(|range| span.opened <= *range.end() && span.closed >= *range.start())
+            && self.min_duration.is_none_or(|min| duration >= min)
+            && self.max_duration.is_none_or(|max| duration <= max)
+            && self.filter.thread_id.is_none_or(|thread_id| {
+                span.entrances
+                    .iter()
+                    .any(|entrance| entrance.thread_id == thread_id)
+            })
+            && self.matches_fields(span.callsite_index, &span.values)
+            && self.relations.iter().all(|relation| match relation {
+                Relation::DescendantOf(ancestor) => {
+                    self.tape.ancestors(index).any(|span| span == *ancestor)
+                }
+                Relation::AncestorOf(descendant) => {
+                    self.tape.ancestors(*descendant).any(|span| span == index)
+                }
+                Relation::HasAncestor(query) => {
+                    self.tape.ancestors(index).any(|span| query.matches(span))
+                }
+                Relation::HasDescendant(query) => {
+                    self.tape.descendants(index).any(|span| query.matches(span))
+                }
+            })
+    }
+
+    /// Lazily iterates over the matching spans.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeIndex<usize>, &'a Span)> + '_ {
+        let tape = self.tape;
+        let callsites = (0..tape.callsites().len())
+            .map(|index| self.matches_callsite(index))
+            .collect::<Vec<_>>();
+
+        // Restricting the search to a subtree or time range avoids visiting
+        // all spans.
+        let subtree = self.relations.iter().find_map(|relation| match relation {
+            Relation::DescendantOf(span) => Some(*span),
+            _ => None,
+        });
+        let candidates: Box<dyn Iterator<Item = NodeIndex<usize>>> =
+            match (subtree, &self.filter.range) {
+                (Some(span), _) => Box::new(tape.descendants(span)),
+                (None, Some(range)) => Box::new(
+                    tape.spans_in_range(range.clone())
+                        .min_duration(self.min_duration.unwrap_or(0)),
+                ),
+                (None, None) => Box::new(tape.spans().node_indices()),
+            };
+
+        candidates.filter_map(move |index| {
+            let span = &tape.spans()[index];
+            (callsites[span.callsite_index] && self.matches_span(index, span))
+                .then_some((index, span))
+        })
+    }
+}
+
+impl<'a> Query<'a, Events> {
+    /// Lazily iterates over the matching events in chronological order.
+    pub fn iter(&self) -> impl Iterator<Item = &'a Event> + '_ {
+        self.iter_indexed().map(|(_, event)| event)
+    }
+
+    /// Lazily iterates over the matching events and their indices in
+    /// [Tape::events] in chronological order.
+    pub fn iter_indexed(&self) -> impl Iterator<Item = (usize, &'a Event)> + '_ {
+        let callsites = (0..self.tape.callsites().len())
+            .map(|index| self.matches_callsite(index))
+            .collect::<Vec<_>>();
+
+        // Events are sorted by their timestamp.
+        let events = self.tape.events();
+        let (start, end) = match &self.filter.range {
+            Some(range) => (
+                events.partition_point(|event| event.timestamp < *range.start()),
+                events.partition_point(|event| event.timestamp <= *range.end()),
+            ),
+            None => (0, events.len()),
+        };
+
+        (start..end)
+            .map(|index| (index, &events[index]))
+            .filter(move |(_, event)| {
+                callsites[event.callsite_index]
+                    && self
+                        .filter
+                        .thread_id
+                        .is_none_or(|thread_id| event.thread_id == thread_id)
+                    && self.matches_fields(event.callsite_index, &event.values)
+            })
+    }
+}
+
+#[test]
+fn test_parse_field_predicate() {
+    let predicate = "status >= 500".parse::<FieldPredicate>().unwrap();
+    assert_eq!(predicate.field, "status");
+    assert_eq!(predicate.operator, Operator::GreaterOrEqual);
+    assert_eq!(predicate.value, Value::I64(500));
+
+    let predicate = r#"user == "bob""#.parse::<FieldPredicate>().unwrap();
+    assert_eq!(predicate.operator, Operator::Equal);
+    assert_eq!(predicate.value, Value::String("bob".into()));
+
+    let predicate = "user=bob".parse::<FieldPredicate>().unwrap();
+    assert_eq!(predicate.field, "user");
+    assert_eq!(predicate.operator, Operator::Equal);
+    let predicate = "status!=500".parse::<FieldPredicate>().unwrap();
+    assert_eq!(predicate.operator, Operator::NotEqual);
+
+    assert!("status".parse::<FieldPredicate>().is_err());
+    assert!("== 5".parse::<FieldPredicate>().is_err());
+}
+
+#[test]
+fn test_query() {
+    let tape = crate::writer::test_tape();
+
+    let outer = tape.query_spans().name("outer");
+    assert_eq!(outer.iter().count(), 2);
+    assert_eq!(
+        outer.clone().field("b < 0".parse().unwrap()).iter().count(),
+        1
+    );
+    assert_eq!(
+        outer
+            .clone()
+            .field("a == true".parse().unwrap())
+            .iter()
+            .count(),
+        1
+    );
+
+    let inner = tape.query_spans().name("inner");
+    assert_eq!(
+        inner
+            .clone()
+            .has_ancestor(outer.clone().field("b == -1".parse().unwrap()))
+            .iter()
+            .count(),
+        2
+    );
+    assert_eq!(inner.clone().time_range(60..=70).iter().count(), 1);
+    assert_eq!(inner.clone().min_duration(45).iter().count(), 1);
+    assert_eq!(outer.clone().has_descendant(inner).iter().count(), 1);
+
+    assert_eq!(tape.query_events().time_range(0..=20).iter().count(), 1);
+    assert_eq!(tape.query_events().time_range(21..=100).iter().count(), 0);
+    let (index, _) = tape.query_events().iter_indexed().next().unwrap();
+    assert_eq!(index, 0);
+    assert_eq!(
+        tape.query_events()
+            .level(tracing::Level::WARN)
+            .iter()
+            .count(),
+        0
+    );
+
+    let (first, _) = tape
+        .query_spans()
+        .name("inner")
+        .time_range(0..=20)
+        .iter()
+        .next()
+        .unwrap();
+    assert_eq!(tape.events_in_span(first).count(), 1);
+    assert_eq!(tape.events_in_span(tape.parent(first).unwrap()).count(), 0);
+}
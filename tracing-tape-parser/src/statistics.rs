@@ -0,0 +1,29 @@
+/// Returns the value below which the fraction `q` of the sorted values lie.
+///
+/// The index is rounded to the nearest value, so the median of an even number
+/// of values is the upper one of the two middle values.
+pub fn percentile(sorted: &[i64], q: f64) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    sorted[((sorted.len() - 1) as f64 * q).round() as usize]
+}
+
+#[test]
+fn test_percentile() {
+    assert_eq!(percentile(&[], 0.5), 0);
+    assert_eq!(percentile(&[7], 0.99), 7);
+    assert_eq!(percentile(&[1, 2, 3, 4], 0.0), 1);
+    assert_eq!(percentile(&[1, 2, 3, 4], 0.5), 3);
+    assert_eq!(percentile(&[1, 2, 3, 4, 5], 0.5), 3);
+    assert_eq!(percentile(&[1, 2, 3, 4], 1.0), 4);
+
+    // The median matches the middle element used for the quartiles.
+    for len in 1..20 {
+        let sorted = (0..len).collect::<Vec<i64>>();
+        assert_eq!(percentile(&sorted, 0.5), sorted[sorted.len() / 2]);
+    }
+
+    let sorted = (1..=1000).collect::<Vec<i64>>();
+    assert_eq!(percentile(&sorted, 0.99), 990);
+}
@@ -0,0 +1,297 @@
+//! Tape-to-tape transformations.
+//!
+//! These create new, self-contained tapes from existing ones, e.g., to cut a
+//! large recording down to the interesting part before sharing it. The
+//! resulting tapes can be written to disk using [Tape::write].
+
+use std::ops::RangeInclusive;
+
+use ahash::HashMap;
+use petgraph::graph::NodeIndex;
+
+use crate::{builder::TapeBuilder, Callsite, Event, Span, SpanEntrance, Tape};
+
+impl Span {
+    pub(crate) fn shifted(&self, offset: i64) -> Span {
+        self.mapped(&RecordMapping {
+            offset,
+            ..Default::default()
+        })
+    }
+
+    fn mapped(&self, mapping: &RecordMapping) -> Span {
+        Span {
+            opened: self.opened + mapping.offset,
+            closed: self.closed + mapping.offset,
+            callsite_index: self.callsite_index,
+            entrances: self
+                .entrances
+                .iter()
+                .map(|entrance| SpanEntrance {
+                    entered: entrance.entered + mapping.offset,
+                    exited: entrance.exited + mapping.offset,
+                    thread_id: mapping.thread(entrance.thread_id),
+                })
+                .collect(),
+            values: self.values.clone(),
+        }
+    }
+}
+
+/// Describes how the records of a tape are copied into a new tape.
+#[derive(Default)]
+struct RecordMapping {
+    /// The new index of each callsite or `None` if the callsite is removed.
+    callsites: Vec<Option<usize>>,
+    /// Threads that need a new id to avoid collisions between tapes.
+    threads: HashMap<u64, u64>,
+    /// Offset that is added to all timestamps.
+    offset: i64,
+}
+
+impl RecordMapping {
+    fn thread(&self, thread_id: u64) -> u64 {
+        self.threads.get(&thread_id).copied().unwrap_or(thread_id)
+    }
+}
+
+impl Tape {
+    /// Returns a tape that only contains the spans overlapping and the events
+    /// within the given timestamp range.
+    ///
+    /// Spans are kept in their entirety, i.e., they are not cut at the range
+    /// boundaries. The timestamps of the resulting tape are rebased such that
+    /// the absolute time of all records stays the same.
+    pub fn slice(&self, range: RangeInclusive<i64>) -> Tape {
+        let mut builder = TapeBuilder::new(self.intro.chapter_size.0, self.timestamp_base());
+        let mapping = RecordMapping {
+            callsites: self.copy_callsites(&mut builder, |_| true),
+            ..Default::default()
+        };
+        self.copy_records(
+            &mut builder,
+            &mapping,
+            |span| span.opened <= *range.end() && span.closed >= *range.start(),
+            |event| range.contains(&event.timestamp),
+        );
+        self.copy_thread_names(&mut builder, &mapping);
+        builder.rebase();
+        builder.finish()
+    }
+
+    /// Returns a tape without the callsites for which `filter` returns
+    /// `false`.
+    ///
+    /// Events and spans of removed callsites are dropped as well. Children of
+    /// removed spans are attached to their closest remaining ancestor.
+    ///
+    /// ```
+    /// # let tape = tracing_tape_parser::TapeBuilder::new(16, 0).finish();
+    /// let filtered = tape.filter(|callsite| {
+    ///     callsite.level() <= tracing::Level::DEBUG && !callsite.target().starts_with("hyper")
+    /// });
+    /// ```
+    pub fn filter<F: Fn(&Callsite) -> bool>(&self, filter: F) -> Tape {
+        let mut builder = TapeBuilder::new(self.intro.chapter_size.0, self.timestamp_base());
+        let mapping = RecordMapping {
+            callsites: self.copy_callsites(&mut builder, filter),
+            ..Default::default()
+        };
+        self.copy_records(&mut builder, &mapping, |_| true, |_| true);
+        self.copy_thread_names(&mut builder, &mapping);
+        builder.finish()
+    }
+
+    /// Merges multiple tapes, e.g., recorded by different processes, into a
+    /// single tape.
+    ///
+    /// The records of all tapes keep their absolute time, i.e., the offsets
+    /// between the timestamp bases of the tapes are preserved. Identical
+    /// callsites are only stored once. Threads that appear in multiple tapes
+    /// are assigned new ids to keep them apart.
+    pub fn merge<'a, I: IntoIterator<Item = &'a Tape>>(tapes: I) -> Tape {
+        let tapes = tapes.into_iter().collect::<Vec<_>>();
+        let timestamp_base = tapes
+            .iter()
+            .map(|tape| tape.timestamp_base())
+            .min()
+            .unwrap_or(0);
+        let chapter_size = tapes
+            .iter()
+            .map(|tape| tape.intro.chapter_size.0)
+            .max()
+            .unwrap_or(20);
+
+        let mut builder = TapeBuilder::new(chapter_size, timestamp_base);
+        let mut callsites = HashMap::<&Callsite, usize>::default();
+        let mut threads = ahash::HashSet::default();
+        for tape in tapes {
+            let callsite_map = tape
+                .callsites()
+                .iter()
+                .map(|callsite| {
+                    Some(
+                        *callsites
+                            .entry(callsite)
+                            .or_insert_with(|| builder.callsite(callsite.clone())),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let mut thread_map = HashMap::default();
+            for thread_id in tape.threads().keys() {
+                let mut new_thread_id = *thread_id;
+                while !threads.insert(new_thread_id) {
+                    new_thread_id = new_thread_id
+                        .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+                        .wrapping_add(1);
+                }
+                if new_thread_id != *thread_id {
+                    thread_map.insert(*thread_id, new_thread_id);
+                }
+            }
+
+            let mapping = RecordMapping {
+                callsites: callsite_map,
+                threads: thread_map,
+                offset: i64::try_from(tape.timestamp_base() - timestamp_base)
+                    .expect("tapes are too far apart to be merged"),
+            };
+            tape.copy_records(&mut builder, &mapping, |_| true, |_| true);
+            tape.copy_thread_names(&mut builder, &mapping);
+        }
+        builder.rebase();
+        builder.finish()
+    }
+
+    /// Copies the callsites accepted by `filter` and returns the new index
+    /// for each callsite of this tape.
+    fn copy_callsites<F: Fn(&Callsite) -> bool>(
+        &self,
+        builder: &mut TapeBuilder,
+        filter: F,
+    ) -> Vec<Option<usize>> {
+        self.callsites()
+            .iter()
+            .map(|callsite| {
+                if filter(callsite) {
+                    Some(builder.callsite(callsite.clone()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Copies all spans and events whose callsite is part of the mapping and
+    /// which are accepted by the given predicates.
+    fn copy_records<S, E>(
+        &self,
+        builder: &mut TapeBuilder,
+        mapping: &RecordMapping,
+        keep_span: S,
+        keep_event: E,
+    ) where
+        S: Fn(&Span) -> bool,
+        E: Fn(&Event) -> bool,
+    {
+        let spans = self.spans();
+        let mut stack: Vec<(NodeIndex<usize>, Option<NodeIndex<usize>>)> = self
+            .root_spans()
+            .iter()
+            .rev()
+            .map(|root| (*root, None))
+            .collect();
+        let mut copied_spans = HashMap::default();
+
+        while let Some((node, new_parent)) = stack.pop() {
+            let span = &spans[node];
+            let new_node = match mapping.callsites[span.callsite_index] {
+                Some(callsite_index) if keep_span(span) => {
+                    let mut span = span.mapped(mapping);
+                    span.callsite_index = callsite_index;
+                    let new_node = builder.span(new_parent, span);
+                    copied_spans.insert(node, new_node);
+                    Some(new_node)
+                }
+                // Children of removed spans are attached to the closest remaining ancestor.
+                _ => new_parent,
+            };
+
+            let mut children = spans.neighbors(node).collect::<Vec<_>>();
+            children.sort_by_key(|child| std::cmp::Reverse(spans[*child].opened));
+            stack.extend(children.into_iter().map(|child| (child, new_node)));
+        }
+
+        for (span, follows) in self.follows_from() {
+            if let (Some(span), Some(follows)) = (copied_spans.get(span), copied_spans.get(follows))
+            {
+                builder.follows_from(*span, *follows);
+            }
+        }
+
+        for event in self.events() {
+            if let Some(callsite_index) = mapping.callsites[event.callsite_index] {
+                if keep_event(event) {
+                    builder.event(Event {
+                        timestamp: event.timestamp + mapping.offset,
+                        callsite_index,
+                        thread_id: mapping.thread(event.thread_id),
+                        // Events of removed spans belong to the closest remaining ancestor.
+                        span: event.span.and_then(|span| {
+                            std::iter::once(span)
+                                .chain(self.ancestors(span))
+                                .find_map(|span| copied_spans.get(&span).copied())
+                        }),
+                        values: event.values.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn copy_thread_names(&self, builder: &mut TapeBuilder, mapping: &RecordMapping) {
+        for (thread_id, name) in self.threads() {
+            if let Some(name) = name {
+                builder.name_thread(mapping.thread(*thread_id), name);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_filter_reattaches_children() {
+    let tape = crate::writer::test_tape();
+    let filtered = tape.filter(|callsite| callsite.name() != "outer");
+
+    assert_eq!(filtered.callsites().len(), 2);
+    assert_eq!(filtered.root_spans().len(), 2);
+    assert!(filtered
+        .root_spans()
+        .iter()
+        .all(
+            |root| filtered.callsites()[filtered.spans()[*root].callsite_index].name() == "inner"
+        ));
+    assert_eq!(filtered.events().len(), 1);
+}
+
+#[test]
+fn test_slice_and_merge_preserve_absolute_time() {
+    let tape = crate::writer::test_tape();
+    let sliced = tape.slice(150..=250);
+    assert_eq!(sliced.spans().node_count(), 1);
+    assert!(sliced.events().is_empty());
+    assert_eq!(sliced.timestamp_base(), tape.timestamp_base() + 200);
+    assert_eq!(sliced.timestamp_range(), 0..=100);
+
+    let merged = Tape::merge([&tape, &sliced]);
+    assert_eq!(merged.callsites().len(), tape.callsites().len());
+    assert_eq!(merged.spans().node_count(), 5);
+    assert_eq!(merged.timestamp_base(), tape.timestamp_base() + 10);
+    let opened = merged
+        .root_spans()
+        .iter()
+        .map(|root| merged.spans()[*root].opened)
+        .collect::<Vec<_>>();
+    assert_eq!(opened, [0, 190, 190]);
+}
@@ -0,0 +1,486 @@
+//! Serialization of parsed tapes back into the tape format.
+
+use std::{borrow::Cow, io::Write};
+
+use ahash::HashMap;
+use petgraph::graph::NodeIndex;
+use tracing_tape::record::{
+    field_type, parent_kind, CallsiteFieldRecord, CallsiteRecord, EventRecord, EventValueRecord,
+    SpanCloseRecord, SpanEnterRecord, SpanExitRecord, SpanFollowsRecord, SpanOpenRecord2,
+    SpanValueRecord, ThreadNameRecord,
+};
+use zerocopy::AsBytes;
+
+use crate::{Event, Tape, Value};
+
+/// Writes records into chapters, such that no record crosses a chapter
+/// boundary.
+struct ChapterWriter<W: Write> {
+    writer: W,
+    chapter_size: usize,
+    chapter_offset: usize,
+}
+
+impl<W: Write> ChapterWriter<W> {
+    fn record(&mut self, parts: &[&[u8]]) -> std::io::Result<()> {
+        let len = parts.iter().map(|part| part.len()).sum::<usize>();
+        assert!(len <= self.chapter_size, "record too large");
+
+        if self.chapter_offset + len > self.chapter_size {
+            self.pad()?;
+        }
+        for part in parts {
+            self.writer.write_all(part)?;
+        }
+        self.chapter_offset += len;
+        Ok(())
+    }
+
+    fn event(&mut self, event: &Event, callsite_id: u64) -> std::io::Result<()> {
+        let values = event
+            .values
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| Some((index, encode_value(value)?)))
+            .collect::<Vec<_>>();
+
+        let record = EventRecord::new(
+            values.len() as u16,
+            event.timestamp,
+            callsite_id,
+            event.thread_id,
+        );
+        self.record(&[record.as_bytes()])?;
+
+        for (field_index, (kind, value)) in values {
+            let record =
+                EventValueRecord::new(field_index as u64 + 1, kind, value.len(), event.thread_id);
+            self.record(&[record.as_bytes(), &value])?;
+        }
+        Ok(())
+    }
+
+    /// Fills the remainder of the current chapter with no-op records.
+    fn pad(&mut self) -> std::io::Result<()> {
+        if self.chapter_offset > 0 {
+            let padding = vec![0; self.chapter_size - self.chapter_offset];
+            self.writer.write_all(&padding)?;
+            self.chapter_offset = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Longest value that fits into a record alongside its header.
+const MAX_VALUE_LEN: usize = u16::MAX as usize - std::mem::size_of::<SpanValueRecord>();
+
+/// Longest name, target, module path, or file, such that all four of them fit
+/// into a callsite record alongside its header.
+const MAX_NAME_LEN: usize = (u16::MAX as usize - std::mem::size_of::<CallsiteRecord>()) / 4;
+
+/// Truncates the string to at most `max_len` bytes at a character boundary.
+fn truncated(value: &str, max_len: usize) -> &[u8] {
+    let mut len = value.len().min(max_len);
+    while !value.is_char_boundary(len) {
+        len -= 1;
+    }
+    &value.as_bytes()[..len]
+}
+
+fn encode_value(value: &Value) -> Option<(u8, Cow<'_, [u8]>)> {
+    let encoded = match value {
+        Value::Empty => return None,
+        Value::Bool(value) => (field_type::BOOL, Cow::Owned(vec![*value as u8])),
+        Value::I64(value) => (field_type::I64, Cow::Owned(value.to_le_bytes().to_vec())),
+        Value::U64(value) => (field_type::U64, Cow::Owned(value.to_le_bytes().to_vec())),
+        Value::I128(value) => (field_type::I128, Cow::Owned(value.to_le_bytes().to_vec())),
+        Value::U128(value) => (field_type::U128, Cow::Owned(value.to_le_bytes().to_vec())),
+        Value::F64(value) => (field_type::F64, Cow::Owned(value.to_le_bytes().to_vec())),
+        Value::String(value) => (
+            field_type::STR,
+            Cow::Borrowed(truncated(value, MAX_VALUE_LEN)),
+        ),
+        Value::Error(value) => (
+            field_type::ERROR,
+            Cow::Borrowed(truncated(value, MAX_VALUE_LEN)),
+        ),
+    };
+    Some(encoded)
+}
+
+/// A span record that depends on the nesting of spans.
+enum SpanRecord {
+    Open,
+    Enter(usize),
+    Exit,
+    Close,
+}
+
+impl Tape {
+    /// Writes the tape in the tape format, e.g., after it has been
+    /// transformed using [Tape::slice], [Tape::filter], or [Tape::merge].
+    pub fn write<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        let mut writer = ChapterWriter {
+            writer,
+            chapter_size: 1 << self.intro.chapter_size.0.max(16),
+            chapter_offset: 0,
+        };
+
+        let intro = tracing_tape::intro::Intro::new(
+            self.intro.chapter_size.0.max(16),
+            self.intro.timestamp_base.get(),
+        );
+        writer.writer.write_all(intro.as_bytes())?;
+
+        let mut threads = self
+            .threads()
+            .iter()
+            .filter_map(|(thread_id, name)| Some((*thread_id, name.as_deref()?)))
+            .collect::<Vec<_>>();
+        threads.sort();
+        for (thread_id, name) in threads {
+            let name = truncated(name, MAX_NAME_LEN);
+            let record = ThreadNameRecord::new(name.len() as u16, thread_id);
+            writer.record(&[record.as_bytes(), name])?;
+        }
+
+        // Ids are derived from the indices, zero is reserved for "no id".
+        let callsite_id = |index: usize| index as u64 + 1;
+        let span_id = |index: NodeIndex<usize>| index.index() as u64 + 1;
+
+        for (index, callsite) in self.callsites().iter().enumerate() {
+            let name = truncated(callsite.name(), MAX_NAME_LEN);
+            let target = truncated(callsite.target(), MAX_NAME_LEN);
+            let module_path = truncated(callsite.module_path(), MAX_NAME_LEN);
+            let file = truncated(callsite.file().unwrap_or(""), MAX_NAME_LEN);
+
+            let record = CallsiteRecord::new(
+                (std::mem::size_of::<CallsiteRecord>()
+                    + name.len()
+                    + target.len()
+                    + module_path.len()
+                    + file.len()) as u16,
+                callsite.kind(),
+                callsite.level(),
+                callsite.fields().len() as u16,
+                name.len() as u16,
+                target.len() as u16,
+                module_path.len() as u16,
+                file.len() as u16,
+                callsite.line().unwrap_or(0),
+                callsite_id(index),
+            );
+            writer.record(&[record.as_bytes(), name, target, module_path, file])?;
+
+            for (field_index, field) in callsite.fields().iter().enumerate() {
+                let field = truncated(field, MAX_NAME_LEN);
+                let record = CallsiteFieldRecord::new(
+                    field.len() as u16,
+                    callsite_id(index),
+                    field_index as u64 + 1,
+                );
+                writer.record(&[record.as_bytes(), field])?;
+            }
+        }
+
+        // The parser reconstructs the span hierarchy from the per-thread context at the time a
+        // span is closed. Thus, records with the same timestamp must be ordered according to the
+        // hierarchy: a parent is opened and entered before its children and exited and closed
+        // after them. This is achieved by sorting by the position in an euler tour of the span
+        // tree. A span that is re-entered when it is exited must be exited first, so such an
+        // entrance is written right after the previous exit.
+        let spans = self.spans();
+        let mut span_records = Vec::with_capacity(spans.node_count() * 4);
+        let mut tour_position = 0;
+        for root in self.root_spans() {
+            let mut stack = vec![(*root, false)];
+            while let Some((node, visited)) = stack.pop() {
+                let span = &spans[node];
+                let is_reentered = |index: usize| {
+                    index > 0 && span.entrances[index - 1].exited == span.entrances[index].entered
+                };
+                tour_position += 1;
+                if visited {
+                    for (index, entrance) in span.entrances.iter().enumerate() {
+                        span_records.push((entrance.exited, tour_position, SpanRecord::Exit, node));
+                        if index + 1 < span.entrances.len() && is_reentered(index + 1) {
+                            span_records.push((
+                                entrance.exited,
+                                tour_position,
+                                SpanRecord::Enter(index + 1),
+                                node,
+                            ));
+                        }
+                    }
+                    span_records.push((span.closed, tour_position, SpanRecord::Close, node));
+                } else {
+                    span_records.push((span.opened, tour_position, SpanRecord::Open, node));
+                    for (index, entrance) in span.entrances.iter().enumerate() {
+                        if !is_reentered(index) {
+                            span_records.push((
+                                entrance.entered,
+                                tour_position,
+                                SpanRecord::Enter(index),
+                                node,
+                            ));
+                        }
+                    }
+
+                    stack.push((node, true));
+                    let mut children = spans.neighbors(node).collect::<Vec<_>>();
+                    children.sort_by_key(|child| std::cmp::Reverse(spans[*child].opened));
+                    stack.extend(children.into_iter().map(|child| (child, false)));
+                }
+            }
+        }
+        span_records.sort_by_key(|(timestamp, tour_position, _, _)| (*timestamp, *tour_position));
+
+        // Follows-from links can only be resolved while both spans are open, so they are written
+        // right after the second one of the two spans has been opened.
+        let mut follows_from = HashMap::<_, Vec<_>>::default();
+        for (span, follows) in self.follows_from() {
+            follows_from
+                .entry(*span)
+                .or_default()
+                .push((*span, *follows));
+            follows_from
+                .entry(*follows)
+                .or_default()
+                .push((*span, *follows));
+        }
+        let mut opened = vec![false; spans.node_count()];
+
+        // Events are associated with the innermost span on their thread while parsing. Events
+        // with the same timestamp as a span record are written inside of the span.
+        let mut events = self.events().iter().peekable();
+        for (timestamp, _, record, node) in span_records {
+            let inside = matches!(record, SpanRecord::Exit | SpanRecord::Close);
+            while let Some(event) = events.next_if(|event| {
+                event.timestamp < timestamp || (inside && event.timestamp == timestamp)
+            }) {
+                writer.event(event, callsite_id(event.callsite_index))?;
+            }
+
+            let span = &spans[node];
+            match record {
+                SpanRecord::Open => {
+                    let record = SpanOpenRecord2::new(
+                        span_id(node),
+                        parent_kind::CURRENT,
+                        0,
+                        callsite_id(span.callsite_index),
+                        timestamp,
+                    );
+                    writer.record(&[record.as_bytes()])?;
+
+                    for (field_index, value) in span.values.iter().enumerate() {
+                        if let Some((kind, value)) = encode_value(value) {
+                            let record = SpanValueRecord::new(
+                                field_index as u64 + 1,
+                                kind,
+                                value.len(),
+                                span_id(node),
+                            );
+                            writer.record(&[record.as_bytes(), &value])?;
+                        }
+                    }
+
+                    opened[node.index()] = true;
+                    for (span, follows) in follows_from.get(&node).into_iter().flatten() {
+                        if opened[span.index()] && opened[follows.index()] {
+                            let record = SpanFollowsRecord::new(span_id(*span), span_id(*follows));
+                            writer.record(&[record.as_bytes()])?;
+                        }
+                    }
+                }
+                SpanRecord::Enter(index) => {
+                    let record = SpanEnterRecord::new(
+                        span_id(node),
+                        timestamp,
+                        span.entrances[index].thread_id,
+                    );
+                    writer.record(&[record.as_bytes()])?;
+                }
+                SpanRecord::Exit => {
+                    let record = SpanExitRecord::new(span_id(node), timestamp);
+                    writer.record(&[record.as_bytes()])?;
+                }
+                SpanRecord::Close => {
+                    let record = SpanCloseRecord::new(span_id(node), timestamp);
+                    writer.record(&[record.as_bytes()])?;
+                }
+            }
+        }
+
+        for event in events {
+            writer.event(event, callsite_id(event.callsite_index))?;
+        }
+
+        writer.pad()?;
+        writer.writer.flush()
+    }
+}
+
+/// A small tape with nested spans, a follows-from link, an event, and a named
+/// thread.
+#[cfg(any(test, feature = "testing"))]
+#[doc(hidden)]
+pub fn test_tape() -> Tape {
+    use std::sync::Arc;
+
+    use crate::{builder::TapeBuilder, Callsite, Metadata, Span, SpanEntrance};
+
+    let metadata = |name: &str, fields: &[&str]| Metadata {
+        level: tracing::Level::INFO,
+        name: name.into(),
+        target: "test".into(),
+        module_path: "test".into(),
+        file: Some("test.rs".into()),
+        line: Some(1),
+        fields: fields.iter().map(|field| Arc::from(*field)).collect(),
+    };
+    let span = |callsite_index, opened, closed, values: &[Value]| Span {
+        opened,
+        closed,
+        callsite_index,
+        entrances: Arc::new([SpanEntrance {
+            entered: opened,
+            exited: closed,
+            thread_id: 1,
+        }]),
+        values: values.into(),
+    };
+
+    let mut builder = TapeBuilder::new(16, 1_000_000);
+    let outer = builder.callsite(Callsite::Span(metadata("outer", &["a", "b"])));
+    let inner = builder.callsite(Callsite::Span(metadata("inner", &[])));
+    let event = builder.callsite(Callsite::Event(metadata("event", &["message"])));
+
+    let root = builder.span(None, span(outer, 10, 100, &[Value::Empty, Value::I64(-1)]));
+    let first = builder.span(Some(root), span(inner, 10, 50, &[]));
+    let follows = builder.span(Some(root), span(inner, 50, 100, &[]));
+    builder.follows_from(follows, root);
+    builder.span(
+        None,
+        span(outer, 200, 300, &[Value::Bool(true), Value::Empty]),
+    );
+    builder.event(Event {
+        timestamp: 20,
+        callsite_index: event,
+        thread_id: 1,
+        span: Some(first),
+        values: Arc::new([Value::String("hello".into())]),
+    });
+    builder.name_thread(1, "main");
+    builder.finish()
+}
+
+#[test]
+fn test_write_round_trip() {
+    use std::sync::Arc;
+
+    use crate::{builder::TapeBuilder, SpanEntrance};
+
+    let tape = test_tape();
+    let mut data = vec![];
+    tape.write(&mut data).unwrap();
+    assert_eq!(
+        data.len() % (1 << 16),
+        std::mem::size_of::<tracing_tape::intro::Intro>()
+    );
+
+    let parsed = Tape::parse(&data).unwrap();
+    assert_eq!(parsed.timestamp_base(), tape.timestamp_base());
+    assert_eq!(parsed.callsites(), tape.callsites());
+    assert_eq!(parsed.threads(), tape.threads());
+    assert_eq!(parsed.threads()[&1].as_deref(), Some("main"));
+    assert_eq!(parsed.root_spans().len(), 2);
+    assert_eq!(parsed.spans().node_count(), 4);
+    assert_eq!(parsed.events().len(), 1);
+    assert_eq!(parsed.events()[0].values[..], tape.events()[0].values[..]);
+    let event_span = &parsed.spans()[parsed.events()[0].span.unwrap()];
+    assert_eq!((event_span.opened, event_span.closed), (10, 50));
+
+    let [(span, follows)] = parsed.follows_from() else {
+        panic!("expected a single follows-from link");
+    };
+    assert_eq!(parsed.spans()[*span].opened, 50);
+    assert_eq!(parsed.spans()[*follows].opened, 10);
+
+    for (root, expected_children) in parsed.root_spans().iter().zip([2, 0]) {
+        assert_eq!(parsed.spans().neighbors(*root).count(), expected_children);
+    }
+    let values = parsed
+        .root_spans()
+        .iter()
+        .map(|root| parsed.spans()[*root].values.to_vec())
+        .collect::<Vec<_>>();
+    assert!(values.contains(&vec![Value::Empty, Value::I64(-1)]));
+    assert!(values.contains(&vec![Value::Bool(true), Value::Empty]));
+
+    // A span that is exited and re-entered at the same time.
+    let entrances = [(10, 50), (50, 50), (50, 100)].map(|(entered, exited)| SpanEntrance {
+        entered,
+        exited,
+        thread_id: 1,
+    });
+    let mut span = tape.spans()[tape.root_spans()[0]].clone();
+    span.entrances = Arc::new(entrances.clone());
+    let mut builder = TapeBuilder::new(16, 0);
+    span.callsite_index = builder.callsite(tape.callsites()[span.callsite_index].clone());
+    builder.span(None, span);
+
+    let mut data = vec![];
+    builder.finish().write(&mut data).unwrap();
+    let parsed = Tape::parse(&data).unwrap();
+    let parsed_entrances = &parsed.spans()[parsed.root_spans()[0]].entrances;
+    let times = |entrances: &[SpanEntrance]| {
+        entrances
+            .iter()
+            .map(|entrance| (entrance.entered, entrance.exited))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(times(parsed_entrances), times(&entrances));
+}
+
+#[test]
+fn test_parse_invalid_tape() {
+    let mut data = vec![];
+    test_tape().write(&mut data).unwrap();
+    let intro_len = std::mem::size_of::<tracing_tape::intro::Intro>();
+
+    for truncated in [0, intro_len - 1, intro_len + 2, intro_len + 4] {
+        let err = Tape::parse(&data[..truncated]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    data[0] = b'X';
+    let err = Tape::parse(&data).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_write_long_callsite() {
+    use crate::{builder::TapeBuilder, Callsite, Metadata};
+
+    let long = "a".repeat(u16::MAX as usize);
+    let mut builder = TapeBuilder::new(16, 0);
+    builder.callsite(Callsite::Span(Metadata {
+        level: tracing::Level::INFO,
+        name: long.as_str().into(),
+        target: long.as_str().into(),
+        module_path: long.as_str().into(),
+        file: Some(long.as_str().into()),
+        line: Some(1),
+        fields: Default::default(),
+    }));
+    let mut data = vec![];
+    builder.finish().write(&mut data).unwrap();
+
+    let parsed = Tape::parse(&data).unwrap();
+    let callsite = &parsed.callsites()[0];
+    assert_eq!(callsite.name().len(), MAX_NAME_LEN);
+    assert_eq!(callsite.target().len(), MAX_NAME_LEN);
+    assert_eq!(callsite.module_path().len(), MAX_NAME_LEN);
+    assert_eq!(callsite.file().map(str::len), Some(MAX_NAME_LEN));
+}
@@ -5,7 +5,7 @@ use std::{
 
 use tracing::{error, info, info_span, subscriber::set_default};
 use tracing_subscriber::{fmt, layer::SubscriberExt, Registry};
-use tracing_tape_recorder::TapeRecorder;
+use tracing_tape_recorder::{correlation::CorrelationId, TapeRecorder};
 
 fn main() {
     let subscriber = Registry::default()
@@ -20,11 +20,18 @@ fn main() {
 
     let mut buffer = [0u8; 8];
     for i in 0..2000 {
-        if let Err(err) = info_span!("request", i).in_scope(|| -> std::io::Result<()> {
+        // The server records the id as well, which links the spans of both
+        // tapes in trace-deck.
+        let trace_id = CorrelationId::new();
+        let request = info_span!("request", i, trace_id = %trace_id);
+        if let Err(err) = request.in_scope(|| -> std::io::Result<()> {
             let n = i as u64 % 10;
             info!(n, "sending request");
 
-            info_span!("write request", i).in_scope(|| stream.write_all(&n.to_be_bytes()))?;
+            let mut message = [0u8; 16];
+            message[..8].copy_from_slice(&trace_id.to_bytes());
+            message[8..].copy_from_slice(&n.to_be_bytes());
+            info_span!("write request", i).in_scope(|| stream.write_all(&message))?;
 
             info_span!("read response", i).in_scope(|| stream.read_exact(&mut buffer))?;
 
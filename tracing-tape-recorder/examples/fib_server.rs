@@ -5,7 +5,7 @@ use std::{
 
 use tracing::{info_span, subscriber::set_default};
 use tracing_subscriber::{fmt, layer::SubscriberExt, Registry};
-use tracing_tape_recorder::TapeRecorder;
+use tracing_tape_recorder::{correlation::CorrelationId, TapeRecorder};
 
 #[tracing::instrument]
 fn fib(n: u64) -> u64 {
@@ -18,20 +18,23 @@ fn fib(n: u64) -> u64 {
 
 #[tracing::instrument]
 fn handle_connection(mut stream: TcpStream, addr: SocketAddr) {
-    let mut buffer = [0u8; 8];
+    let mut buffer = [0u8; 16];
     loop {
         if let Err(err) = info_span!("read request").in_scope(|| stream.read_exact(&mut buffer)) {
             tracing::error!(?err, "failed to read from stream");
             break;
         }
 
-        let n = u64::from_be_bytes(buffer);
-        tracing::info!(n, "received request");
-        let result = fib(n);
-
-        if let Err(err) =
+        let (trace_id, n) = buffer.split_at(8);
+        let trace_id = CorrelationId::from_bytes(trace_id.try_into().unwrap());
+        let n = u64::from_be_bytes(n.try_into().unwrap());
+        let result = info_span!("handle request", trace_id = %trace_id).in_scope(|| {
+            tracing::info!(n, "received request");
+            let result = fib(n);
             info_span!("write response").in_scope(|| stream.write_all(&result.to_be_bytes()))
-        {
+        });
+
+        if let Err(err) = result {
             tracing::error!(?err, "failed to write to stream");
             break;
         }
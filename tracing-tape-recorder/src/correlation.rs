@@ -0,0 +1,117 @@
+//! Correlation ids link the spans of different tapes, e.g., a request span of
+//! a client and the span of the server that handled it.
+//!
+//! The id is created by the process that starts the work, sent along with the
+//! request, and recorded in a field of the spans on both sides. trace-deck
+//! links spans of different tapes with the same value of the field, which is
+//! [DEFAULT_FIELD] unless configured otherwise.
+//!
+//! ```rust
+//! use tracing::info_span;
+//! use tracing_tape_recorder::correlation::CorrelationId;
+//!
+//! // Client
+//! let trace_id = CorrelationId::new();
+//! let request = info_span!("request", trace_id = %trace_id);
+//! let bytes = trace_id.to_bytes();
+//!
+//! // Server
+//! let trace_id = CorrelationId::from_bytes(bytes);
+//! let handler = info_span!("handle request", trace_id = %trace_id);
+//! ```
+
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// The field name trace-deck uses for correlation by default.
+pub const DEFAULT_FIELD: &str = "trace_id";
+
+static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// An id that is unique across processes with high probability. It is
+/// displayed as 16 hexadecimal digits, record it with `%` to store it as
+/// such.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CorrelationId(u64);
+
+impl CorrelationId {
+    /// Creates a new id from the current time, the process id, and a
+    /// sequence number.
+    pub fn new() -> Self {
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        let sequence = NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let seed = time ^ (u64::from(std::process::id()) << 32) ^ sequence;
+
+        // splitmix64 spreads the bits of similar seeds.
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        Self(z ^ (z >> 31))
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Encodes the id for sending it to another process.
+    pub fn to_bytes(self) -> [u8; 8] {
+        self.0.to_be_bytes()
+    }
+
+    /// Decodes an id encoded with [Self::to_bytes].
+    pub fn from_bytes(bytes: [u8; 8]) -> Self {
+        Self(u64::from_be_bytes(bytes))
+    }
+}
+
+impl Default for CorrelationId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<u64> for CorrelationId {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl Display for CorrelationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Parses the hexadecimal representation, e.g., from a request header.
+impl FromStr for CorrelationId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(Self)
+    }
+}
+
+#[test]
+fn test_correlation_id() {
+    let id = CorrelationId::from(0x0123_4567_89ab_cdef);
+    assert_eq!(
+        id.to_bytes(),
+        [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]
+    );
+    assert_eq!(CorrelationId::from_bytes(id.to_bytes()), id);
+    assert_eq!(id.to_string(), "0123456789abcdef");
+    assert_eq!("0123456789abcdef".parse(), Ok(id));
+
+    // Small ids keep their leading zeros.
+    let id = CorrelationId::from(0xff);
+    assert_eq!(id.to_string(), "00000000000000ff");
+    assert_eq!(id.to_string().parse(), Ok(id));
+    assert!("not an id".parse::<CorrelationId>().is_err());
+
+    assert_ne!(CorrelationId::new(), CorrelationId::new());
+}
@@ -11,7 +11,7 @@
 //! ```
 //! Then, you can use the [TapeRecorder] layer in your application:
 //!
-//! ```rust
+//! ```rust,no_run
 //! use tracing::trace_span;
 //! use tracing_subscriber::{fmt, layer::SubscriberExt, Registry};
 //! use tracing_tape_recorder::TapeRecorder;
@@ -61,6 +61,8 @@ use tracing_tape::{
 };
 use zerocopy::AsBytes;
 
+pub mod correlation;
+
 #[derive(Debug)]
 struct Chapter {
     chapter_size: usize,
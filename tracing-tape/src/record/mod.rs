@@ -9,6 +9,9 @@ pub use span::{
     parent_kind, SpanCloseRecord, SpanEnterRecord, SpanExitRecord, SpanFollowsRecord,
     SpanOpenRecord, SpanOpenRecord2, SpanValueRecord,
 };
+
+mod thread;
+pub use thread::ThreadNameRecord;
 use zerocopy::{little_endian, AsBytes, FromBytes, FromZeroes, Unaligned};
 
 pub mod record_kind {
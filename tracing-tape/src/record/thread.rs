@@ -0,0 +1,24 @@
+use zerocopy::{little_endian, AsBytes, FromBytes, FromZeroes, Unaligned};
+
+use super::{record_kind, RecordHeader};
+
+#[derive(Debug, Clone, Copy, AsBytes, FromZeroes, FromBytes, Unaligned)]
+#[repr(C)]
+pub struct ThreadNameRecord {
+    pub header: RecordHeader,
+    pub name_len: little_endian::U16,
+    pub thread_id: little_endian::U64,
+}
+
+impl ThreadNameRecord {
+    pub fn new(name_len: u16, thread_id: u64) -> Self {
+        ThreadNameRecord {
+            header: RecordHeader::new(
+                record_kind::THREAD_NAME,
+                std::mem::size_of::<Self>() as u16 + name_len,
+            ),
+            name_len: name_len.into(),
+            thread_id: thread_id.into(),
+        }
+    }
+}